@@ -0,0 +1,37 @@
+use dnn_rs::nn::model::NeuralNetwork;
+use dnn_rs::nn::activation::{ActivationFunction, ReLU};
+use dnn_rs::nn::layers::Linear;
+use dnn_rs::nn::loss::MSE;
+
+use dnn_rs::optim::sgd::SGD;
+
+use dnn_rs::data::make_regression;
+
+fn main() {
+    // A ready-made nonlinear benchmark: y = sin(x) on [-π, π]
+    let (x, y) = make_regression(200, 1, 0.0, 0);
+    let (x_test, y_test) = make_regression(50, 1, 0.0, 1);
+
+    // 1 -> 32 -> 1 network, the output layer has no activation
+    let layers = vec![Box::new(Linear::new(1, 32)), Box::new(Linear::new(32, 1))];
+    let activations: Vec<Box<dyn ActivationFunction>> = vec![Box::new(ReLU::new())];
+    let loss = Box::new(MSE::new());
+
+    let model = NeuralNetwork::new(layers, activations, loss);
+    let mut optim = SGD::new(model, 0.01, 0.9);
+
+    // Training loop
+    for epoch in 0..2000 {
+        optim.update(&x, &y);
+        if epoch % 500 == 0 {
+            let y_pred = optim.model.forward(&x);
+            let loss = optim.model.loss.forward(&y_pred, &y);
+            println!("epoch {}: train loss {:.5}", epoch, loss);
+        }
+    }
+
+    // Evaluate on held out points of the same curve
+    let y_pred = optim.model.forward(&x_test);
+    let test_loss = optim.model.loss.forward(&y_pred, &y_test);
+    println!("test loss: {:.5}", test_loss);
+}
@@ -1,5 +1,5 @@
 use dnn_rs::nn::model::NeuralNetwork;
-use dnn_rs::nn::activation::ReLU;
+use dnn_rs::nn::activation::{ActivationFunction, ReLU};
 use dnn_rs::nn::layers::Linear;
 use dnn_rs::nn::loss::MSE;
 
@@ -15,7 +15,7 @@ fn main() {
     let activation2 = ReLU::new();
 
     let layers = vec![Box::new(linear1), Box::new(linear2)];
-    let activations = vec![Box::new(activation1), Box::new(activation2)];
+    let activations: Vec<Box<dyn ActivationFunction>> = vec![Box::new(activation1), Box::new(activation2)];
     let loss = Box::new(MSE::new());
 
     let model = NeuralNetwork::new(layers, activations, loss);
@@ -0,0 +1,193 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Representation Analysis
+    *
+    * Tools for comparing what different networks (or different layers of the
+    * same network) have learned, from their activations on the same batch.
+    *
+    * Currently, the following measures are implemented:
+    * 1. cka - Linear centered kernel alignment between two activation
+    *          matrices X (N x D1) and Y (N x D2) with the same samples as rows:
+    *          CKA = ||Yᵀ X||_F^2 / (||Xᵀ X||_F * ||Yᵀ Y||_F),
+    *          for X and Y with centered columns. It lies in [0, 1], is 1 for
+    *          identical representations and is invariant to rotations
+    *          (including permutations) of the features and to isotropic
+    *          scaling, so the two matrices may have different widths.
+    * 2. empirical_ntk - Empirical neural tangent kernel of a network f with
+    *                    parameters θ: Θ(x, x') = ∇_θ f(x) ∇_θ f(x')ᵀ, from
+    *                    the per-sample parameter gradients (Jacobians) of
+    *                    the outputs. For one output it is the inner product
+    *                    of the two gradients; for wide networks it stays
+    *                    nearly constant during training, where training
+    *                    with gradient descent behaves like kernel regression
+    *                    with Θ.
+    * 3. class_prototypes - Mean feature vector (embedding) of every class,
+    *                       e.g. for nearest-prototype or few-shot
+    *                       classification on top of a network's features.
+    *
+**/
+
+
+// Subtracts the mean of every column
+fn center_columns(X: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut centered = X.clone();
+    for mut column in centered.column_iter_mut() {
+        let mean = column.mean();
+        column.add_scalar_mut(-mean);
+    }
+    return centered;
+}
+
+// Linear CKA between the representations reps_a (N x D1) and reps_b (N x D2)
+// of the same N samples
+pub fn cka(reps_a: &DMatrix<f64>, reps_b: &DMatrix<f64>) -> f64 {
+    assert_eq!(reps_a.nrows(), reps_b.nrows(), "Both representations must have the same number of samples");
+    let X = center_columns(reps_a);
+    let Y = center_columns(reps_b);
+    let cross = (Y.transpose() * &X).norm_squared();
+    let self_a = (X.transpose() * &X).norm();
+    let self_b = (Y.transpose() * &Y).norm();
+    assert!(self_a > 0.0 && self_b > 0.0, "CKA is undefined for a constant representation");
+    return cross / (self_a * self_b);
+}
+
+// Jacobian of the outputs of net with respect to its parameters (in the
+// order of flat_parameters) for every sample: row i * K + k holds the
+// gradient of output k of sample i, for K outputs
+fn parameter_jacobian(net: &mut NeuralNetwork, x: &DMatrix<f64>) -> DMatrix<f64> {
+    let mut rows = Vec::new();
+    let mut num_outputs = 0;
+    for i in 0..x.nrows() {
+        let output = net.forward(&x.rows(i, 1).into_owned());
+        num_outputs = output.ncols();
+        for k in 0..num_outputs {
+            let mut dLdA = DMatrix::zeros(1, num_outputs);
+            dLdA[(0, k)] = 1.0;
+            let _ = net.backward_from(&dLdA);
+            rows.push(net.flat_gradients());
+        }
+    }
+    let num_parameters = net.num_parameters();
+    return DMatrix::from_fn(x.nrows() * num_outputs, num_parameters, |r, p| rows[r][p]);
+}
+
+// Empirical NTK between the samples (rows) of x_a (N_a samples) and x_b (N_b
+// samples) for a network with K outputs, as an (N_a * K) x (N_b * K) matrix
+// where entry (i * K + k, j * K + l) is <∇_θ f_k(x_a_i), ∇_θ f_l(x_b_j)>,
+// i.e. N_a x N_b for a single output. Runs one backward pass per sample and
+// output, so it is only meant for small models, and overwrites the gradients
+// stored in the layers. Stochastic activations such as Dropout should be put
+// in eval mode first.
+pub fn empirical_ntk(net: &mut NeuralNetwork, x_a: &DMatrix<f64>, x_b: &DMatrix<f64>) -> DMatrix<f64> {
+    let J_a = parameter_jacobian(net, x_a);
+    let J_b = parameter_jacobian(net, x_b);
+    return J_a * J_b.transpose();
+}
+
+// Prototype of every class from features (N x D, one sample per row) and
+// their labels in 0..num_classes: row k of the returned num_classes x D
+// matrix is the mean of the rows labeled k. A class without samples has no
+// mean and gets a zero row.
+pub fn class_prototypes(features: &DMatrix<f64>, labels: &[usize], num_classes: usize) -> DMatrix<f64> {
+    assert_eq!(features.nrows(), labels.len(), "Expected one label per sample");
+    let mut prototypes = DMatrix::zeros(num_classes, features.ncols());
+    let mut counts = vec![0usize; num_classes];
+    for (i, &label) in labels.iter().enumerate() {
+        assert!(label < num_classes, "Label {} out of range ({} classes)", label, num_classes);
+        let mut prototype = prototypes.row_mut(label);
+        prototype += features.row(i);
+        counts[label] += 1;
+    }
+    for (k, &count) in counts.iter().enumerate() {
+        if count > 0 {
+            prototypes.row_mut(k).unscale_mut(count as f64);
+        }
+    }
+    return prototypes;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::Tanh;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_cka() {
+        let X = DMatrix::from_fn(8, 3, |i, j| ((i * 3 + j) as f64).sin() + 0.1 * i as f64);
+        assert_abs_diff_eq!(cka(&X, &X), 1.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(cka(&X, &(2.0 * &X)), 1.0, epsilon = 1e-12);
+
+        // Permuting the features is a rotation, which linear CKA ignores
+        let permuted_features = DMatrix::from_fn(8, 3, |i, j| X[(i, (j + 1) % 3)]);
+        assert_abs_diff_eq!(cka(&X, &permuted_features), 1.0, epsilon = 1e-12);
+
+        // Shuffling the samples breaks the correspondence between the rows
+        let order = [3, 7, 0, 5, 1, 6, 2, 4];
+        let shuffled = DMatrix::from_fn(8, 3, |i, j| X[(order[i], j)]);
+        let similarity = cka(&X, &shuffled);
+        assert!((0.0..0.9).contains(&similarity), "CKA {}", similarity);
+    }
+
+    #[test]
+    fn test_empirical_ntk_of_linear_layer() {
+        // For f(x) = W x + b, ∇_W f_k(x) holds x in row k and ∇_b f_k(x) = e_k,
+        // so Θ_kl(x, x') = δ_kl * (x · x' + 1) whatever W and b are
+        let mut net = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![], Box::new(MSE::new()));
+        let x_a = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, -1.0,
+                                                  0.5, 0.0, 3.0]);
+        let x_b = DMatrix::from_row_slice(3, 3, &[0.0, 1.0, 1.0,
+                                                  -2.0, 0.5, 0.25,
+                                                  1.0, 1.0, 1.0]);
+        let ntk = empirical_ntk(&mut net, &x_a, &x_b);
+        assert_eq!(ntk.shape(), (4, 6));
+        for i in 0..2 {
+            for j in 0..3 {
+                let expected = x_a.row(i).dot(&x_b.row(j)) + 1.0;
+                for k in 0..2 {
+                    for l in 0..2 {
+                        let entry = if k == l { expected } else { 0.0 };
+                        assert_abs_diff_eq!(ntk[(i * 2 + k, j * 2 + l)], entry, epsilon = 1e-12);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_empirical_ntk_is_symmetric_gram_matrix() {
+        let mut net = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 1))],
+                                         vec![Box::new(Tanh::new())], Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -1.0,
+                                                0.5, 2.0,
+                                                -0.3, 0.1]);
+        let ntk = empirical_ntk(&mut net, &x, &x);
+        assert_eq!(ntk.shape(), (3, 3));
+        assert_abs_diff_eq!(ntk, ntk.transpose(), epsilon = 1e-12);
+        assert!(ntk.symmetric_eigenvalues().iter().all(|e| *e > -1e-9));
+    }
+
+    #[test]
+    fn test_class_prototypes() {
+        let features = DMatrix::from_row_slice(5, 2, &[1.0, 2.0,
+                                                       3.0, 4.0,
+                                                       -1.0, 0.0,
+                                                       5.0, -6.0,
+                                                       0.5, 1.0]);
+        let labels = [0, 0, 1, 0, 1];
+        let prototypes = class_prototypes(&features, &labels, 3);
+        assert_eq!(prototypes.shape(), (3, 2));
+        for k in 0..2 {
+            let rows: Vec<usize> = (0..5).filter(|&i| labels[i] == k).collect();
+            let mean = features.select_rows(&rows).row_mean();
+            assert_abs_diff_eq!(prototypes.row(k).into_owned(), mean, epsilon = 1e-12);
+        }
+        assert_abs_diff_eq!(prototypes.row(0).into_owned(), nalgebra::RowDVector::from_vec(vec![3.0, 0.0]), epsilon = 1e-12);
+        // Class 2 has no samples
+        assert_eq!(prototypes.row(2).iter().filter(|v| **v == 0.0).count(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use nalgebra::{DMatrix};
+
+/**
+    * Calibration
+    *
+    * A classifier is calibrated when its predicted probabilities match the
+    * observed frequencies, e.g. of all predictions made with 80% confidence,
+    * 80% are correct. Networks trained to a low loss are typically
+    * overconfident.
+    *
+    * Temperature scaling is the standard post-hoc fix: the logits are divided
+    * by a single scalar T > 0 before the softmax, p = softmax(z / T). T is
+    * fitted on a held out validation set by minimizing the negative log
+    * likelihood (NLL). T > 1 softens overconfident predictions, T < 1 sharpens
+    * underconfident ones, and the predicted class never changes.
+    *
+    * Logits and targets follow the layout of NeuralNetwork::forward: each row
+    * is one sample and each column one class. Targets are one-hot.
+    *
+    * Currently, the following methods are implemented:
+    * 1. fit_temperature - Fits T by minimizing the validation NLL.
+    * 2. apply_temperature - Calibrated probabilities softmax(z / T).
+    *
+**/
+
+
+// Mean NLL of the one-hot targets under softmax(logits / T), together with
+// its derivative with respect to log(T). With z = logits / T and p = softmax(z):
+// ∂NLL/∂z = (p - Y) / N and ∂z/∂log(T) = -z, so ∂NLL/∂log(T) = -Σ (p - Y) ⊙ z / N
+fn temperature_nll(logits: &DMatrix<f64>, targets: &DMatrix<f64>, T: f64) -> (f64, f64) {
+    let N = logits.nrows() as f64;
+    let mut nll = 0.0;
+    let mut grad = 0.0;
+    for i in 0..logits.nrows() {
+        let z = logits.row(i) / T;
+        let max = z.max();
+        let log_sum_exp = max + z.map(|v| (v - max).exp()).sum().ln();
+        for j in 0..z.ncols() {
+            let p = (z[j] - log_sum_exp).exp();
+            nll -= targets[(i, j)] * (z[j] - log_sum_exp);
+            grad -= (p - targets[(i, j)]) * z[j];
+        }
+    }
+    return (nll / N, grad / N);
+}
+
+// Fits the temperature minimizing the mean NLL of targets under
+// softmax(logits / T). The NLL is convex in 1/T, so gradient descent on
+// log(T) (keeping T positive) with a backtracking line search converges
+// reliably, starting from T = 1.
+pub fn fit_temperature(logits: &DMatrix<f64>, targets: &DMatrix<f64>) -> f64 {
+    assert_eq!(logits.shape(), targets.shape(), "Logits and targets must have the same shape");
+    let mut log_T: f64 = 0.0;
+    let (mut nll, mut grad) = temperature_nll(logits, targets, 1.0);
+    for _ in 0..100 {
+        if grad.abs() < 1e-10 {
+            break;
+        }
+        // Halve the step until the NLL decreases
+        let mut step = 1.0;
+        let mut improved = false;
+        while step > 1e-8 {
+            let candidate = log_T - step * grad;
+            let (candidate_nll, candidate_grad) = temperature_nll(logits, targets, candidate.exp());
+            if candidate_nll < nll {
+                log_T = candidate;
+                nll = candidate_nll;
+                grad = candidate_grad;
+                improved = true;
+                break;
+            }
+            step *= 0.5;
+        }
+        if !improved {
+            break;
+        }
+    }
+    return log_T.exp();
+}
+
+// Calibrated class probabilities softmax(logits / T), computed row by row
+pub fn apply_temperature(logits: &DMatrix<f64>, T: f64) -> DMatrix<f64> {
+    assert!(T > 0.0, "Temperature must be positive, got {}", T);
+    let mut P = logits / T;
+    for mut row in P.row_iter_mut() {
+        let max = row.max();
+        row.apply(|v| *v = (*v - max).exp());
+        let sum = row.sum();
+        row /= sum;
+    }
+    return P;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // Logits of a classifier that is right 70% of the time, but whose logits
+    // are scaled up 5x, so it claims far more confidence than it has.
+    fn overconfident_logits(n: usize, seed: u64) -> (DMatrix<f64>, DMatrix<f64>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut logits = DMatrix::zeros(n, 3);
+        let mut targets = DMatrix::zeros(n, 3);
+        for i in 0..n {
+            let label = i % 3;
+            targets[(i, label)] = 1.0;
+            let predicted = if rng.gen::<f64>() < 0.7 { label } else { (label + 1) % 3 };
+            for j in 0..3 {
+                logits[(i, j)] = 5.0 * (rng.gen::<f64>() + if j == predicted { 1.0 } else { 0.0 });
+            }
+        }
+        return (logits, targets);
+    }
+
+    #[test]
+    fn test_fit_temperature_softens_overconfident_logits() {
+        let (logits, targets) = overconfident_logits(300, 0);
+        let T = fit_temperature(&logits, &targets);
+        assert!(T > 1.0, "T = {}", T);
+
+        let (nll_before, _) = temperature_nll(&logits, &targets, 1.0);
+        let (nll_after, grad_after) = temperature_nll(&logits, &targets, T);
+        assert!(nll_after < nll_before);
+        assert_abs_diff_eq!(grad_after, 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_temperature_nll_gradient() {
+        let (logits, targets) = overconfident_logits(20, 1);
+        let (T, eps) = (1.7, 1e-6);
+        let (_, grad) = temperature_nll(&logits, &targets, T);
+        let (nll_plus, _) = temperature_nll(&logits, &targets, (T.ln() + eps).exp());
+        let (nll_minus, _) = temperature_nll(&logits, &targets, (T.ln() - eps).exp());
+        assert_abs_diff_eq!(grad, (nll_plus - nll_minus) / (2.0 * eps), epsilon = 1e-7);
+    }
+
+    #[test]
+    fn test_apply_temperature() {
+        let logits = DMatrix::from_row_slice(2, 2, &[2.0, 0.0,
+                                                     1.0, 1.0]);
+        let P = apply_temperature(&logits, 2.0);
+        let p = 1.0 / (1.0 + (-1.0f64).exp());
+        let expected = DMatrix::from_row_slice(2, 2, &[p, 1.0 - p,
+                                                       0.5, 0.5]);
+        assert_abs_diff_eq!(P, expected, epsilon = 1e-12);
+    }
+}
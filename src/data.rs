@@ -0,0 +1,639 @@
+use nalgebra::{DMatrix};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use std::f64::consts;
+
+/**
+    * Data Utilities
+    *
+    * Helpers for producing and handling the data a network is trained on.
+    * Following the rest of the library, a dataset is a pair of matrices
+    * (X, Y) where each row is one sample: X has shape N x C_in and Y has
+    * shape N x C_out.
+    *
+    * Currently, the following generators are implemented:
+    * 1. make_regression - Nonlinear regression targets y = Σ_j sin(x_j) + noise.
+    * 2. make_classification - Gaussian blobs, one per class, with one-hot targets.
+    *
+    * Every generator takes a seed so that the same call always produces the
+    * same dataset.
+    *
+    * The DataLoader splits a dataset into mini-batches for training, optionally
+    * shuffling the samples every epoch. shuffle_rows applies the same shuffle
+    * to an (X, Y) pair in place, so samples stay paired with their targets.
+    *
+    * Instead of visiting every sample once per epoch, a DataLoader can draw
+    * its samples from a WeightedSampler: with replacement and proportional to
+    * one weight per sample, e.g. to oversample rare classes. A
+    * HardNegativeMiner turns the per-sample losses of the last epoch into
+    * such weights, so the next epoch revisits the hardest samples more often.
+    *
+    * A CurriculumLoader orders the samples by a difficulty score instead and
+    * trains on a growing prefix of them, from the easiest samples in the
+    * first epoch to the whole dataset at the end of the curriculum.
+    *
+    * Features are standardized to zero mean and unit variance with a
+    * StandardScaler fitted on a whole dataset, or with a RunningStandardizer
+    * whose statistics are updated batch by batch when the data arrives as a
+    * stream and never fits in memory at once.
+    *
+**/
+
+
+// Generates a toy nonlinear regression problem. Inputs are sampled uniformly
+// from [-π, π] and the target is y = Σ_j sin(x_j) + noise * ε with ε ~ N(0, 1).
+// With n_features = 1 this is the classic y = sin(x) benchmark.
+// Returns X (n_samples x n_features) and Y (n_samples x 1).
+pub fn make_regression(n_samples : usize, n_features : usize, noise : f64, seed : u64) -> (DMatrix<f64>, DMatrix<f64>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let X = DMatrix::from_fn(n_samples, n_features, |_, _| rng.gen_range(-consts::PI..consts::PI));
+    let Y = DMatrix::from_fn(n_samples, 1, |i, _| {
+        let eps : f64 = rng.sample(StandardNormal);
+        X.row(i).iter().map(|x| x.sin()).sum::<f64>() + noise * eps
+    });
+    return (X, Y);
+}
+
+// Generates a toy classification problem with one Gaussian blob per class.
+// Each class center is drawn uniformly from [-5, 5]^n_features and samples
+// are spread around it with unit variance. Samples are assigned to classes
+// in turn, so the classes are balanced.
+// Returns X (n_samples x n_features) and one-hot Y (n_samples x n_classes).
+pub fn make_classification(n_samples : usize, n_features : usize, n_classes : usize, seed : u64) -> (DMatrix<f64>, DMatrix<f64>) {
+    assert!(n_classes > 0, "make_classification needs at least one class");
+    let mut rng = StdRng::seed_from_u64(seed);
+    let centers = DMatrix::from_fn(n_classes, n_features, |_, _| rng.gen_range(-5.0..5.0));
+    let mut X = DMatrix::zeros(n_samples, n_features);
+    let mut Y = DMatrix::zeros(n_samples, n_classes);
+    for i in 0..n_samples {
+        let class = i % n_classes;
+        for j in 0..n_features {
+            let eps : f64 = rng.sample(StandardNormal);
+            X[(i, j)] = centers[(class, j)] + eps;
+        }
+        Y[(i, class)] = 1.0;
+    }
+    return (X, Y);
+}
+
+// A random permutation of 0..n
+fn permutation(n : usize, rng : &mut StdRng) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..n).collect();
+    indices.shuffle(rng);
+    return indices;
+}
+
+// Shuffles the samples (rows) of x and y in place, applying the same random
+// permutation to both so that every input stays paired with its target.
+// The same seed always produces the same permutation.
+pub fn shuffle_rows(x : &mut DMatrix<f64>, y : &mut DMatrix<f64>, seed : u64) {
+    assert_eq!(x.nrows(), y.nrows(), "x and y must have the same number of samples (rows)");
+    let mut rng = StdRng::seed_from_u64(seed);
+    let indices = permutation(x.nrows(), &mut rng);
+    *x = x.select_rows(&indices);
+    *y = y.select_rows(&indices);
+}
+
+// Draws sample indices with replacement, each index i with probability
+// weights[i] / Σ weights. Every epoch consists of num_samples draws.
+#[derive(Clone)]
+pub struct WeightedSampler {
+    pub num_samples: usize, // Number of draws per epoch
+    cumulative: Vec<f64> // Running sums of the weights
+}
+
+impl WeightedSampler {
+    pub fn new(weights: &[f64], num_samples: usize) -> Self {
+        assert!(weights.iter().all(|w| *w >= 0.0 && w.is_finite()), "Sample weights must be finite and non-negative");
+        let cumulative: Vec<f64> = weights.iter()
+            .scan(0.0, |sum, w| { *sum += w; Some(*sum) })
+            .collect();
+        assert!(cumulative.last().is_some_and(|total| *total > 0.0), "Sample weights must not all be zero");
+        WeightedSampler {
+            num_samples: num_samples,
+            cumulative: cumulative
+        }
+    }
+
+    // The sample indices of one epoch
+    pub fn sample(&self, rng: &mut StdRng) -> Vec<usize> {
+        let total = self.cumulative[self.cumulative.len() - 1];
+        return (0..self.num_samples)
+            .map(|_| {
+                let u = rng.gen::<f64>() * total;
+                // First index whose running sum exceeds u, which skips zero weights
+                self.cumulative.partition_point(|c| *c <= u).min(self.cumulative.len() - 1)
+            })
+            .collect();
+    }
+}
+
+// Biases sampling toward the samples the model currently gets most wrong.
+// After every epoch the miner is updated with the loss of each sample, and
+// the weight of sample i becomes a mix of uniform and loss-proportional
+// sampling:
+//   w_i = (1 - hard_fraction) / N + hard_fraction * l_i / Σ_j l_j
+// so hard_fraction of the draws (in expectation) go to the high-loss
+// samples while every sample keeps a chance of being revisited. Before the
+// first update all weights are uniform. The weights feed a WeightedSampler
+// for the DataLoader, e.g. loader.sampler = Some(miner.sampler(n)).
+pub struct HardNegativeMiner {
+    pub hard_fraction: f64, // Fraction of the sampling mass distributed by loss
+    weights: Vec<f64> // Current sampling weight of each sample
+}
+
+impl HardNegativeMiner {
+    pub fn new(num_samples: usize, hard_fraction: f64) -> Self {
+        assert!(num_samples > 0, "HardNegativeMiner needs at least one sample");
+        assert!((0.0..=1.0).contains(&hard_fraction), "hard_fraction must be in [0, 1], got {}", hard_fraction);
+        HardNegativeMiner {
+            hard_fraction: hard_fraction,
+            weights: vec![1.0 / num_samples as f64; num_samples]
+        }
+    }
+
+    // Recomputes the weights from the per-sample losses of the last epoch.
+    // If all losses are zero the weights fall back to uniform.
+    pub fn update(&mut self, losses: &[f64]) {
+        assert_eq!(losses.len(), self.weights.len(), "Expected one loss per sample");
+        assert!(losses.iter().all(|l| *l >= 0.0 && l.is_finite()), "Losses must be finite and non-negative");
+        let n = losses.len() as f64;
+        let total: f64 = losses.iter().sum();
+        for (w, l) in self.weights.iter_mut().zip(losses.iter()) {
+            let hard = if total > 0.0 { l / total } else { 1.0 / n };
+            *w = (1.0 - self.hard_fraction) / n + self.hard_fraction * hard;
+        }
+    }
+
+    pub fn weights(&self) -> &[f64] {
+        return &self.weights;
+    }
+
+    // A sampler drawing num_samples indices per epoch from the current weights
+    pub fn sampler(&self, num_samples: usize) -> WeightedSampler {
+        return WeightedSampler::new(&self.weights, num_samples);
+    }
+}
+
+// Splits a dataset into mini-batches. Each call to batches() yields one epoch;
+// when shuffle is set the samples are visited in a new random order each epoch
+// (drawn from a seeded RNG, so runs are reproducible). The last batch is
+// smaller when the number of samples is not a multiple of batch_size.
+#[derive(Clone)]
+pub struct DataLoader {
+    pub X: DMatrix<f64>, // Inputs (N x C_in)
+    pub Y: DMatrix<f64>, // Targets (N x C_out)
+    pub batch_size: usize,
+    pub shuffle: bool,
+    pub sampler: Option<WeightedSampler>, // When set, epochs are drawn from the sampler instead
+    rng: StdRng
+}
+
+impl DataLoader {
+    pub fn new(X: DMatrix<f64>, Y: DMatrix<f64>, batch_size: usize, shuffle: bool, seed: u64) -> Self {
+        assert_eq!(X.nrows(), Y.nrows(), "X and Y must have the same number of samples (rows)");
+        assert!(batch_size > 0, "batch_size must be positive");
+        DataLoader {
+            X: X,
+            Y: Y,
+            batch_size: batch_size,
+            shuffle: shuffle,
+            sampler: None,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    // Draws every epoch from a WeightedSampler over the given per-sample
+    // weights: num_samples draws with replacement, in random order, using a
+    // new RNG seeded with seed. Replaces the shuffle setting.
+    pub fn with_sampler(mut self, weights: &[f64], num_samples: usize, seed: u64) -> Self {
+        assert_eq!(weights.len(), self.X.nrows(), "Expected one weight per sample");
+        self.sampler = Some(WeightedSampler::new(weights, num_samples));
+        self.rng = StdRng::seed_from_u64(seed);
+        return self;
+    }
+
+    // Number of samples in one epoch
+    pub fn num_samples(&self) -> usize {
+        return match &self.sampler {
+            Some(sampler) => sampler.num_samples,
+            None => self.X.nrows()
+        };
+    }
+
+    // Number of batches in one epoch, including a final partial batch
+    pub fn num_batches(&self) -> usize {
+        return self.num_samples().div_ceil(self.batch_size);
+    }
+
+    // Returns the (x, y) mini-batches of one epoch.
+    pub fn batches(&mut self) -> Vec<(DMatrix<f64>, DMatrix<f64>)> {
+        let indices = if let Some(sampler) = &self.sampler {
+            sampler.sample(&mut self.rng)
+        } else if self.shuffle {
+            permutation(self.num_samples(), &mut self.rng)
+        } else {
+            (0..self.num_samples()).collect()
+        };
+        let mut batches = Vec::with_capacity(self.num_batches());
+        for chunk in indices.chunks(self.batch_size) {
+            batches.push((self.X.select_rows(chunk), self.Y.select_rows(chunk)));
+        }
+        return batches;
+    }
+}
+
+// How fast a CurriculumLoader grows the fraction of the data it uses, from
+// initial_fraction c0 at epoch 0 to 1 at epoch T = num_epochs - 1:
+//   Linear: c(t) = c0 + (1 - c0) * t / T
+//   Root:   c(t) = sqrt(c0^2 + (1 - c0^2) * t / T), faster early on so every
+//           new sample gets about the same number of epochs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pacing {
+    Linear,
+    Root
+}
+
+// Curriculum learning: presents the easiest samples first and gradually adds
+// harder ones. The samples are sorted by difficulty (lower is easier) once;
+// each call to batches() yields one epoch over the easiest c(t) * N samples,
+// shuffled, and advances the epoch. After num_epochs epochs the full dataset
+// is used. The pacing defaults to Linear from a tenth of the data.
+pub struct CurriculumLoader {
+    pub X: DMatrix<f64>, // Inputs (N x C_in), sorted from easiest to hardest
+    pub Y: DMatrix<f64>, // Targets (N x C_out), in the same order
+    pub batch_size: usize,
+    pub pacing: Pacing,
+    pub initial_fraction: f64, // Fraction of the data used in the first epoch
+    pub num_epochs: usize, // Length of the curriculum in epochs
+    pub epoch: usize, // Index of the next epoch
+    rng: StdRng
+}
+
+impl CurriculumLoader {
+    pub fn new(X: DMatrix<f64>, Y: DMatrix<f64>, difficulty: &[f64], batch_size: usize, num_epochs: usize,
+               seed: u64) -> Self {
+        assert_eq!(X.nrows(), Y.nrows(), "X and Y must have the same number of samples (rows)");
+        assert_eq!(difficulty.len(), X.nrows(), "Expected one difficulty score per sample");
+        assert!(batch_size > 0, "batch_size must be positive");
+        assert!(num_epochs > 0, "The curriculum needs at least one epoch");
+        let mut order: Vec<usize> = (0..X.nrows()).collect();
+        order.sort_by(|&i, &j| difficulty[i].total_cmp(&difficulty[j]));
+        CurriculumLoader {
+            X: X.select_rows(&order),
+            Y: Y.select_rows(&order),
+            batch_size: batch_size,
+            pacing: Pacing::Linear,
+            initial_fraction: 0.1,
+            num_epochs: num_epochs,
+            epoch: 0,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+
+    // Uses the given pacing function, starting from initial_fraction of the data
+    pub fn with_pacing(mut self, pacing: Pacing, initial_fraction: f64) -> Self {
+        assert!(initial_fraction > 0.0 && initial_fraction <= 1.0, "initial_fraction must be in (0, 1], got {}", initial_fraction);
+        self.pacing = pacing;
+        self.initial_fraction = initial_fraction;
+        return self;
+    }
+
+    // Fraction c(t) of the data used in the given epoch
+    pub fn fraction(&self, epoch: usize) -> f64 {
+        if self.num_epochs == 1 {
+            return 1.0;
+        }
+        let progress = (epoch as f64 / (self.num_epochs - 1) as f64).min(1.0);
+        let c0 = self.initial_fraction;
+        return match self.pacing {
+            Pacing::Linear => c0 + (1.0 - c0) * progress,
+            Pacing::Root => (c0 * c0 + (1.0 - c0 * c0) * progress).sqrt()
+        };
+    }
+
+    // Number of samples in the next epoch, at least one
+    pub fn num_samples(&self) -> usize {
+        let n = self.X.nrows();
+        return ((self.fraction(self.epoch) * n as f64).ceil() as usize).clamp(1, n);
+    }
+
+    // Returns the (x, y) mini-batches of the next epoch and advances the curriculum
+    pub fn batches(&mut self) -> Vec<(DMatrix<f64>, DMatrix<f64>)> {
+        let indices = permutation(self.num_samples(), &mut self.rng);
+        self.epoch += 1;
+        return indices.chunks(self.batch_size)
+            .map(|chunk| (self.X.select_rows(chunk), self.Y.select_rows(chunk)))
+            .collect();
+    }
+}
+
+// Standardizes features as (x - mean) / std, with the mean and (population)
+// standard deviation of every feature (column) computed from a whole dataset.
+// Constant features have std 0 and are only centered.
+pub struct StandardScaler {
+    pub mean: DMatrix<f64>, // Mean of each feature (1 x C)
+    pub std: DMatrix<f64> // Standard deviation of each feature (1 x C)
+}
+
+impl StandardScaler {
+    pub fn fit(x: &DMatrix<f64>) -> Self {
+        assert!(x.nrows() > 0, "Cannot fit a StandardScaler on an empty dataset");
+        let n = x.nrows() as f64;
+        let mean = x.row_sum() / n;
+        let centered = DMatrix::from_fn(x.nrows(), x.ncols(), |i, j| x[(i, j)] - mean[(0, j)]);
+        let std = (centered.component_mul(&centered).row_sum() / n).map(|v| v.sqrt());
+        StandardScaler {
+            mean: DMatrix::from_iterator(1, x.ncols(), mean.iter().cloned()),
+            std: DMatrix::from_iterator(1, x.ncols(), std.iter().cloned())
+        }
+    }
+
+    pub fn transform(&self, x: &DMatrix<f64>) -> DMatrix<f64> {
+        return standardize(x, &self.mean, &self.std);
+    }
+}
+
+// (x - mean) / std column by column, leaving the scale of constant features
+fn standardize(x: &DMatrix<f64>, mean: &DMatrix<f64>, std: &DMatrix<f64>) -> DMatrix<f64> {
+    assert_eq!(x.ncols(), mean.ncols(), "Expected {} features, got {}", mean.ncols(), x.ncols());
+    return DMatrix::from_fn(x.nrows(), x.ncols(), |i, j| {
+        let scale = if std[(0, j)] > 0.0 { std[(0, j)] } else { 1.0 };
+        (x[(i, j)] - mean[(0, j)]) / scale
+    });
+}
+
+// Standardizes streaming features with a running mean and variance, kept up
+// to date by Welford's online algorithm. Each batch is merged into the
+// running statistics with the parallel form of the update (Chan et al.): for
+// a batch of n_b samples with mean μ_b and sum of squared deviations M_b,
+//   δ = μ_b - μ,   n' = n + n_b
+//   μ' = μ + δ * n_b / n'
+//   M' = M + M_b + δ^2 * n * n_b / n'
+// and the variance is M / n. This is numerically stable, unlike tracking
+// Σ x and Σ x^2, and gives the same statistics as a single pass over all
+// the data.
+pub struct RunningStandardizer {
+    pub count: usize, // Number of samples seen so far
+    pub mean: DMatrix<f64>, // Running mean of each feature (1 x C)
+    m2: DMatrix<f64> // Running sum of squared deviations from the mean (1 x C)
+}
+
+impl RunningStandardizer {
+    pub fn new(num_features: usize) -> Self {
+        RunningStandardizer {
+            count: 0,
+            mean: DMatrix::zeros(1, num_features),
+            m2: DMatrix::zeros(1, num_features)
+        }
+    }
+
+    // Adds the samples (rows) of x to the running statistics
+    pub fn update(&mut self, x: &DMatrix<f64>) {
+        assert_eq!(x.ncols(), self.mean.ncols(), "Expected {} features, got {}", self.mean.ncols(), x.ncols());
+        if x.nrows() == 0 {
+            return;
+        }
+        let batch = StandardScaler::fit(x);
+        let n = self.count as f64;
+        let n_b = x.nrows() as f64;
+        let total = n + n_b;
+        for j in 0..x.ncols() {
+            let delta = batch.mean[(0, j)] - self.mean[(0, j)];
+            let m2_b = batch.std[(0, j)] * batch.std[(0, j)] * n_b;
+            self.mean[(0, j)] += delta * n_b / total;
+            self.m2[(0, j)] += m2_b + delta * delta * n * n_b / total;
+        }
+        self.count += x.nrows();
+    }
+
+    // Population variance of each feature over all samples seen (1 x C)
+    pub fn variance(&self) -> DMatrix<f64> {
+        return self.m2.map(|m| if self.count > 0 { m / self.count as f64 } else { 0.0 });
+    }
+
+    // Standardizes x with the statistics seen so far
+    pub fn transform(&self, x: &DMatrix<f64>) -> DMatrix<f64> {
+        return standardize(x, &self.mean, &self.variance().map(|v| v.sqrt()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_make_regression_shapes() {
+        let (X, Y) = make_regression(50, 3, 0.1, 0);
+        assert_eq!(X.shape(), (50, 3));
+        assert_eq!(Y.shape(), (50, 1));
+        assert!(X.iter().all(|x| x.abs() <= consts::PI));
+    }
+
+    #[test]
+    fn test_make_regression_noiseless_is_sine() {
+        let (X, Y) = make_regression(20, 1, 0.0, 7);
+        assert_abs_diff_eq!(Y, X.map(|x| x.sin()), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_make_regression_reproducible() {
+        let (X1, Y1) = make_regression(10, 2, 0.5, 42);
+        let (X2, Y2) = make_regression(10, 2, 0.5, 42);
+        let (X3, _) = make_regression(10, 2, 0.5, 43);
+        assert_eq!(X1, X2);
+        assert_eq!(Y1, Y2);
+        assert_ne!(X1, X3);
+    }
+
+    #[test]
+    fn test_make_classification_shapes() {
+        let (X, Y) = make_classification(30, 2, 3, 0);
+        assert_eq!(X.shape(), (30, 2));
+        assert_eq!(Y.shape(), (30, 3));
+        // Each row of Y is one-hot and the classes are balanced
+        for i in 0..30 {
+            assert_abs_diff_eq!(Y.row(i).sum(), 1.0, epsilon = 1e-12);
+        }
+        for k in 0..3 {
+            assert_abs_diff_eq!(Y.column(k).sum(), 10.0, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_make_classification_reproducible() {
+        let (X1, Y1) = make_classification(12, 4, 2, 3);
+        let (X2, Y2) = make_classification(12, 4, 2, 3);
+        assert_eq!(X1, X2);
+        assert_eq!(Y1, Y2);
+    }
+
+    #[test]
+    fn test_data_loader_batches() {
+        let X = DMatrix::from_fn(10, 2, |i, j| (2 * i + j) as f64);
+        let Y = DMatrix::from_fn(10, 1, |i, _| i as f64);
+        let mut loader = DataLoader::new(X.clone(), Y, 4, false, 0);
+        let batches = loader.batches();
+        assert_eq!(loader.num_batches(), 3);
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].0, X.rows(0, 4).into_owned());
+        assert_eq!(batches[2].0.nrows(), 2); // Ragged final batch
+    }
+
+    #[test]
+    fn test_data_loader_shuffle_keeps_pairs() {
+        let X = DMatrix::from_fn(20, 1, |i, _| i as f64);
+        let Y = DMatrix::from_fn(20, 1, |i, _| 10.0 * i as f64);
+        let mut loader = DataLoader::new(X.clone(), Y, 8, true, 1);
+        let first = loader.batches();
+        let second = loader.batches();
+        assert_ne!(first[0].0, second[0].0); // New order every epoch
+        for (x, y) in first.iter().chain(second.iter()) {
+            assert_eq!(&x.map(|v| 10.0 * v), y);
+        }
+        let n: usize = first.iter().map(|(x, _)| x.nrows()).sum();
+        assert_eq!(n, 20);
+        assert_ne!(first[0].0, X.rows(0, 8).into_owned());
+    }
+
+    #[test]
+    fn test_weighted_sampler_draws_proportionally() {
+        let X = DMatrix::from_fn(4, 1, |i, _| i as f64);
+        let Y = X.clone();
+        // Sample 3 is weighted 7 times as much as the others, sample 1 never drawn
+        let weights = [1.0, 0.0, 2.0, 7.0];
+        let mut loader = DataLoader::new(X, Y, 100, false, 0).with_sampler(&weights, 1000, 3);
+        assert_eq!(loader.num_samples(), 1000);
+        assert_eq!(loader.num_batches(), 10);
+
+        let mut counts = [0usize; 4];
+        for _ in 0..10 {
+            for (x, y) in loader.batches() {
+                assert_eq!(x, y);
+                for v in x.iter() {
+                    counts[*v as usize] += 1;
+                }
+            }
+        }
+        assert_eq!(counts.iter().sum::<usize>(), 10000);
+        assert_eq!(counts[1], 0);
+        for (count, w) in counts.iter().zip(weights.iter()) {
+            let expected = 10000.0 * w / 10.0;
+            assert!((*count as f64 - expected).abs() < 200.0, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_hard_negative_miner_oversamples_high_loss() {
+        let X = DMatrix::from_fn(6, 1, |i, _| i as f64);
+        let mut loader = DataLoader::new(X.clone(), X, 100, false, 0);
+        let mut miner = HardNegativeMiner::new(6, 0.5);
+        assert!(miner.weights().iter().all(|w| (w - 1.0 / 6.0).abs() < 1e-12));
+
+        // Samples 4 and 5 had a high loss in the last epoch
+        miner.update(&[0.1, 0.1, 0.0, 0.2, 5.0, 4.6]);
+        assert_abs_diff_eq!(miner.weights().iter().sum::<f64>(), 1.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(miner.weights()[2], 0.5 / 6.0, epsilon = 1e-12); // Zero loss keeps the uniform share
+        loader.sampler = Some(miner.sampler(3000));
+
+        let mut counts = [0usize; 6];
+        for (x, _) in loader.batches() {
+            for v in x.iter() {
+                counts[*v as usize] += 1;
+            }
+        }
+        let (hard, easy) = (counts[4].min(counts[5]), counts[..4].iter().max().copied().unwrap());
+        assert!(hard > 2 * easy, "{:?}", counts);
+    }
+
+    #[test]
+    fn test_curriculum_loader_starts_easy_and_ends_with_all_samples() {
+        // The value of each sample is its difficulty, given in scrambled order
+        let X = DMatrix::from_fn(20, 1, |i, _| ((i * 7) % 20) as f64);
+        let difficulty: Vec<f64> = X.iter().cloned().collect();
+        let mut loader = CurriculumLoader::new(X.clone(), X, &difficulty, 3, 4, 0).with_pacing(Pacing::Linear, 0.25);
+
+        let values = |batches: Vec<(DMatrix<f64>, DMatrix<f64>)>| {
+            let mut values: Vec<f64> = batches.iter().flat_map(|(x, y)| {
+                assert_eq!(x, y);
+                x.iter().cloned().collect::<Vec<f64>>()
+            }).collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            values
+        };
+        // Epoch 0 uses the easiest quarter of the data, epoch 1 half of it
+        assert_eq!(values(loader.batches()), (0..5).map(|v| v as f64).collect::<Vec<f64>>());
+        assert_eq!(values(loader.batches()), (0..10).map(|v| v as f64).collect::<Vec<f64>>());
+        let _ = loader.batches();
+        assert_eq!(values(loader.batches()), (0..20).map(|v| v as f64).collect::<Vec<f64>>());
+        assert_eq!(loader.num_samples(), 20); // Past the curriculum the full dataset is kept
+
+        // Root pacing adds samples faster early on
+        let root = CurriculumLoader::new(DMatrix::zeros(20, 1), DMatrix::zeros(20, 1), &difficulty, 3, 4, 0)
+            .with_pacing(Pacing::Root, 0.25);
+        assert!(root.fraction(1) > 0.5 && root.fraction(0) == 0.25 && root.fraction(3) == 1.0);
+    }
+
+    #[test]
+    fn test_shuffle_rows_keeps_pairs() {
+        let mut x = DMatrix::from_fn(10, 2, |i, j| (i * 10 + j) as f64);
+        let mut y = DMatrix::from_fn(10, 1, |i, _| i as f64);
+        let x_original = x.clone();
+        shuffle_rows(&mut x, &mut y, 5);
+
+        assert_ne!(x, x_original);
+        // Row i of x came from original row y[i], for every row
+        for i in 0..10 {
+            let source = y[(i, 0)] as usize;
+            assert_eq!(x.row(i), x_original.row(source));
+        }
+    }
+
+    #[test]
+    fn test_shuffle_rows_reproducible() {
+        let (mut x1, mut y1) = make_regression(15, 2, 0.1, 0);
+        let (mut x2, mut y2) = (x1.clone(), y1.clone());
+        shuffle_rows(&mut x1, &mut y1, 11);
+        shuffle_rows(&mut x2, &mut y2, 11);
+        assert_eq!(x1, x2);
+        assert_eq!(y1, y2);
+    }
+
+    #[test]
+    fn test_standard_scaler() {
+        let x = DMatrix::from_row_slice(4, 2, &[1.0, 5.0,
+                                                2.0, 5.0,
+                                                3.0, 5.0,
+                                                4.0, 5.0]);
+        let scaler = StandardScaler::fit(&x);
+        assert_abs_diff_eq!(scaler.mean, DMatrix::from_row_slice(1, 2, &[2.5, 5.0]), epsilon = 1e-12);
+        assert_abs_diff_eq!(scaler.std, DMatrix::from_row_slice(1, 2, &[1.25f64.sqrt(), 0.0]), epsilon = 1e-12);
+
+        // The constant second feature is only centered
+        let z = scaler.transform(&x);
+        assert_abs_diff_eq!(z.column(0).sum(), 0.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(z.column(0).norm_squared() / 4.0, 1.0, epsilon = 1e-12);
+        assert!(z.column(1).iter().all(|v| v.abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_running_standardizer_matches_standard_scaler() {
+        let (x, _) = make_regression(103, 3, 0.0, 9);
+        let x = x.map(|v| 1000.0 + 10.0 * v); // Large offset, where naive sums lose precision
+        let mut running = RunningStandardizer::new(3);
+        let mut start = 0;
+        for size in [1, 10, 37, 0, 55] {
+            running.update(&x.rows(start, size).into_owned());
+            start += size;
+        }
+        assert_eq!(running.count, 103);
+
+        let scaler = StandardScaler::fit(&x);
+        assert_abs_diff_eq!(running.mean, scaler.mean, epsilon = 1e-9);
+        assert_abs_diff_eq!(running.variance().map(|v| v.sqrt()), scaler.std, epsilon = 1e-9);
+        assert_abs_diff_eq!(running.transform(&x), scaler.transform(&x), epsilon = 1e-9);
+    }
+}
@@ -0,0 +1,166 @@
+use nalgebra::{DMatrix};
+use crate::nn::layers::Layer;
+
+/**
+    * Debugging Utilities
+    *
+    * Tools for checking that a layer's analytic backward pass agrees with its
+    * forward pass. A wrong gradient rarely makes training crash; it usually
+    * just makes it converge slowly or not at all, so new layers should always
+    * be checked numerically.
+    *
+    * The check uses the scalar loss L = Σ (Z ⊙ G), where Z = forward(A) and G
+    * is a fixed upstream gradient. Then ∂L/∂Z = G, and backward(G) must return
+    * ∂L/∂A. Each input (and parameter) entry x is perturbed on both sides and
+    * the central difference (L(x + eps) - L(x - eps)) / (2 * eps) is compared
+    * with the analytic gradient.
+    *
+    * grad_check does the same for any scalar function of a matrix, e.g. the
+    * loss of a model as a function of one of its parameters.
+    *
+**/
+
+
+// Relative error between an analytic and a numerical gradient entry. The
+// denominator is floored at 1 so that tiny gradients, where finite
+// differences are mostly rounding noise, are compared in absolute terms.
+fn relative_error(analytic: f64, numeric: f64) -> f64 {
+    return (analytic - numeric).abs() / analytic.abs().max(numeric.abs()).max(1.0);
+}
+
+// Compares the analytic gradient of a scalar function with central
+// differences: every entry x of params is perturbed by ±eps and
+// (L(x + eps) - L(x - eps)) / (2 * eps) is compared with the matching entry
+// of analytic. Returns the maximum relative error (see relative_error), so a
+// correct gradient gives values around 1e-7 or below.
+pub fn grad_check<F: FnMut(&DMatrix<f64>) -> f64>(mut loss: F, params: &DMatrix<f64>, analytic: &DMatrix<f64>, eps: f64) -> f64 {
+    assert_eq!(params.shape(), analytic.shape(), "The gradient must have the shape of the parameters");
+    let mut max_error: f64 = 0.0;
+    let mut perturbed = params.clone();
+    for k in 0..params.len() {
+        perturbed[k] = params[k] + eps;
+        let loss_plus = loss(&perturbed);
+        perturbed[k] = params[k] - eps;
+        let loss_minus = loss(&perturbed);
+        perturbed[k] = params[k];
+
+        let numeric = (loss_plus - loss_minus) / (2.0 * eps);
+        max_error = max_error.max(relative_error(analytic[k], numeric));
+    }
+    return max_error;
+}
+
+// L = Σ (forward(A) ⊙ G)
+fn probe_loss<L: Layer + ?Sized>(layer: &mut L, input: &DMatrix<f64>, upstream_grad: &DMatrix<f64>) -> f64 {
+    return layer.forward(input).component_mul(upstream_grad).sum();
+}
+
+// Numerically verifies the backward pass of any layer. Returns the maximum
+// relative error over every entry of the input gradient and of the gradients
+// of the layer's parameters. Values around 1e-7 or below indicate a correct
+// backward; anything above 1e-4 almost certainly indicates a bug.
+pub fn check_layer_gradient<L: Layer + ?Sized>(layer: &mut L, input: &DMatrix<f64>, upstream_grad: &DMatrix<f64>, eps: f64) -> f64 {
+    // Analytic gradients
+    let _ = layer.forward(input);
+    let dLdA = layer.backward(upstream_grad);
+    let param_grads: Vec<DMatrix<f64>> = layer.gradients().into_iter().cloned().collect();
+
+    // Gradient with respect to the input
+    let mut max_error = grad_check(|A| probe_loss(layer, A, upstream_grad), input, &dLdA, eps);
+
+    // Gradients with respect to the parameters
+    for (p, grad) in param_grads.iter().enumerate() {
+        for k in 0..grad.len() {
+            let original = layer.parameters_mut()[p][k];
+            layer.parameters_mut()[p][k] = original + eps;
+            let loss_plus = probe_loss(layer, input, upstream_grad);
+            layer.parameters_mut()[p][k] = original - eps;
+            let loss_minus = probe_loss(layer, input, upstream_grad);
+            layer.parameters_mut()[p][k] = original;
+
+            let numeric = (loss_plus - loss_minus) / (2.0 * eps);
+            max_error = max_error.max(relative_error(grad[k], numeric));
+        }
+    }
+
+    // Leave the layer's caches consistent with the unperturbed input
+    let _ = layer.forward(input);
+    let _ = layer.backward(upstream_grad);
+    return max_error;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{ReLU, Sigmoid};
+    use crate::nn::layers::Linear;
+
+    // A layer whose backward forgets the weights, to make sure the check fails
+    struct BrokenLinear {
+        inner: Linear
+    }
+
+    impl Layer for BrokenLinear {
+        fn forward(&mut self, A: &DMatrix<f64>) -> DMatrix<f64> {
+            return self.inner.forward(A);
+        }
+        fn backward(&mut self, dLdZ: &DMatrix<f64>) -> DMatrix<f64> {
+            let _ = self.inner.backward(dLdZ);
+            return dLdZ.columns(0, self.inner.W.ncols()).into_owned();
+        }
+    }
+
+    #[test]
+    fn test_check_linear_gradient() {
+        let mut linear = Linear::new(3, 2);
+        let input = DMatrix::from_row_slice(4, 3, &[0.5, -1.0, 2.0,
+                                                    1.5, 0.0, -0.5,
+                                                    -2.0, 1.0, 0.25,
+                                                    0.1, 0.2, 0.3]);
+        let upstream_grad = DMatrix::from_row_slice(4, 2, &[1.0, -2.0,
+                                                            0.5, 0.5,
+                                                            -1.0, 3.0,
+                                                            2.0, 0.0]);
+        let error = check_layer_gradient(&mut linear, &input, &upstream_grad, 1e-5);
+        assert!(error < 1e-7, "max relative error {}", error);
+    }
+
+    #[test]
+    fn test_check_detects_wrong_gradient() {
+        let mut linear = BrokenLinear { inner: Linear::new(2, 2) };
+        linear.inner.W = DMatrix::from_row_slice(2, 2, &[2.0, 1.0,
+                                                         -1.0, 3.0]);
+        let input = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                    3.0, 4.0]);
+        let upstream_grad = DMatrix::from_element(2, 2, 1.0);
+        let error = check_layer_gradient(&mut linear, &input, &upstream_grad, 1e-5);
+        assert!(error > 1e-2);
+    }
+
+    #[test]
+    fn test_grad_check_activations() {
+        // No entry lies at the kink of ReLU
+        let Z = DMatrix::from_row_slice(2, 3, &[0.5, -1.0, 2.0,
+                                                -0.3, 0.8, 1.5]);
+        let G = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.5, 3.0, -1.0]);
+
+        let mut relu = ReLU::new();
+        let _ = relu.forward(&Z);
+        let analytic = relu.backward(&G);
+        let error = grad_check(|Z| ReLU::new().forward(Z).component_mul(&G).sum(), &Z, &analytic, 1e-5);
+        assert!(error < 1e-7, "max relative error {}", error);
+
+        // Sigmoid rounds its outputs to 1e-5 and its gradients to 1e-4, so a
+        // larger step and tolerance are needed
+        let mut sigmoid = Sigmoid::new();
+        let _ = sigmoid.forward(&Z);
+        let analytic = sigmoid.backward(&G);
+        let error = grad_check(|Z| Sigmoid::new().forward(Z).component_mul(&G).sum(), &Z, &analytic, 1e-2);
+        assert!(error < 1e-3, "max relative error {}", error);
+
+        // A wrong gradient is caught
+        let error = grad_check(|Z| ReLU::new().forward(Z).component_mul(&G).sum(), &Z, &G, 1e-5);
+        assert!(error > 0.5);
+    }
+}
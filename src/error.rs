@@ -0,0 +1,54 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/**
+    * Errors
+    *
+    * DnnError is the error type returned by the fallible operations of the
+    * library, e.g. building components from configuration or loading
+    * parameters.
+    *
+**/
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnnError {
+    UnknownActivation(String), // No activation is registered under this name
+    MissingKeys(Vec<String>), // State dict keys the model expects but were not given
+    UnexpectedKeys(Vec<String>), // State dict keys that match no parameter of the model
+    ShapeMismatch { key: String, expected: (usize, usize), found: (usize, usize) },
+    ArchitectureMismatch(String), // Two models that must have the same architecture differ, and how
+    Io(String), // Reading or writing a file failed
+    InvalidFormat(String), // A serialized model is malformed
+    UnsupportedVersion { found: u32, supported: u32 }, // A serialized model has an incompatible format version
+    InvalidShape(String), // A tensor shape does not fit its data or the requested reshape
+}
+
+impl fmt::Display for DnnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DnnError::UnknownActivation(name) => write!(f, "unknown activation function: {:?}", name),
+            DnnError::MissingKeys(keys) => write!(f, "missing keys in state dict: {}", keys.join(", ")),
+            DnnError::UnexpectedKeys(keys) => write!(f, "unexpected keys in state dict: {}", keys.join(", ")),
+            DnnError::ShapeMismatch { key, expected, found } =>
+                write!(f, "shape mismatch for {}: expected {}x{}, found {}x{}", key, expected.0, expected.1, found.0, found.1),
+            DnnError::ArchitectureMismatch(reason) => write!(f, "architecture mismatch: {}", reason),
+            DnnError::Io(reason) => write!(f, "i/o error: {}", reason),
+            DnnError::InvalidFormat(reason) => write!(f, "invalid model format: {}", reason),
+            DnnError::UnsupportedVersion { found, supported } =>
+                write!(f, "unsupported model format version {} (this version of the library reads version {})", found, supported),
+            DnnError::InvalidShape(reason) => write!(f, "invalid tensor shape: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DnnError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for DnnError {
+    fn from(error: std::io::Error) -> Self {
+        return DnnError::Io(error.to_string());
+    }
+}
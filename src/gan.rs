@@ -0,0 +1,80 @@
+use nalgebra::{DMatrix};
+use rand::Rng;
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Generative Adversarial Networks
+    *
+    * Utilities for training GANs, where a generator and a discriminator (or
+    * critic) D are trained against each other.
+    *
+    * Currently, the following utilities are implemented:
+    * 1. gradient_penalty - The WGAN-GP penalty. A Wasserstein critic must be
+    *                       1-Lipschitz, which WGAN-GP encourages softly by
+    *                       penalizing input gradients whose norm is not 1 at
+    *                       random points x̂ = ε * x_real + (1 - ε) * x_fake
+    *                       between real and generated samples:
+    *                       GP = λ * mean_i (‖∂D(x̂_i)/∂x̂_i‖ - 1)^2
+    *
+**/
+
+
+// Computes the WGAN-GP gradient penalty of a critic with a single output.
+// Each real sample (row) is paired with the fake sample in the same row and
+// interpolated with its own ε ~ U(0, 1). The input gradient of the critic
+// is obtained by backpropagating ∂D/∂D = 1 through the network, which also
+// overwrites the gradients stored in its layers.
+pub fn gradient_penalty(discriminator: &mut NeuralNetwork, real: &DMatrix<f64>, fake: &DMatrix<f64>, lambda: f64) -> f64 {
+    assert_eq!(real.shape(), fake.shape(), "Real and fake batches must have the same shape");
+    let mut rng = crate::random::rng();
+    let mut interpolated = real.clone();
+    for i in 0..real.nrows() {
+        let eps: f64 = rng.gen();
+        let row = eps * real.row(i) + (1.0 - eps) * fake.row(i);
+        interpolated.row_mut(i).copy_from(&row);
+    }
+
+    let output = discriminator.forward(&interpolated);
+    assert_eq!(output.ncols(), 1, "The discriminator must have a single output");
+    // The samples are independent, so row i of the input gradient of Σ_i D(x̂_i) is ∂D(x̂_i)/∂x̂_i
+    let grad = discriminator.backward_from(&DMatrix::from_element(output.nrows(), 1, 1.0));
+    let penalty: f64 = grad.row_iter().map(|g| (g.norm() - 1.0).powi(2)).sum();
+    return lambda * penalty / real.nrows() as f64;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    fn linear_critic(w: &[f64]) -> NeuralNetwork {
+        let mut critic = NeuralNetwork::new(vec![Box::new(Linear::new(w.len(), 1))], vec![], Box::new(MSE::new()));
+        critic.layers[0].W = DMatrix::from_row_slice(1, w.len(), w);
+        return critic;
+    }
+
+    #[test]
+    fn test_gradient_penalty_zero_for_unit_gradient_norm() {
+        // A linear critic has input gradient W everywhere, here with norm 1
+        let mut critic = linear_critic(&[0.6, -0.8]);
+        let real = DMatrix::from_row_slice(3, 2, &[1.0, 2.0,
+                                                   -1.0, 0.5,
+                                                   3.0, 3.0]);
+        let fake = DMatrix::from_row_slice(3, 2, &[0.0, 0.0,
+                                                   2.0, -2.0,
+                                                   1.0, -1.0]);
+        assert_abs_diff_eq!(gradient_penalty(&mut critic, &real, &fake, 10.0), 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_gradient_penalty_penalizes_norm_deviation() {
+        // ‖W‖ = 2, so every sample contributes (2 - 1)^2 = 1
+        let mut critic = linear_critic(&[0.0, 2.0]);
+        let real = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                   -1.0, 0.5]);
+        let fake = DMatrix::zeros(2, 2);
+        assert_abs_diff_eq!(gradient_penalty(&mut critic, &real, &fake, 10.0), 10.0, epsilon = 1e-12);
+    }
+}
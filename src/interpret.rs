@@ -0,0 +1,88 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Interpretability
+    *
+    * Attribution methods explain a prediction by assigning every input
+    * feature a share of it.
+    *
+    * Currently, the following methods are implemented:
+    * 1. integrated_gradients - Integrates the input gradient of one output
+    *                           along the straight line from a baseline x' to
+    *                           the input x:
+    *                           IG_j = (x_j - x'_j) * ∫_0^1 ∂F(x' + α (x - x'))/∂x_j dα
+    *                           The attributions satisfy completeness,
+    *                           Σ_j IG_j = F(x) - F(x'), exactly for the exact
+    *                           integral and up to the quadrature error for a
+    *                           finite number of steps.
+    *
+**/
+
+
+// Integrated gradients of output column target for every sample (row) of x,
+// relative to the baseline of the same shape (often zeros). The integral is
+// approximated by the midpoint rule with steps points α_k = (k + 1/2) / steps.
+// Returns the attributions in the shape of x. Backpropagating through the
+// network overwrites the gradients stored in its layers.
+pub fn integrated_gradients(net: &mut NeuralNetwork, x: &DMatrix<f64>, baseline: &DMatrix<f64>,
+                            target: usize, steps: usize) -> DMatrix<f64> {
+    assert_eq!(x.shape(), baseline.shape(), "The input and the baseline must have the same shape");
+    assert!(steps > 0, "integrated_gradients needs at least one step");
+    let delta = x - baseline;
+    let mut total = DMatrix::zeros(x.nrows(), x.ncols());
+    for k in 0..steps {
+        let alpha = (k as f64 + 0.5) / steps as f64;
+        let output = net.forward(&(baseline + alpha * &delta));
+        assert!(target < output.ncols(), "Target {} out of range ({} outputs)", target, output.ncols());
+        // The samples are independent, so row i of the input gradient of Σ_i F_target(x_i) is ∂F_target(x_i)/∂x_i
+        let mut dLdA = DMatrix::zeros(output.nrows(), output.ncols());
+        dLdA.column_mut(target).fill(1.0);
+        total += net.backward_from(&dLdA);
+    }
+    return delta.component_mul(&(total / steps as f64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{Sigmoid, Tanh};
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_integrated_gradients_completeness_linear() {
+        let mut net = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![], Box::new(MSE::new()));
+        net.layers[0].W = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                          0.3, 0.0, 4.0]);
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, -1.0,
+                                                0.5, -0.5, 3.0]);
+        let baseline = DMatrix::zeros(2, 3);
+        let attributions = integrated_gradients(&mut net, &x, &baseline, 1, 4);
+
+        // For a linear model IG_j = W_target,j * (x_j - x'_j)
+        for i in 0..2 {
+            for j in 0..3 {
+                assert_abs_diff_eq!(attributions[(i, j)], net.layers[0].W[(1, j)] * x[(i, j)], epsilon = 1e-12);
+            }
+        }
+        let difference = net.forward(&x) - net.forward(&baseline);
+        for i in 0..2 {
+            assert_abs_diff_eq!(attributions.row(i).sum(), difference[(i, 1)], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_integrated_gradients_completeness_nonlinear() {
+        let mut net = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                         vec![Box::new(Tanh::new()), Box::new(Sigmoid::new())],
+                                         Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(1, 2, &[1.0, -0.5]);
+        let baseline = DMatrix::from_row_slice(1, 2, &[-0.5, 0.5]);
+        let attributions = integrated_gradients(&mut net, &x, &baseline, 0, 200);
+        let difference = net.forward(&x)[(0, 0)] - net.forward(&baseline)[(0, 0)];
+        // Tanh and Sigmoid round their outputs, which bounds the accuracy
+        assert_abs_diff_eq!(attributions.sum(), difference, epsilon = 1e-3);
+    }
+}
@@ -1,10 +1,84 @@
+// The code follows the notation of the math it implements (W, b, dLdZ, l_N, ...)
+// and favours explicit returns and field initializers for readability.
+#![allow(non_snake_case)]
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::new_without_default)]
+#![allow(clippy::empty_line_after_doc_comments, clippy::doc_overindented_list_items)]
+// usize::is_multiple_of needs Rust 1.87, newer than the crate requires
+#![allow(clippy::manual_is_multiple_of)]
+// Without the std feature only the core math (activation functions and
+// layers) is compiled, against alloc, e.g. for inference on embedded targets.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod nn {
+    #[cfg(feature = "std")]
     pub mod model;
+    #[cfg(feature = "std")]
+    pub mod sequential;
     pub mod layers;
     pub mod activation;
+    #[cfg(feature = "std")]
     pub mod loss;
+    #[cfg(feature = "std")]
+    pub mod dropout;
+    #[cfg(feature = "std")]
+    pub mod batchnorm;
+    #[cfg(feature = "std")]
+    pub mod multihead;
+    #[cfg(feature = "std")]
+    pub mod flatbuffer;
+    #[cfg(feature = "serde")]
+    pub mod serialize;
+
+    pub use activation::activation_from_name;
 }
 
+#[cfg(feature = "std")]
 pub mod optim {
     pub mod sgd;
-}
\ No newline at end of file
+    pub mod scheduler;
+    pub mod optimizer;
+    pub mod adam;
+    pub mod rmsprop;
+    pub mod adabelief;
+    pub mod radam;
+    pub mod sam;
+    pub mod stochastic_rounding;
+}
+
+pub mod error;
+pub mod scalar;
+pub mod tensor;
+#[cfg(feature = "std")]
+pub mod random;
+#[cfg(feature = "std")]
+pub mod data;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod tune;
+#[cfg(feature = "std")]
+pub mod math;
+#[cfg(feature = "std")]
+pub mod debug;
+#[cfg(feature = "std")]
+pub mod viz;
+#[cfg(feature = "std")]
+pub mod calibration;
+#[cfg(feature = "std")]
+pub mod regularization;
+#[cfg(feature = "std")]
+pub mod gan;
+#[cfg(feature = "std")]
+pub mod rl;
+#[cfg(feature = "std")]
+pub mod interpret;
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod pruning;
+#[cfg(feature = "std")]
+pub mod training;
+#[cfg(feature = "std")]
+pub mod precision;
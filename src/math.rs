@@ -0,0 +1,46 @@
+use nalgebra::{DMatrix};
+
+/**
+    * Matrix Helpers
+    *
+    * Small matrix operations that are shared by several parts of the library
+    * but are not provided directly by nalgebra.
+    *
+    * Currently, the following helpers are implemented:
+    * 1. pad_cols - Symmetrically pads a matrix with constant columns, e.g. to
+    *               keep the length of a sequence unchanged by a convolution.
+    *
+**/
+
+
+// Adds `pad` columns filled with `value` on both the left and the right of m.
+// An (R x C) input becomes (R x (C + 2 * pad)), with the original values in
+// columns pad..pad + C.
+pub fn pad_cols(m: &DMatrix<f64>, pad: usize, value: f64) -> DMatrix<f64> {
+    let mut padded = DMatrix::from_element(m.nrows(), m.ncols() + 2 * pad, value);
+    padded.columns_mut(pad, m.ncols()).copy_from(m);
+    return padded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_cols() {
+        let m = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                4.0, 5.0, 6.0]);
+        let padded = pad_cols(&m, 2, -1.0);
+        assert_eq!(padded.shape(), (2, 7));
+        assert_eq!(padded.columns(2, 3), m);
+        for j in [0, 1, 5, 6] {
+            assert!(padded.column(j).iter().all(|v| *v == -1.0));
+        }
+    }
+
+    #[test]
+    fn test_pad_cols_zero_pad_is_identity() {
+        let m = DMatrix::from_row_slice(1, 2, &[7.0, 8.0]);
+        assert_eq!(pad_cols(&m, 0, 0.0), m);
+    }
+}
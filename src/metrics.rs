@@ -0,0 +1,271 @@
+use nalgebra::{DMatrix};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/**
+    * Metrics
+    *
+    * Metrics summarise how well a model's predictions match the targets. They
+    * are used for evaluation only and do not need to be differentiable.
+    *
+    * Predictions and targets follow the layout of NeuralNetwork::forward: each
+    * row is one sample and each column one class. The predicted (or true) class
+    * of a sample is the argmax of its row; ties go to the lowest class index.
+    *
+    * Currently, the following metrics are implemented:
+    * 1. accuracy - Fraction of samples whose predicted class is the true one.
+    * 2. confusion_matrix - Counts of (true class, predicted class) pairs.
+    * 3. ConfusionMatrixAccumulator - Builds the confusion matrix batch by batch.
+    * 4. bootstrap_loss_ci - Bootstrap confidence interval of a mean loss.
+    * 5. expected_calibration_error - Gap between confidence and accuracy.
+    *
+**/
+
+
+// Index of the largest entry in each row. Ties are broken towards the
+// lowest index so results are deterministic.
+pub(crate) fn argmax_rows(M: &DMatrix<f64>) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(M.nrows());
+    for i in 0..M.nrows() {
+        let mut best = 0;
+        for j in 1..M.ncols() {
+            if M[(i, j)] > M[(i, best)] {
+                best = j;
+            }
+        }
+        indices.push(best);
+    }
+    return indices;
+}
+
+// Fraction of samples (rows) whose predicted class, the argmax of the row of
+// predictions, equals the true class, the argmax of the row of targets.
+// Targets may be one-hot or class probabilities. Panics on an empty batch,
+// whose accuracy is undefined.
+pub fn accuracy(predictions: &DMatrix<f64>, targets: &DMatrix<f64>) -> f64 {
+    assert_eq!(predictions.shape(), targets.shape(), "Predictions and targets must have the same shape");
+    assert!(predictions.nrows() > 0 && predictions.ncols() > 0, "accuracy of an empty batch is undefined");
+    let predicted = argmax_rows(predictions);
+    let actual = argmax_rows(targets);
+    let correct = predicted.iter().zip(actual.iter()).filter(|(p, t)| p == t).count();
+    return correct as f64 / predictions.nrows() as f64;
+}
+
+// Computes the C x C confusion matrix, where entry (i, j) counts the samples
+// of true class i that were predicted as class j.
+pub fn confusion_matrix(predictions: &DMatrix<f64>, targets: &DMatrix<f64>) -> DMatrix<usize> {
+    let mut accumulator = ConfusionMatrixAccumulator::new(targets.ncols());
+    accumulator.update(predictions, targets);
+    return accumulator.finalize();
+}
+
+// Accumulates a confusion matrix over several batches, so evaluating over a
+// large dataset never needs all the predictions in memory at once.
+pub struct ConfusionMatrixAccumulator {
+    pub num_classes: usize,
+    counts: DMatrix<usize> // Running counts (C x C)
+}
+
+impl ConfusionMatrixAccumulator {
+    pub fn new(num_classes: usize) -> Self {
+        ConfusionMatrixAccumulator {
+            num_classes: num_classes,
+            counts: DMatrix::zeros(num_classes, num_classes)
+        }
+    }
+
+    // Adds the (true class, predicted class) pairs of one batch.
+    pub fn update(&mut self, predictions: &DMatrix<f64>, targets: &DMatrix<f64>) {
+        assert_eq!(predictions.shape(), targets.shape(), "Predictions and targets must have the same shape");
+        assert_eq!(targets.ncols(), self.num_classes,
+                   "Expected {} classes, got {}", self.num_classes, targets.ncols());
+        let predicted = argmax_rows(predictions);
+        let actual = argmax_rows(targets);
+        for (t, p) in actual.iter().zip(predicted.iter()) {
+            self.counts[(*t, *p)] += 1;
+        }
+    }
+
+    // Returns the confusion matrix accumulated so far.
+    pub fn finalize(self) -> DMatrix<usize> {
+        return self.counts;
+    }
+}
+
+// Percentile bootstrap confidence interval for the mean of per-sample (or
+// per-batch) losses. The losses are resampled with replacement n_boot times,
+// and the alpha/2 and 1 - alpha/2 quantiles of the resampled means are
+// returned as (lower, upper). E.g. alpha = 0.05 gives a 95% interval.
+pub fn bootstrap_loss_ci(losses: &[f64], n_boot: usize, alpha: f64, seed: u64) -> (f64, f64) {
+    assert!(!losses.is_empty(), "bootstrap_loss_ci needs at least one loss");
+    assert!(n_boot > 0, "n_boot must be positive");
+    assert!(alpha > 0.0 && alpha < 1.0, "alpha must be in (0, 1), got {}", alpha);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let n = losses.len();
+    let mut means: Vec<f64> = (0..n_boot)
+        .map(|_| (0..n).map(|_| losses[rng.gen_range(0..n)]).sum::<f64>() / n as f64)
+        .collect();
+    means.sort_by(|a, b| a.total_cmp(b));
+
+    let quantile = |q: f64| means[((q * (n_boot - 1) as f64).round() as usize).min(n_boot - 1)];
+    return (quantile(alpha / 2.0), quantile(1.0 - alpha / 2.0));
+}
+
+// Expected calibration error (ECE). The confidence of a prediction is its
+// largest class probability. Predictions are grouped into n_bins equal-width
+// confidence bins over [0, 1], and ECE is the average over bins of
+// |accuracy - mean confidence|, weighted by the fraction of samples in each
+// bin. A calibrated model has ECE ≈ 0, see calibration::fit_temperature.
+pub fn expected_calibration_error(probs: &DMatrix<f64>, targets: &DMatrix<f64>, n_bins: usize) -> f64 {
+    assert_eq!(probs.shape(), targets.shape(), "Predictions and targets must have the same shape");
+    assert!(n_bins > 0, "n_bins must be positive");
+    let predicted = argmax_rows(probs);
+    let actual = argmax_rows(targets);
+
+    let mut counts = vec![0usize; n_bins];
+    let mut correct = vec![0usize; n_bins];
+    let mut confidence_sums = vec![0.0; n_bins];
+    for i in 0..probs.nrows() {
+        let confidence = probs[(i, predicted[i])];
+        let bin = ((confidence * n_bins as f64) as usize).min(n_bins - 1);
+        counts[bin] += 1;
+        confidence_sums[bin] += confidence;
+        if predicted[i] == actual[i] {
+            correct[bin] += 1;
+        }
+    }
+
+    let mut ece = 0.0;
+    for bin in 0..n_bins {
+        if counts[bin] == 0 {
+            continue;
+        }
+        let n = counts[bin] as f64;
+        let gap = (correct[bin] as f64 / n - confidence_sums[bin] / n).abs();
+        ece += n / probs.nrows() as f64 * gap;
+    }
+    return ece;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accuracy() {
+        let predictions = DMatrix::from_row_slice(4, 3, &[0.8, 0.1, 0.1,
+                                                          0.2, 0.7, 0.1,
+                                                          0.3, 0.3, 0.4,
+                                                          0.5, 0.5, 0.0]); // Tie -> class 0
+        let targets = DMatrix::from_row_slice(4, 3, &[1.0, 0.0, 0.0,
+                                                      0.0, 0.0, 1.0,
+                                                      0.0, 0.0, 1.0,
+                                                      1.0, 0.0, 0.0]);
+        assert_eq!(accuracy(&predictions, &targets), 0.75);
+        // The accuracy is the trace of the confusion matrix over the number of samples
+        assert_eq!(confusion_matrix(&predictions, &targets).trace(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "accuracy of an empty batch is undefined")]
+    fn test_accuracy_empty_batch() {
+        let _ = accuracy(&DMatrix::zeros(0, 3), &DMatrix::zeros(0, 3));
+    }
+
+    #[test]
+    fn test_confusion_matrix() {
+        let predictions = DMatrix::from_row_slice(4, 3, &[0.8, 0.1, 0.1,
+                                                          0.2, 0.7, 0.1,
+                                                          0.3, 0.3, 0.4,
+                                                          0.5, 0.5, 0.0]); // Tie -> class 0
+        let targets = DMatrix::from_row_slice(4, 3, &[1.0, 0.0, 0.0,
+                                                      0.0, 0.0, 1.0,
+                                                      0.0, 0.0, 1.0,
+                                                      0.0, 1.0, 0.0]);
+        let cm = confusion_matrix(&predictions, &targets);
+        let expected = DMatrix::from_row_slice(3, 3, &[1, 0, 0,
+                                                       1, 0, 0,
+                                                       0, 1, 1]);
+        assert_eq!(cm, expected);
+    }
+
+    #[test]
+    fn test_accumulator_matches_single_pass() {
+        let predictions = DMatrix::from_row_slice(5, 2, &[0.9, 0.1,
+                                                          0.4, 0.6,
+                                                          0.3, 0.7,
+                                                          0.6, 0.4,
+                                                          0.2, 0.8]);
+        let targets = DMatrix::from_row_slice(5, 2, &[1.0, 0.0,
+                                                      1.0, 0.0,
+                                                      0.0, 1.0,
+                                                      0.0, 1.0,
+                                                      0.0, 1.0]);
+        let mut accumulator = ConfusionMatrixAccumulator::new(2);
+        accumulator.update(&predictions.rows(0, 2).into_owned(), &targets.rows(0, 2).into_owned());
+        accumulator.update(&predictions.rows(2, 3).into_owned(), &targets.rows(2, 3).into_owned());
+        assert_eq!(accumulator.finalize(), confusion_matrix(&predictions, &targets));
+    }
+
+    // Losses drawn from N(2, 1)
+    fn normal_losses(n: usize, seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        return (0..n).map(|_| 2.0 + rng.sample::<f64, _>(rand_distr::StandardNormal)).collect();
+    }
+
+    #[test]
+    fn test_bootstrap_ci_contains_true_mean() {
+        let losses = normal_losses(400, 0);
+        let (lower, upper) = bootstrap_loss_ci(&losses, 2000, 0.05, 1);
+        assert!(lower < upper);
+        assert!(lower < 2.0 && 2.0 < upper, "({}, {})", lower, upper);
+        // The interval is roughly mean ± 1.96 * σ / sqrt(n)
+        assert!((upper - lower - 2.0 * 1.96 / 20.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_bootstrap_ci_narrows_with_more_data() {
+        let (lower_small, upper_small) = bootstrap_loss_ci(&normal_losses(50, 2), 500, 0.05, 3);
+        let (lower_large, upper_large) = bootstrap_loss_ci(&normal_losses(1000, 2), 500, 0.05, 3);
+        assert!(upper_large - lower_large < 0.5 * (upper_small - lower_small));
+    }
+
+    #[test]
+    fn test_bootstrap_ci_stabilizes_with_more_resamples() {
+        // With more resamples the interval estimate depends less on the seed
+        let losses = normal_losses(100, 4);
+        let spread = |n_boot: usize| {
+            let (a, _) = bootstrap_loss_ci(&losses, n_boot, 0.1, 5);
+            let (b, _) = bootstrap_loss_ci(&losses, n_boot, 0.1, 6);
+            (a - b).abs()
+        };
+        assert!(spread(5000) < spread(20).max(1e-3));
+    }
+
+    // n binary predictions with the given confidence in class 0, of which
+    // num_correct are right
+    fn predictions_with_confidence(n: usize, confidence: f64, num_correct: usize) -> (DMatrix<f64>, DMatrix<f64>) {
+        let probs = DMatrix::from_fn(n, 2, |_, j| if j == 0 { confidence } else { 1.0 - confidence });
+        let targets = DMatrix::from_fn(n, 2, |i, j| if (i < num_correct) == (j == 0) { 1.0 } else { 0.0 });
+        return (probs, targets);
+    }
+
+    #[test]
+    fn test_ece_calibrated() {
+        // 80% confident and right 8 out of 10 times, 60% confident and right 3 out of 5 times
+        let (p1, t1) = predictions_with_confidence(10, 0.8, 8);
+        let (p2, t2) = predictions_with_confidence(5, 0.6, 3);
+        let probs = DMatrix::from_fn(15, 2, |i, j| if i < 10 { p1[(i, j)] } else { p2[(i - 10, j)] });
+        let targets = DMatrix::from_fn(15, 2, |i, j| if i < 10 { t1[(i, j)] } else { t2[(i - 10, j)] });
+        assert!(expected_calibration_error(&probs, &targets, 10) < 1e-12);
+    }
+
+    #[test]
+    fn test_ece_overconfident() {
+        // 90% confident but right only half of the time
+        let (probs, targets) = predictions_with_confidence(20, 0.9, 10);
+        let ece = expected_calibration_error(&probs, &targets, 10);
+        assert!((ece - 0.4).abs() < 1e-12, "{}", ece);
+    }
+}
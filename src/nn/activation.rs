@@ -1,5 +1,17 @@
 use nalgebra::{DMatrix};
-use std::f64::consts;
+// Float math (exp, powf, round, ...) comes from libm without std
+#[cfg(not(feature = "std"))]
+use nalgebra::ComplexField;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f64::consts;
+use crate::error::DnnError;
+use crate::nn::layers::{GradientReversal, LogSumExpPool};
+use crate::scalar::{from_f64, to_f64, Real};
 
 /**
     * Activation Functions
@@ -23,111 +35,884 @@ use std::f64::consts;
     * 1. Identity - f(x) = x
     * 2. ReLU - f(x) = max(0, x)
     * 3. Sigmoid f(z) = 1/(1 + e^-z)
+    * 4. Tanh - f(z) = (e^z - e^-z)/(e^z + e^-z)
+    * 5. Softmax - f(z)_i = e^z_i / Σ_j e^z_j, normalized along rows (default) or columns
+    * 6. ScaledTanh - f(z) = lower + (upper - lower) * (tanh(z) + 1) / 2, an output
+    *                 activation for regression targets known to lie in [lower, upper]
+    * 7. Lambda - f(z) given by a user supplied closure, together with its derivative
+    * 8. LeakyReLU - f(z) = z if z > 0, slope * z otherwise, so negative units keep a gradient
+    * 9. SwishBeta - f(z) = z * sigmoid(β z) with a learnable β; β = 1 is SiLU
+    * 10. ELU - f(z) = z if z > 0, α (e^z - 1) otherwise, which is smooth for α = 1
+    *           and pushes mean activations towards zero
+    * 11. GELU - f(z) = z Φ(z), using the tanh approximation
+    *            0.5 z (1 + tanh(sqrt(2/π) (z + 0.044715 z^3)))
     * 
+    * All of them implement the ActivationFunction trait, so they can be stored
+    * as Box<dyn ActivationFunction> and constructed by name with
+    * activation_from_name. Activations must be Clone, so that boxed
+    * activations (and whole models) can be cloned, and Send + Sync, so that
+    * a model can be shared between threads.
+    *
+    * Identity, ReLU, LeakyReLU, Sigmoid, Tanh, Softmax, ELU and GELU are
+    * generic over the scalar type (see scalar), e.g. ReLU<f32>; the others
+    * are f64 only.
+    *
+    * Parameterized activations such as SwishBeta expose their learnable
+    * parameters and their gradients (parameters, gradients and their _mut
+    * variants), like a Layer, and store the gradients in backward_params,
+    * which NeuralNetwork calls during backpropagation. Like the weights of a
+    * Linear layer, each parameter can be frozen (requires_grad).
     *
 **/
 
 
+pub trait ActivationFunction<T: Real = f64>: ActivationClone<T> + Send + Sync {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T>;
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T>;
+    // Name identifying the activation. Activations without constructor
+    // arguments accept this name in activation_from_name.
+    fn name(&self) -> &str;
+    // Whether an output value a lies in a flat region of the activation,
+    // where the local gradient is close to zero. Only bounded activations
+    // saturate, so the default is false.
+    fn is_saturated(&self, _a : T) -> bool {
+        return false;
+    }
+    // Switches between training and inference behaviour. Only stochastic
+    // activations such as Dropout behave differently, so the default does
+    // nothing.
+    fn set_training(&mut self, _training : bool) {}
+    // Inference-only forward pass, with nothing cached for backward. It
+    // returns the same values as forward in inference mode (stochastic
+    // activations such as Dropout are deterministic here). The default runs
+    // forward on a copy, which costs a clone of the cache; cheap element-wise
+    // activations compute the output directly.
+    fn predict(&self, Z : &DMatrix<T>) -> DMatrix<T> {
+        let mut copy = self.clone_box();
+        copy.set_training(false);
+        return copy.forward(Z);
+    }
+    // Forward-mode derivative: the Jacobian-vector product (∂A/∂Z) V at the
+    // input of the last forward pass, for a tangent V shaped like Z. backward
+    // computes the transposed product (∂A/∂Z)^T dLdA, and the Jacobian of
+    // element-wise activations (diagonal) and of softmax is symmetric, so the
+    // default reuses backward.
+    fn jvp(&self, V : &DMatrix<T>) -> DMatrix<T> {
+        return self.backward(V);
+    }
+    // Upper bound on the Lipschitz constant of the activation, i.e. on
+    // ‖f(z1) - f(z2)‖ / ‖z1 - z2‖. Infinite unless an activation knows a
+    // finite bound, so that bounds built on it stay safe.
+    fn lipschitz_constant(&self) -> f64 {
+        return f64::INFINITY;
+    }
+    // Number of output features for an input with input_size features, or
+    // None if the activation cannot take that many. Most activations keep
+    // the number of features and take any.
+    fn output_size(&self, input_size : usize) -> Option<usize> {
+        return Some(input_size);
+    }
+    // Backward pass that also stores the gradients of the activation's own
+    // parameters. Activations without parameters keep the default, which
+    // is backward.
+    fn backward_params(&mut self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return self.backward(dLdA);
+    }
+    // Learnable parameters and their gradients from the last backward_params,
+    // in matching order. Empty for activations without parameters.
+    fn parameters(&self) -> Vec<&DMatrix<T>> {
+        return Vec::new();
+    }
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return Vec::new();
+    }
+    fn gradients(&self) -> Vec<&DMatrix<T>> {
+        return Vec::new();
+    }
+    fn gradients_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return Vec::new();
+    }
+    // Whether optimizers should update each parameter, in the order of
+    // parameters, and a setter to freeze (false) or unfreeze the parameter
+    // at index. Activations without parameters have no flags to set.
+    fn requires_grad(&self) -> Vec<bool> {
+        return vec![true; self.parameters().len()];
+    }
+    fn set_requires_grad(&mut self, _index : usize, _requires : bool) {}
+    // State that is not learned by gradient descent but is part of a trained
+    // model, e.g. the running statistics of BatchNorm. NeuralNetwork's state
+    // dict holds it next to the parameters. Empty for most activations.
+    fn buffers(&self) -> Vec<&DMatrix<T>> {
+        return Vec::new();
+    }
+    fn buffers_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return Vec::new();
+    }
+    // Whether this is a softmax normalizing each row (sample), the only
+    // activation a loss fused with softmax may skip
+    fn is_row_softmax(&self) -> bool {
+        return false;
+    }
+    // Whether the activation acts on every entry on its own, A_ij = f(Z_ij),
+    // so that a unit can be duplicated without changing the others (see
+    // NeuralNetwork::widen_layer). True unless the activation mixes entries,
+    // like softmax.
+    fn is_elementwise(&self) -> bool {
+        return true;
+    }
+    // Constructor arguments and learned values which, together with the
+    // name, rebuild the activation with activation_from_config, e.g. when a
+    // model is loaded from a file. Empty for activations without any.
+    fn config(&self) -> Vec<f64> {
+        return Vec::new();
+    }
+}
+
+// Lets a boxed activation be cloned. It is implemented for every activation
+// that is Clone, so activations only need to derive Clone.
+pub trait ActivationClone<T: Real = f64> {
+    fn clone_box(&self) -> Box<dyn ActivationFunction<T>>;
+}
+
+impl<T: Real, A: ActivationFunction<T> + Clone + 'static> ActivationClone<T> for A {
+    fn clone_box(&self) -> Box<dyn ActivationFunction<T>> {
+        return Box::new(self.clone());
+    }
+}
+
+impl<T: Real> Clone for Box<dyn ActivationFunction<T>> {
+    fn clone(&self) -> Self {
+        return self.clone_box();
+    }
+}
+
+// Builds an activation function from its name, e.g. from a configuration
+// file. Names are case-insensitive: "identity", "relu", "sigmoid", "tanh",
+// "softmax" (row-wise), "gelu" and "bf16" (see precision::Bf16Cast) are
+// supported.
+pub fn activation_from_name(name : &str) -> Result<Box<dyn ActivationFunction>, DnnError> {
+    return match name.to_ascii_lowercase().as_str() {
+        "identity" => Ok(Box::new(Identity::new())),
+        "relu" => Ok(Box::new(ReLU::new())),
+        "sigmoid" => Ok(Box::new(Sigmoid::new())),
+        "tanh" => Ok(Box::new(Tanh::new())),
+        "softmax" => Ok(Box::new(Softmax::new())),
+        "gelu" => Ok(Box::new(GELU::new())),
+        #[cfg(feature = "std")]
+        "bf16" => Ok(Box::new(crate::precision::Bf16Cast::new())),
+        _ => Err(DnnError::UnknownActivation(name.to_string()))
+    };
+}
+
+// Builds an activation function from its name and the values returned by
+// its config method. Activations without a config are built by name (see
+// activation_from_name); "leaky_relu" (slope), "swish_beta" (β), "softmax"
+// (0 for Axis::Row, 1 for Axis::Col), "scaled_tanh" (lower, upper), "elu"
+// (α), "gradient_reversal" (λ), "logsumexp_pool" (temperature), "dropout"
+// (p) and "batchnorm" (see BatchNorm::from_config) take their arguments from
+// config.
+pub fn activation_from_config(name : &str, config : &[f64]) -> Result<Box<dyn ActivationFunction>, DnnError> {
+    if config.is_empty() {
+        return activation_from_name(name);
+    }
+    let key = name.to_ascii_lowercase();
+    #[cfg(feature = "std")]
+    if key == "batchnorm" {
+        return Ok(Box::new(crate::nn::batchnorm::BatchNorm::from_config(config)?));
+    }
+    let expected = match key.as_str() {
+        "leaky_relu" | "swish_beta" | "softmax" | "elu" | "gradient_reversal" | "logsumexp_pool" => 1,
+        #[cfg(feature = "std")]
+        "dropout" => 1,
+        "scaled_tanh" => 2,
+        _ => return Err(DnnError::UnknownActivation(name.to_string()))
+    };
+    if config.len() != expected {
+        return Err(DnnError::InvalidFormat(format!("{} expects {} config values, got {}", name, expected, config.len())));
+    }
+    return match key.as_str() {
+        "leaky_relu" => Ok(Box::new(LeakyReLU::new(config[0]))),
+        "swish_beta" => Ok(Box::new(SwishBeta::new(config[0]))),
+        "softmax" if config[0] == 0.0 => Ok(Box::new(Softmax::with_axis(Axis::Row))),
+        "softmax" if config[0] == 1.0 => Ok(Box::new(Softmax::with_axis(Axis::Col))),
+        "softmax" => Err(DnnError::InvalidFormat(format!("invalid softmax axis {}", config[0]))),
+        "scaled_tanh" => Ok(Box::new(ScaledTanh::new(config[0], config[1]))),
+        "gradient_reversal" => Ok(Box::new(GradientReversal::new(config[0]))),
+        "logsumexp_pool" if config[0] > 0.0 => Ok(Box::new(LogSumExpPool::new(config[0]))),
+        "logsumexp_pool" => Err(DnnError::InvalidFormat(format!("invalid logsumexp_pool temperature {}", config[0]))),
+        #[cfg(feature = "std")]
+        "dropout" if (0.0..1.0).contains(&config[0]) => Ok(Box::new(crate::nn::dropout::Dropout::new(config[0]))),
+        #[cfg(feature = "std")]
+        "dropout" => Err(DnnError::InvalidFormat(format!("invalid dropout probability {}", config[0]))),
+        _ => Ok(Box::new(ELU::new(config[0])))
+    };
+}
+
+
 // Identity Activation Function
-pub struct Identity {
-    A : DMatrix<f64>
+#[derive(Clone)]
+pub struct Identity<T: Real = f64> {
+    A : DMatrix<T>
 }
 
-impl Identity {
+impl<T: Real> Identity<T> {
     pub fn new() -> Self {
         Identity {
             A : DMatrix::zeros(0, 0)
         }
     }
 
-    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
         self.A = Z.clone(); // Identity(Z) = Z
         return self.A.clone();
     }
 
-    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
         let dLdZ = dLdA; // Derivative of Identity is 1, so dLdZ = dLdA * 1 = dLdA
         return dLdZ.clone();
     }
 }
 
 // ReLU Activation Function
-pub struct ReLU {
-    A : DMatrix<f64>
+#[derive(Clone)]
+pub struct ReLU<T: Real = f64> {
+    A : DMatrix<T>
 }
 
-impl ReLU {
+impl<T: Real> ReLU<T> {
     pub fn new() -> Self {
         ReLU {
             A : DMatrix::zeros(0, 0)
         }
     }
 
-    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
-        self.A = Z.map(|x| x.max(0.0)); // ReLU(Z) = max(0, Z)
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        self.A = Z.map(|x| x.max(T::zero())); // ReLU(Z) = max(0, Z)
         return self.A.clone();
     }
 
-    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
         // Assert that forward pass is  called before backward pass
         // to ensure that self.A is set to the correct value.
         assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
 
         // Derivative of ReLU is 1 if x > 0, 0 otherwise
-        let dAdZ = self.A.map(|x| if x > 0.0 { 1.0 } else { 0.0 });
+        let dAdZ = self.A.map(|x| if x > T::zero() { T::one() } else { T::zero() });
         return dLdA.component_mul(&dAdZ); // dLdZ = dLdA * dA/dZ
     }
 }
 
+// Leaky ReLU Activation Function
+#[derive(Clone)]
+pub struct LeakyReLU<T: Real = f64> {
+    pub slope : T, // Slope for negative inputs
+    Z : DMatrix<T> // Input of the last forward pass. A alone cannot tell the regions apart for slope = 0.
+}
+
+impl<T: Real> LeakyReLU<T> {
+    pub fn new(slope : T) -> Self {
+        LeakyReLU {
+            slope : slope,
+            Z : DMatrix::zeros(0, 0)
+        }
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        self.Z = Z.clone();
+        return Z.map(|x| if x > T::zero() { x } else { self.slope * x });
+    }
+
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        assert!(!self.Z.is_empty(), "Forward pass not called before backward pass");
+        // Derivative of LeakyReLU is 1 if z > 0, slope otherwise
+        let dAdZ = self.Z.map(|z| if z > T::zero() { T::one() } else { self.slope });
+        return dLdA.component_mul(&dAdZ);
+    }
+}
+
+// The common default slope of 0.01
+impl<T: Real> Default for LeakyReLU<T> {
+    fn default() -> Self {
+        return LeakyReLU::new(from_f64(0.01));
+    }
+}
+
+// Swish-β Activation Function, A = Z ⊙ sigmoid(β Z), with a learnable scalar
+// β shared by all units. β is kept as a 1 x 1 matrix so it can be updated as
+// a parameter like any weight.
+#[derive(Clone)]
+pub struct SwishBeta {
+    pub beta : DMatrix<f64>, // β (1 x 1)
+    pub dLdbeta : DMatrix<f64>, // Gradient of the loss with respect to β (1 x 1)
+    pub requires_grad : bool, // Whether optimizers update β
+    Z : DMatrix<f64>, // Input of the last forward pass
+    S : DMatrix<f64> // sigmoid(β Z) of the last forward pass
+}
+
+impl SwishBeta {
+    pub fn new(beta : f64) -> Self {
+        SwishBeta {
+            beta : DMatrix::from_element(1, 1, beta),
+            dLdbeta : DMatrix::zeros(1, 1),
+            requires_grad : true,
+            Z : DMatrix::zeros(0, 0),
+            S : DMatrix::zeros(0, 0)
+        }
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        let beta = self.beta[(0, 0)];
+        self.Z = Z.clone();
+        self.S = Z.map(|z| 1.0 / (1.0 + (-beta * z).exp()));
+        return Z.component_mul(&self.S);
+    }
+
+    // dA/dZ = s + β z s (1 - s), with s = sigmoid(β z)
+    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.Z.is_empty(), "Forward pass not called before backward pass");
+        let beta = self.beta[(0, 0)];
+        let dAdZ = self.Z.zip_map(&self.S, |z, s| s + beta * z * s * (1.0 - s));
+        return dLdA.component_mul(&dAdZ);
+    }
+
+    // Also stores ∂L/∂β = Σ dLdA ⊙ z^2 s (1 - s)
+    pub fn backward_params(&mut self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        let dAdbeta = self.Z.zip_map(&self.S, |z, s| z * z * s * (1.0 - s));
+        self.dLdbeta = DMatrix::from_element(1, 1, dLdA.dot(&dAdbeta));
+        return self.backward(dLdA);
+    }
+}
+
 // Sigmoid Activation Function
-pub struct Sigmoid {
-    A : DMatrix<f64>
+#[derive(Clone)]
+pub struct Sigmoid<T: Real = f64> {
+    A : DMatrix<T>
 }
 
-impl Sigmoid {
+impl<T: Real> Sigmoid<T> {
     pub fn new() -> Self {
         Sigmoid{
             A : DMatrix::zeros(0, 0)
         }
     }
-    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64>{
-        self.A = Z.map(|x| 1.0/(1.0 + consts::E.powf(-x)));
-        self.A = self.A.map(|a| ((a * 1e5).round())/ 1e5);
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T>{
+        let e : T = from_f64(consts::E);
+        let (scale, one) : (T, T) = (from_f64(1e5), T::one());
+        self.A = Z.map(|x| one/(one + e.powf(-x)));
+        self.A = self.A.map(|a| ((a * scale).round())/ scale);
         return self.A.clone();
     }
-    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64>{
-        let dAdZ = self.A.map(|x| x * (1.0 - x));
-        return (dLdA.component_mul(&dAdZ)).map(|a| ((a * 1e4).round())/ 1e4);
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T>{
+        let (scale, one) : (T, T) = (from_f64(1e4), T::one());
+        let dAdZ = self.A.map(|x| x * (one - x));
+        return (dLdA.component_mul(&dAdZ)).map(|a| ((a * scale).round())/ scale);
     }
 
 }
 
-pub struct Tanh {
-    A : DMatrix<f64>
+#[derive(Clone)]
+pub struct Tanh<T: Real = f64> {
+    A : DMatrix<T>
 }
-impl Tanh {
+impl<T: Real> Tanh<T> {
     pub fn new() -> Self {
         Tanh {
             A : DMatrix::zeros(0, 0)
         }
     }
     // \tanh(x) = \frac{e^z - e^{-z}}{e^{z} + e^{-z}}
-    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64>{
-        self.A = Z.map(|z| (consts::E.powf(z) - consts::E.powf(-z))/
-                            (consts::E.powf(z) + consts::E.powf(-z)));
-        self.A = self.A.map(|a| ((a * 1e4).round())/ 1e4);
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T>{
+        let e : T = from_f64(consts::E);
+        let scale : T = from_f64(1e4);
+        self.A = Z.map(|z| (e.powf(z) - e.powf(-z))/
+                            (e.powf(z) + e.powf(-z)));
+        self.A = self.A.map(|a| ((a * scale).round())/ scale);
         return self.A.clone();
     }
     // d/dx tanh(x) -> 1 - tanh(x)^2
-    pub fn backward(&mut self, dLdA : &DMatrix<f64>) -> DMatrix<f64>{
-        let dAdZ = self.A.map(|x| 1.0 - x*x);
-        return (dLdA.component_mul(&dAdZ)).map(|a| ((a * 1e4).round())/ 1e4);
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T>{
+        let scale : T = from_f64(1e4);
+        let dAdZ = self.A.map(|x| T::one() - x*x);
+        return (dLdA.component_mul(&dAdZ)).map(|a| ((a * scale).round())/ scale);
+    }
+}
+
+// The dimension along which Softmax normalizes. Samples are rows in this
+// library, so Axis::Row (each row sums to 1) is the default; Axis::Col is
+// for data laid out with samples as columns.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    Row,
+    Col
+}
+
+// Softmax Activation Function
+#[derive(Clone)]
+pub struct Softmax<T: Real = f64> {
+    A : DMatrix<T>,
+    pub axis : Axis
+}
+
+impl<T: Real> Softmax<T> {
+    pub fn new() -> Self {
+        Softmax::with_axis(Axis::Row)
+    }
+
+    pub fn with_axis(axis : Axis) -> Self {
+        Softmax {
+            A : DMatrix::zeros(0, 0),
+            axis : axis
+        }
+    }
+
+    // Row-wise softmax. The row max is subtracted before exponentiating, which
+    // leaves the result unchanged but keeps e^z from overflowing.
+    fn softmax_rows(Z : &DMatrix<T>) -> DMatrix<T> {
+        let mut A = Z.clone();
+        for mut row in A.row_iter_mut() {
+            let max = row.max();
+            row.apply(|z| *z = (*z - max).exp());
+            let sum = row.sum();
+            row /= sum;
+        }
+        return A;
+    }
+
+    // For one row a with upstream gradient g, the softmax Jacobian is
+    // diag(a) - a a^T, so dLdZ = a ⊙ (g - (g · a)).
+    fn backward_rows(A : &DMatrix<T>, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        let mut dLdZ = DMatrix::zeros(A.nrows(), A.ncols());
+        for i in 0..A.nrows() {
+            let a = A.row(i);
+            let g = dLdA.row(i);
+            let dot = g.dot(&a);
+            dLdZ.set_row(i, &(g.add_scalar(-dot)).component_mul(&a));
+        }
+        return dLdZ;
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        self.A = match self.axis {
+            Axis::Row => Softmax::softmax_rows(Z),
+            Axis::Col => Softmax::softmax_rows(&Z.transpose()).transpose()
+        };
+        return self.A.clone();
+    }
+
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        return match self.axis {
+            Axis::Row => Softmax::backward_rows(&self.A, dLdA),
+            Axis::Col => Softmax::backward_rows(&self.A.transpose(), &dLdA.transpose()).transpose()
+        };
+    }
+}
+
+// Scaled Tanh Activation Function
+#[derive(Clone)]
+pub struct ScaledTanh {
+    T : DMatrix<f64>, // tanh(Z), cached for backward
+    pub lower : f64,
+    pub upper : f64
+}
+
+impl ScaledTanh {
+    pub fn new(lower : f64, upper : f64) -> Self {
+        assert!(lower < upper, "ScaledTanh needs lower < upper, got [{}, {}]", lower, upper);
+        ScaledTanh {
+            T : DMatrix::zeros(0, 0),
+            lower : lower,
+            upper : upper
+        }
+    }
+
+    // Maps tanh's range (-1, 1) onto (lower, upper)
+    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        self.T = Z.map(|z| z.tanh());
+        let half_range = (self.upper - self.lower) / 2.0;
+        return self.T.map(|t| self.lower + half_range * (t + 1.0));
+    }
+
+    // dA/dZ = (upper - lower) / 2 * (1 - tanh(Z)^2)
+    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.T.is_empty(), "Forward pass not called before backward pass");
+        let half_range = (self.upper - self.lower) / 2.0;
+        let dAdZ = self.T.map(|t| half_range * (1.0 - t * t));
+        return dLdA.component_mul(&dAdZ);
+    }
+}
+
+// Lambda Activation Function, built from a closure f and its derivative f'.
+// Useful for prototyping an activation before giving it its own type.
+#[derive(Clone)]
+pub struct Lambda {
+    Z : DMatrix<f64>, // Input, cached to evaluate f' in backward
+    forward_fn : Arc<dyn Fn(f64) -> f64 + Send + Sync>,
+    grad_fn : Arc<dyn Fn(f64) -> f64 + Send + Sync>
+}
+
+impl Lambda {
+    pub fn new<F, G>(forward_fn : F, grad_fn : G) -> Self
+    where
+        F: Fn(f64) -> f64 + Send + Sync + 'static,
+        G: Fn(f64) -> f64 + Send + Sync + 'static
+    {
+        Lambda {
+            Z : DMatrix::zeros(0, 0),
+            forward_fn : Arc::new(forward_fn),
+            grad_fn : Arc::new(grad_fn)
+        }
+    }
+
+    // A = f(Z), element-wise
+    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        self.Z = Z.clone();
+        return Z.map(|z| (self.forward_fn)(z));
+    }
+
+    // dA/dZ = f'(Z), element-wise
+    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.Z.is_empty(), "Forward pass not called before backward pass");
+        let dAdZ = self.Z.map(|z| (self.grad_fn)(z));
+        return dLdA.component_mul(&dAdZ);
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for Identity<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Identity::forward(self, Z);
+    }
+
+    fn predict(&self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Z.clone();
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return Identity::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "identity";
+    }
+
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for ReLU<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return ReLU::forward(self, Z);
+    }
+
+    fn predict(&self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Z.map(|x| x.max(T::zero()));
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return ReLU::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "relu";
+    }
+
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for LeakyReLU<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return LeakyReLU::forward(self, Z);
+    }
+
+    fn predict(&self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Z.map(|x| if x > T::zero() { x } else { self.slope * x });
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return LeakyReLU::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "leaky_relu";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![to_f64(self.slope)];
+    }
+
+    fn lipschitz_constant(&self) -> f64 {
+        return to_f64(self.slope.abs()).max(1.0);
+    }
+}
+
+impl ActivationFunction for SwishBeta {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return SwishBeta::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return SwishBeta::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "swish_beta";
+    }
+
+    fn backward_params(&mut self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return SwishBeta::backward_params(self, dLdA);
+    }
+
+    fn parameters(&self) -> Vec<&DMatrix<f64>> {
+        return vec![&self.beta];
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return vec![&mut self.beta];
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<f64>> {
+        return vec![&self.dLdbeta];
+    }
+
+    fn gradients_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return vec![&mut self.dLdbeta];
+    }
+
+    fn requires_grad(&self) -> Vec<bool> {
+        return vec![self.requires_grad];
+    }
+
+    fn set_requires_grad(&mut self, _index : usize, requires : bool) {
+        self.requires_grad = requires;
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![self.beta[(0, 0)]];
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for Sigmoid<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Sigmoid::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return Sigmoid::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "sigmoid";
+    }
+
+    // The derivative s (1 - s) peaks at z = 0
+    fn lipschitz_constant(&self) -> f64 {
+        return 0.25;
+    }
+
+    // σ'(z) = a(1 - a) < 0.01 once a is within 0.01 of 0 or 1
+    fn is_saturated(&self, a : T) -> bool {
+        return !(from_f64::<T>(0.01)..=from_f64(0.99)).contains(&a);
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for Tanh<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Tanh::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return Tanh::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "tanh";
+    }
+
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+
+    // tanh'(z) = 1 - a^2 < 0.02 once |a| > 0.99
+    fn is_saturated(&self, a : T) -> bool {
+        return a.abs() > from_f64(0.99);
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for Softmax<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return Softmax::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return Softmax::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "softmax";
+    }
+
+    fn is_row_softmax(&self) -> bool {
+        return self.axis == Axis::Row;
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![if self.axis == Axis::Row { 0.0 } else { 1.0 }];
+    }
+
+    fn is_elementwise(&self) -> bool {
+        return false;
+    }
+
+    // The spectral norm of diag(a) - a a^T is at most max_i a_i <= 1
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+}
+
+impl ActivationFunction for ScaledTanh {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return ScaledTanh::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return ScaledTanh::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "scaled_tanh";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![self.lower, self.upper];
+    }
+
+    fn lipschitz_constant(&self) -> f64 {
+        return (self.upper - self.lower).abs() / 2.0;
+    }
+}
+
+// ELU Activation Function
+#[derive(Clone)]
+pub struct ELU<T: Real = f64> {
+    pub alpha : T, // Scale of the negative region, which saturates at -α
+    Z : DMatrix<T> // Input of the last forward pass
+}
+
+impl<T: Real> ELU<T> {
+    pub fn new(alpha : T) -> Self {
+        ELU {
+            alpha : alpha,
+            Z : DMatrix::zeros(0, 0)
+        }
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        self.Z = Z.clone();
+        return Z.map(|z| if z > T::zero() { z } else { self.alpha * (z.exp() - T::one()) });
+    }
+
+    // Derivative of ELU is 1 if z > 0, α e^z = A + α otherwise
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        assert!(!self.Z.is_empty(), "Forward pass not called before backward pass");
+        let dAdZ = self.Z.map(|z| if z > T::zero() { T::one() } else { self.alpha * z.exp() });
+        return dLdA.component_mul(&dAdZ);
+    }
+}
+
+// GELU Activation Function (tanh approximation)
+#[derive(Clone)]
+pub struct GELU<T: Real = f64> {
+    Z : DMatrix<T> // Input of the last forward pass
+}
+
+// sqrt(2/π)
+const GELU_K : f64 = 0.7978845608028654;
+const GELU_C : f64 = 0.044715;
+
+impl<T: Real> GELU<T> {
+    pub fn new() -> Self {
+        GELU {
+            Z : DMatrix::zeros(0, 0)
+        }
+    }
+
+    // A = 0.5 z (1 + tanh(u)), u = sqrt(2/π) (z + 0.044715 z^3)
+    pub fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        self.Z = Z.clone();
+        let (k, c, half, one) : (T, T, T, T) = (from_f64(GELU_K), from_f64(GELU_C), from_f64(0.5), T::one());
+        return Z.map(|z| half * z * (one + (k * (z + c * z * z * z)).tanh()));
+    }
+
+    // dA/dZ = 0.5 (1 + tanh(u)) + 0.5 z (1 - tanh(u)^2) sqrt(2/π) (1 + 3 * 0.044715 z^2)
+    pub fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        assert!(!self.Z.is_empty(), "Forward pass not called before backward pass");
+        let (k, c, half, one, three) : (T, T, T, T, T) = (from_f64(GELU_K), from_f64(GELU_C), from_f64(0.5), T::one(), from_f64(3.0));
+        let dAdZ = self.Z.map(|z| {
+            let t = (k * (z + c * z * z * z)).tanh();
+            half * (one + t) + half * z * (one - t * t) * k * (one + three * c * z * z)
+        });
+        return dLdA.component_mul(&dAdZ);
+    }
+}
+
+impl ActivationFunction for Lambda {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return Lambda::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return Lambda::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "lambda";
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for ELU<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return ELU::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return ELU::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "elu";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![to_f64(self.alpha)];
+    }
+
+    // The slope is 1 for z > 0 and at most α below
+    fn lipschitz_constant(&self) -> f64 {
+        return to_f64(self.alpha.abs()).max(1.0);
+    }
+}
+
+impl<T: Real> ActivationFunction<T> for GELU<T> {
+    fn forward(&mut self, Z : &DMatrix<T>) -> DMatrix<T> {
+        return GELU::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<T>) -> DMatrix<T> {
+        return GELU::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "gelu";
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::debug::check_layer_gradient;
     use approx::assert_abs_diff_eq;
 
     #[test]
@@ -172,6 +957,65 @@ mod tests {
                                                        0.0, 0.0, 6.0]);
         assert_abs_diff_eq!(dLdZ, expected, epsilon = 1e-12);
     }
+    #[test]
+    fn test_leaky_relu_forward_backward() {
+        let mut leaky = LeakyReLU::new(0.1);
+        let Z = DMatrix::from_row_slice(2, 3, &[2.0, -1.0, 0.0,
+                                                -3.5, 0.5, -0.2]);
+        let A = leaky.forward(&Z);
+        let expected = DMatrix::from_row_slice(2, 3, &[2.0, -0.1, 0.0,
+                                                       -0.35, 0.5, -0.02]);
+        assert_abs_diff_eq!(A, expected, epsilon = 1e-12);
+
+        let dLdA = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                   4.0, 5.0, 6.0]);
+        let dLdZ = leaky.backward(&dLdA);
+        let expected = DMatrix::from_row_slice(2, 3, &[1.0, 0.2, 0.3,
+                                                       0.4, 5.0, 0.6]);
+        assert_abs_diff_eq!(dLdZ, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_leaky_relu_default_slope() {
+        let mut leaky = LeakyReLU::default();
+        assert_eq!(leaky.slope, 0.01);
+        let A = leaky.forward(&DMatrix::from_row_slice(1, 2, &[-100.0, 100.0]));
+        assert_abs_diff_eq!(A, DMatrix::from_row_slice(1, 2, &[-1.0, 100.0]), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_swish_beta_one_is_silu() {
+        let mut swish = SwishBeta::new(1.0);
+        let Z = DMatrix::from_row_slice(1, 4, &[-2.0, -0.5, 0.0, 3.0]);
+        let A = swish.forward(&Z);
+        let silu = Z.map(|z| z / (1.0 + (-z).exp()));
+        assert_abs_diff_eq!(A, silu, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_swish_beta_gradients_match_finite_differences() {
+        let Z = DMatrix::from_row_slice(2, 3, &[-2.0, -0.5, 0.3,
+                                                1.0, 2.5, -1.2]);
+        let G = DMatrix::from_row_slice(2, 3, &[0.5, -1.0, 2.0,
+                                                1.5, -0.3, 0.7]); // Upstream ∂L/∂A, with L = Σ G ⊙ A
+        let beta = 0.8;
+        let loss = |beta : f64, Z : &DMatrix<f64>| SwishBeta::new(beta).forward(Z).dot(&G);
+        let mut swish = SwishBeta::new(beta);
+        let _ = swish.forward(&Z);
+        let dLdZ = swish.backward_params(&G);
+
+        let h = 1e-6;
+        let numeric = (loss(beta + h, &Z) - loss(beta - h, &Z)) / (2.0 * h);
+        assert_abs_diff_eq!(swish.gradients()[0][(0, 0)], numeric, epsilon = 1e-7);
+        for k in 0..Z.len() {
+            let mut plus = Z.clone();
+            plus[k] += h;
+            let mut minus = Z.clone();
+            minus[k] -= h;
+            assert_abs_diff_eq!(dLdZ[k], (loss(beta, &plus) - loss(beta, &minus)) / (2.0 * h), epsilon = 1e-7);
+        }
+    }
+
     #[test]
     fn test_sigmoid_forward(){
         let mut sigmoid = Sigmoid::new();
@@ -206,6 +1050,26 @@ mod tests {
       
         assert_abs_diff_eq!(dLdZ, expected, epsilon = 1e-4);  
     }
+    #[test]
+    fn test_activations_through_trait_objects() {
+        // A layer that holds its activation as a trait object
+        struct Activated {
+            activation: Box<dyn ActivationFunction>
+        }
+        let Z = DMatrix::from_row_slice(1, 3, &[-1.0, 0.0, 2.0]);
+        let dLdA = DMatrix::from_row_slice(1, 3, &[1.0, 1.0, 1.0]);
+        let boxed: Vec<Box<dyn ActivationFunction>> = vec![Box::new(Identity::new()), Box::new(ReLU::new()), Box::new(Sigmoid::new())];
+        let expected_A = [[-1.0, 0.0, 2.0], [0.0, 0.0, 2.0], [0.2689, 0.5, 0.8808]];
+        let expected_dLdZ = [[1.0, 1.0, 1.0], [0.0, 0.0, 1.0], [0.1966, 0.25, 0.105]];
+        for (k, activation) in boxed.into_iter().enumerate() {
+            let mut layer = Activated { activation: activation };
+            let A = layer.activation.forward(&Z);
+            let dLdZ = layer.activation.backward(&dLdA);
+            assert_abs_diff_eq!(A, DMatrix::from_row_slice(1, 3, &expected_A[k]), epsilon = 1e-4);
+            assert_abs_diff_eq!(dLdZ, DMatrix::from_row_slice(1, 3, &expected_dLdZ[k]), epsilon = 1e-4);
+        }
+    }
+
     #[test]
     fn test_tanh_forward(){
         let mut tanh = Tanh::new();
@@ -240,4 +1104,161 @@ mod tests {
     assert_abs_diff_eq!(dLdZ, expected, epsilon = 1e-3);        
     }
 
+    #[test]
+    fn test_softmax_rows_sum_to_one() {
+        let mut softmax = Softmax::new();
+        let Z = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                -1.0, 0.0, 1.0]);
+        let A = softmax.forward(&Z);
+        let expected = DMatrix::from_row_slice(2, 3, &[0.0900, 0.2447, 0.6652,
+                                                       0.0900, 0.2447, 0.6652]);
+        assert_abs_diff_eq!(A, expected, epsilon = 1e-4);
+        for i in 0..2 {
+            assert_abs_diff_eq!(A.row(i).sum(), 1.0, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_softmax_col_axis_matches_row_axis_on_transpose() {
+        let Z = DMatrix::from_row_slice(2, 3, &[0.5, -1.0, 2.0,
+                                                1.5, 0.0, -0.5]);
+        let dLdA = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                   0.0, 3.0, -1.0]);
+        let mut by_row = Softmax::new();
+        let mut by_col = Softmax::with_axis(Axis::Col);
+
+        let A_row = by_row.forward(&Z);
+        let A_col = by_col.forward(&Z.transpose());
+        for j in 0..A_col.ncols() {
+            assert_abs_diff_eq!(A_col.column(j).sum(), 1.0, epsilon = 1e-12);
+        }
+        assert_abs_diff_eq!(A_col, A_row.transpose(), epsilon = 1e-12);
+
+        let dLdZ_row = by_row.backward(&dLdA);
+        let dLdZ_col = by_col.backward(&dLdA.transpose());
+        assert_abs_diff_eq!(dLdZ_col, dLdZ_row.transpose(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_softmax_backward() {
+        let mut softmax = Softmax::new();
+        let Z = DMatrix::from_row_slice(1, 3, &[1.0, 2.0, 3.0]);
+        let A = softmax.forward(&Z);
+        let dLdA = DMatrix::from_row_slice(1, 3, &[1.0, 0.0, 0.0]);
+        let dLdZ = softmax.backward(&dLdA);
+        // ∂a_0/∂z_j = a_0 * (δ_0j - a_j)
+        let expected = DMatrix::from_row_slice(1, 3, &[A[0] * (1.0 - A[0]), -A[0] * A[1], -A[0] * A[2]]);
+        assert_abs_diff_eq!(dLdZ, expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_activation_from_name() {
+        let Z = DMatrix::from_row_slice(1, 3, &[-1.0, 0.5, 2.0]);
+        let dLdA = DMatrix::from_row_slice(1, 3, &[1.0, -1.0, 0.5]);
+        let cases: Vec<(&str, Box<dyn ActivationFunction>)> = vec![
+            ("identity", Box::new(Identity::new())),
+            ("relu", Box::new(ReLU::new())),
+            ("sigmoid", Box::new(Sigmoid::new())),
+            ("tanh", Box::new(Tanh::new())),
+            ("softmax", Box::new(Softmax::new())),
+            ("gelu", Box::new(GELU::new())),
+        ];
+        for (name, mut expected) in cases {
+            let mut activation = activation_from_name(name).unwrap();
+            assert_eq!(activation.name(), name);
+            assert_abs_diff_eq!(activation.forward(&Z), expected.forward(&Z), epsilon = 1e-12);
+            assert_abs_diff_eq!(activation.backward(&dLdA), expected.backward(&dLdA), epsilon = 1e-12);
+        }
+        assert_eq!(activation_from_name("ReLU").unwrap().name(), "relu");
+    }
+
+    #[test]
+    fn test_activation_from_unknown_name() {
+        let result = activation_from_name("swishy");
+        assert_eq!(result.err(), Some(DnnError::UnknownActivation("swishy".to_string())));
+    }
+
+    #[test]
+    fn test_scaled_tanh_bounds() {
+        let mut scaled = ScaledTanh::new(-2.0, 5.0);
+        let Z = DMatrix::from_row_slice(2, 3, &[-100.0, -1.0, 0.0,
+                                                0.5, 3.0, 100.0]);
+        let A = scaled.forward(&Z);
+        assert!(A.iter().all(|a| (-2.0..=5.0).contains(a)));
+        assert_abs_diff_eq!(A[(0, 2)], 1.5, epsilon = 1e-12); // Midpoint at z = 0
+        assert_abs_diff_eq!(A[(0, 0)], -2.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(A[(1, 2)], 5.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_scaled_tanh_gradient() {
+        let mut scaled = ScaledTanh::new(0.0, 10.0);
+        let Z = DMatrix::from_row_slice(2, 3, &[-1.5, -0.2, 0.0,
+                                                0.3, 0.9, 2.0]);
+        let dLdA = DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 0.5,
+                                                   2.0, 0.0, -0.5]);
+        let error = check_layer_gradient(&mut scaled, &Z, &dLdA, 1e-5);
+        assert!(error < 1e-7, "max relative error {}", error);
+    }
+
+    #[test]
+    fn test_lambda_relu_matches_relu() {
+        let mut lambda = Lambda::new(|z| z.max(0.0), |z| if z > 0.0 { 1.0 } else { 0.0 });
+        let mut relu = ReLU::new();
+        let Z = DMatrix::from_row_slice(2, 3, &[0.0378, 0.3022, -1.6123,
+                                                -2.5186, -1.9395, 1.4077]);
+        let dLdA = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 3.0,
+                                                   0.5, 0.25, -1.5]);
+        assert_abs_diff_eq!(lambda.forward(&Z), relu.forward(&Z), epsilon = 1e-12);
+        assert_abs_diff_eq!(lambda.backward(&dLdA), relu.backward(&dLdA), epsilon = 1e-12);
+        assert_eq!(ActivationFunction::name(&lambda), "lambda");
+    }
+
+    #[test]
+    fn test_elu() {
+        let mut elu = ELU::new(1.0);
+        let Z = DMatrix::from_row_slice(1, 4, &[-1.0, 0.0, 2.0, -3.0]);
+        let A = elu.forward(&Z);
+        assert_abs_diff_eq!(A, DMatrix::from_row_slice(1, 4, &[-0.632121, 0.0, 2.0, -0.950213]), epsilon = 1e-4);
+        let dLdZ = elu.backward(&DMatrix::from_element(1, 4, 1.0));
+        assert_abs_diff_eq!(dLdZ, DMatrix::from_row_slice(1, 4, &[0.367879, 1.0, 1.0, 0.049787]), epsilon = 1e-4);
+        // Below zero the derivative is A + α
+        assert_abs_diff_eq!(dLdZ[(0, 0)], A[(0, 0)] + 1.0, epsilon = 1e-12);
+
+        let mut elu = ELU::new(0.5);
+        assert_abs_diff_eq!(elu.forward(&DMatrix::from_element(1, 1, -1.0))[(0, 0)], -0.316060, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_gelu() {
+        let mut gelu = GELU::new();
+        let Z = DMatrix::from_row_slice(1, 5, &[-3.0, -1.0, 0.0, 1.0, 2.0]);
+        let A = gelu.forward(&Z);
+        assert_abs_diff_eq!(A, DMatrix::from_row_slice(1, 5, &[-0.003637, -0.158808, 0.0, 0.841192, 1.954598]), epsilon = 1e-4);
+        let dLdZ = gelu.backward(&DMatrix::from_element(1, 5, 1.0));
+        assert_abs_diff_eq!(dLdZ, DMatrix::from_row_slice(1, 5, &[-0.011584, -0.082964, 0.5, 1.082964, 1.086099]), epsilon = 1e-4);
+
+        let input = DMatrix::from_row_slice(2, 3, &[0.5, -1.0, 2.0,
+                                                    -0.3, 0.8, -2.5]);
+        let upstream_grad = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                            0.5, 3.0, -1.0]);
+        assert!(check_layer_gradient(&mut GELU::new(), &input, &upstream_grad, 1e-5) < 1e-7);
+        assert!(check_layer_gradient(&mut ELU::new(1.5), &input, &upstream_grad, 1e-5) < 1e-7);
+    }
+
+    #[test]
+    fn test_elu_and_gelu_in_f32() {
+        let Z = DMatrix::from_row_slice(1, 4, &[-2.0, -0.5, 0.0, 1.5]);
+        let Z32 = Z.map(|z| z as f32);
+        let dLdA = DMatrix::from_row_slice(1, 4, &[1.0, -2.0, 0.5, 3.0]);
+        let dLdA32 = dLdA.map(|g| g as f32);
+
+        let (mut elu, mut elu32) = (ELU::new(1.5), ELU::<f32>::new(1.5));
+        assert_abs_diff_eq!(elu32.forward(&Z32).map(|a| a as f64), elu.forward(&Z), epsilon = 1e-6);
+        assert_abs_diff_eq!(elu32.backward(&dLdA32).map(|g| g as f64), elu.backward(&dLdA), epsilon = 1e-6);
+
+        let (mut gelu, mut gelu32) = (GELU::new(), GELU::<f32>::new());
+        assert_abs_diff_eq!(gelu32.forward(&Z32).map(|a| a as f64), gelu.forward(&Z), epsilon = 1e-6);
+        assert_abs_diff_eq!(gelu32.backward(&dLdA32).map(|g| g as f64), gelu.backward(&dLdA), epsilon = 1e-6);
+    }
 }
@@ -0,0 +1,353 @@
+use nalgebra::{DMatrix};
+use crate::error::DnnError;
+use crate::nn::activation::ActivationFunction;
+
+/**
+    * Batch Normalization
+    *
+    * Batch normalization normalizes every feature of its input over the
+    * batch and then applies a learnable scale γ and shift β per feature:
+    *
+    *   training:  Â = (Z - μ_B) / sqrt(σ_B^2 + ε),  A = Â ⊙ γ + β
+    *   inference: Â = (Z - μ_R) / sqrt(σ_R^2 + ε),  A = Â ⊙ γ + β
+    *
+    * where μ_B and σ_B^2 are the mean and (biased) variance of each column
+    * of the batch Z (N x C). Keeping the inputs of the next layer at zero
+    * mean and unit variance makes deep networks train faster and with larger
+    * learning rates. During training, the running statistics μ_R and σ_R^2
+    * track the batch statistics as exponential moving averages,
+    *
+    *   μ_R = (1 - momentum) * μ_R + momentum * μ_B,
+    *
+    * and inference uses them instead, so the output of a sample does not
+    * depend on the rest of its batch. ε keeps the division finite when a
+    * feature has zero variance, e.g. for a batch of a single sample.
+    *
+    * backward in training mode accounts for μ_B and σ_B^2 depending on the
+    * whole batch:
+    *
+    *   ∂L/∂Z = γ / (N * sqrt(σ_B^2 + ε)) ⊙ (N * G - Σ_i G_i - Â ⊙ Σ_i (G_i ⊙ Â_i)),
+    *
+    * with G = ∂L/∂A, and backward_params also stores ∂L/∂γ = Σ_i G_i ⊙ Â_i
+    * and ∂L/∂β = Σ_i G_i.
+    *
+    * Like Dropout, BatchNorm implements the ActivationFunction trait, so it
+    * is placed in the activation slot after a layer and switched between
+    * training and inference by NeuralNetwork::train and NeuralNetwork::eval.
+    * γ and β are its learnable parameters. Its config holds momentum, ε,
+    * γ, β and the running statistics, so a saved model keeps all of them.
+    *
+**/
+
+
+// Applies f to every column of M, giving a 1 x C row of per-feature values
+fn per_feature<F: Fn(&[f64]) -> f64>(M : &DMatrix<f64>, f : F) -> DMatrix<f64> {
+    return DMatrix::from_fn(1, M.ncols(), |_, j| f(M.column(j).as_slice()));
+}
+
+fn mean(x : &[f64]) -> f64 {
+    return x.iter().sum::<f64>() / x.len() as f64;
+}
+
+// Biased variance, i.e. normalized by the number of samples
+fn variance(x : &[f64]) -> f64 {
+    let mean = mean(x);
+    return x.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / x.len() as f64;
+}
+
+fn sum(x : &[f64]) -> f64 {
+    return x.iter().sum();
+}
+
+#[derive(Clone)]
+pub struct BatchNorm {
+    pub gamma : DMatrix<f64>, // Scale γ (1 x C)
+    pub beta : DMatrix<f64>, // Shift β (1 x C)
+    pub running_mean : DMatrix<f64>, // Running mean μ_R (1 x C)
+    pub running_var : DMatrix<f64>, // Running variance σ_R^2 (1 x C)
+    pub momentum : f64, // Weight of the batch statistics in the running averages
+    pub eps : f64, // Added to the variance before taking the square root
+    pub training : bool, // Whether batch (training) or running (inference) statistics are used
+    pub dLdgamma : DMatrix<f64>, // Gradient of the loss with respect to γ (1 x C)
+    pub dLdbeta : DMatrix<f64>, // Gradient of the loss with respect to β (1 x C)
+    pub requires_grad_gamma : bool, // Whether optimizers update γ
+    pub requires_grad_beta : bool, // Whether optimizers update β
+    X_hat : DMatrix<f64>, // Normalized input Â of the last forward pass (N x C)
+    inv_std : DMatrix<f64>, // 1 / sqrt(σ^2 + ε) of the last forward pass (1 x C)
+    batch_stats : bool // Whether the last forward pass used the batch statistics
+}
+
+impl BatchNorm {
+    // Batch normalization over num_features features, starting from γ = 1,
+    // β = 0, μ_R = 0 and σ_R^2 = 1, with momentum 0.1 and ε = 1e-5
+    pub fn new(num_features : usize) -> Self {
+        BatchNorm {
+            gamma : DMatrix::from_element(1, num_features, 1.0),
+            beta : DMatrix::zeros(1, num_features),
+            running_mean : DMatrix::zeros(1, num_features),
+            running_var : DMatrix::from_element(1, num_features, 1.0),
+            momentum : 0.1,
+            eps : 1e-5,
+            training : true,
+            dLdgamma : DMatrix::zeros(1, num_features),
+            dLdbeta : DMatrix::zeros(1, num_features),
+            requires_grad_gamma : true,
+            requires_grad_beta : true,
+            X_hat : DMatrix::zeros(0, 0),
+            inv_std : DMatrix::zeros(0, 0),
+            batch_stats : true
+        }
+    }
+
+    // Rebuilds a BatchNorm from its config [momentum, ε, γ, β, μ_R, σ_R^2],
+    // where each of the last four is C values
+    pub fn from_config(config : &[f64]) -> Result<Self, DnnError> {
+        if config.len() < 2 || (config.len() - 2) % 4 != 0 {
+            return Err(DnnError::InvalidFormat(format!("invalid batchnorm config of {} values", config.len())));
+        }
+        let C = (config.len() - 2) / 4;
+        let row = |k : usize| DMatrix::from_row_slice(1, C, &config[2 + k * C..2 + (k + 1) * C]);
+        let mut batchnorm = BatchNorm::new(C);
+        batchnorm.momentum = config[0];
+        batchnorm.eps = config[1];
+        batchnorm.gamma = row(0);
+        batchnorm.beta = row(1);
+        batchnorm.running_mean = row(2);
+        batchnorm.running_var = row(3);
+        return Ok(batchnorm);
+    }
+
+    pub fn with_momentum(mut self, momentum : f64) -> Self {
+        assert!((0.0..=1.0).contains(&momentum), "momentum must be in [0, 1], got {}", momentum);
+        self.momentum = momentum;
+        return self;
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        assert_eq!(Z.ncols(), self.gamma.ncols(), "BatchNorm expects {} features, got {}", self.gamma.ncols(), Z.ncols());
+        assert!(Z.nrows() > 0, "BatchNorm needs a non-empty batch");
+        let (mean, var) = if self.training {
+            let mean = per_feature(Z, mean);
+            let var = per_feature(Z, variance);
+            self.running_mean = &self.running_mean * (1.0 - self.momentum) + &mean * self.momentum;
+            self.running_var = &self.running_var * (1.0 - self.momentum) + &var * self.momentum;
+            (mean, var)
+        } else {
+            (self.running_mean.clone(), self.running_var.clone())
+        };
+        let eps = self.eps;
+        self.inv_std = var.map(|v| 1.0 / (v + eps).sqrt());
+        self.X_hat = DMatrix::from_fn(Z.nrows(), Z.ncols(), |i, j| (Z[(i, j)] - mean[j]) * self.inv_std[j]);
+        self.batch_stats = self.training;
+        return DMatrix::from_fn(Z.nrows(), Z.ncols(), |i, j| self.X_hat[(i, j)] * self.gamma[j] + self.beta[j]);
+    }
+
+    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.X_hat.is_empty(), "Forward pass not called before backward pass");
+        let (N, C) = self.X_hat.shape();
+        // ∂L/∂Â = G ⊙ γ
+        let dLdX_hat = DMatrix::from_fn(N, C, |i, j| dLdA[(i, j)] * self.gamma[j]);
+        if !self.batch_stats {
+            // The running statistics are constants
+            return DMatrix::from_fn(N, C, |i, j| dLdX_hat[(i, j)] * self.inv_std[j]);
+        }
+        let sum_G = per_feature(&dLdX_hat, sum);
+        let weighted_sum = per_feature(&dLdX_hat.component_mul(&self.X_hat), sum);
+        let n = N as f64;
+        return DMatrix::from_fn(N, C, |i, j| {
+            self.inv_std[j] / n * (n * dLdX_hat[(i, j)] - sum_G[j] - self.X_hat[(i, j)] * weighted_sum[j])
+        });
+    }
+
+    // Also stores ∂L/∂γ = Σ_i G_i ⊙ Â_i and ∂L/∂β = Σ_i G_i
+    pub fn backward_params(&mut self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        let dLdZ = self.backward(dLdA);
+        self.dLdgamma = per_feature(&dLdA.component_mul(&self.X_hat), sum);
+        self.dLdbeta = per_feature(dLdA, sum);
+        return dLdZ;
+    }
+}
+
+impl ActivationFunction for BatchNorm {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return BatchNorm::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return BatchNorm::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "batchnorm";
+    }
+
+    fn set_training(&mut self, training : bool) {
+        self.training = training;
+    }
+
+    // Normalizes exactly the features it was built for
+    fn output_size(&self, input_size : usize) -> Option<usize> {
+        return if input_size == self.gamma.ncols() { Some(input_size) } else { None };
+    }
+
+    // γ, β and the running statistics are per feature
+    fn is_elementwise(&self) -> bool {
+        return false;
+    }
+
+    fn backward_params(&mut self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return BatchNorm::backward_params(self, dLdA);
+    }
+
+    fn parameters(&self) -> Vec<&DMatrix<f64>> {
+        return vec![&self.gamma, &self.beta];
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return vec![&mut self.gamma, &mut self.beta];
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<f64>> {
+        return vec![&self.dLdgamma, &self.dLdbeta];
+    }
+
+    fn gradients_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return vec![&mut self.dLdgamma, &mut self.dLdbeta];
+    }
+
+    fn requires_grad(&self) -> Vec<bool> {
+        return vec![self.requires_grad_gamma, self.requires_grad_beta];
+    }
+
+    // Index 0 is γ, 1 is β
+    fn set_requires_grad(&mut self, index : usize, requires : bool) {
+        if index == 0 {
+            self.requires_grad_gamma = requires;
+        } else {
+            self.requires_grad_beta = requires;
+        }
+    }
+
+    // The running mean and variance
+    fn buffers(&self) -> Vec<&DMatrix<f64>> {
+        return vec![&self.running_mean, &self.running_var];
+    }
+
+    fn buffers_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return vec![&mut self.running_mean, &mut self.running_var];
+    }
+
+    fn config(&self) -> Vec<f64> {
+        let mut config = vec![self.momentum, self.eps];
+        for row in [&self.gamma, &self.beta, &self.running_mean, &self.running_var] {
+            config.extend(row.iter());
+        }
+        return config;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::check_layer_gradient;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::nn::model::NeuralNetwork;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_batchnorm_normalizes_in_training_mode() {
+        let mut batchnorm = BatchNorm::new(3);
+        let Z = DMatrix::from_row_slice(4, 3, &[1.0, 10.0, -5.0,
+                                                2.0, 20.0, -5.5,
+                                                3.0, 30.0, -4.0,
+                                                4.0, 40.0, -6.5]);
+        let A = batchnorm.forward(&Z);
+        for j in 0..3 {
+            let column = A.column(j);
+            assert_abs_diff_eq!(column.mean(), 0.0, epsilon = 1e-12);
+            assert_abs_diff_eq!(column.variance(), 1.0, epsilon = 1e-4);
+        }
+        // The running statistics moved by momentum towards the batch ones
+        assert_abs_diff_eq!(batchnorm.running_mean, per_feature(&Z, mean) * 0.1, epsilon = 1e-12);
+        assert_abs_diff_eq!(batchnorm.running_var, per_feature(&Z, variance) * 0.1 + DMatrix::from_element(1, 3, 0.9), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_batchnorm_uses_running_statistics_in_eval_mode() {
+        let mut batchnorm = BatchNorm::new(2);
+        batchnorm.running_mean = DMatrix::from_row_slice(1, 2, &[1.0, -2.0]);
+        batchnorm.running_var = DMatrix::from_row_slice(1, 2, &[4.0, 0.25]);
+        batchnorm.gamma = DMatrix::from_row_slice(1, 2, &[2.0, 1.0]);
+        batchnorm.beta = DMatrix::from_row_slice(1, 2, &[0.5, 0.0]);
+        batchnorm.eps = 0.0;
+        batchnorm.set_training(false);
+
+        // A single sample is normalized independently of any batch
+        let A = batchnorm.forward(&DMatrix::from_row_slice(1, 2, &[3.0, -1.0]));
+        assert_abs_diff_eq!(A, DMatrix::from_row_slice(1, 2, &[2.5, 2.0]), epsilon = 1e-12);
+        assert_eq!(batchnorm.running_mean, DMatrix::from_row_slice(1, 2, &[1.0, -2.0]));
+        let dLdZ = batchnorm.backward(&DMatrix::from_element(1, 2, 1.0));
+        assert_abs_diff_eq!(dLdZ, DMatrix::from_row_slice(1, 2, &[1.0, 2.0]), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_batchnorm_single_sample_batch() {
+        let mut batchnorm = BatchNorm::new(2);
+        batchnorm.beta = DMatrix::from_row_slice(1, 2, &[0.5, -1.0]);
+        let A = batchnorm.forward(&DMatrix::from_row_slice(1, 2, &[3.0, 7.0]));
+        // Zero variance: every sample equals the mean and maps to β
+        assert_eq!(A, batchnorm.beta);
+        assert!(batchnorm.backward(&DMatrix::from_element(1, 2, 1.0)).iter().all(|g| g.is_finite()));
+    }
+
+    #[test]
+    fn test_batchnorm_gradient() {
+        let mut batchnorm = BatchNorm::new(3);
+        batchnorm.gamma = DMatrix::from_row_slice(1, 3, &[1.5, -0.5, 2.0]);
+        batchnorm.beta = DMatrix::from_row_slice(1, 3, &[0.1, 0.2, -0.3]);
+        let input = DMatrix::from_row_slice(4, 3, &[0.5, -1.0, 2.0,
+                                                    1.5, 0.0, -0.5,
+                                                    -2.0, 1.0, 0.25,
+                                                    0.1, 0.2, 0.3]);
+        let upstream_grad = DMatrix::from_row_slice(4, 3, &[1.0, -2.0, 0.5,
+                                                            0.5, 0.5, 1.0,
+                                                            -1.0, 3.0, 0.0,
+                                                            2.0, 0.0, -1.5]);
+        let error = check_layer_gradient(&mut batchnorm, &input, &upstream_grad, 1e-5);
+        assert!(error < 1e-6, "max relative error {}", error);
+    }
+
+    #[test]
+    fn test_batchnorm_survives_save_and_load() {
+        let mut original = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![Box::new(BatchNorm::new(2).with_momentum(0.3))], Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(3, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0,
+                                                2.0, 1.0, 1.5]);
+        let _ = original.forward(&x);
+        let mut bn = original.activations[0].parameters_mut();
+        *bn[0] = DMatrix::from_row_slice(1, 2, &[1.5, 0.5]);
+        *bn[1] = DMatrix::from_row_slice(1, 2, &[-0.2, 0.1]);
+
+        let mut loaded = NeuralNetwork::from_flatbuffer(&original.to_flatbuffer().unwrap()).unwrap();
+        assert_eq!(loaded.activations[0].config(), original.activations[0].config());
+        original.eval();
+        loaded.eval();
+        assert_eq!(loaded.forward(&x), original.forward(&x));
+    }
+
+    #[test]
+    #[should_panic(expected = "not element-wise")]
+    fn test_widen_layer_rejects_batchnorm() {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2)), Box::new(Linear::new(2, 1))],
+                                           vec![Box::new(BatchNorm::new(2))], Box::new(MSE::new()));
+        model.widen_layer(0, 4);
+    }
+
+    #[test]
+    fn test_load_rejects_batchnorm_of_another_width() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![Box::new(BatchNorm::new(4))], Box::new(MSE::new()));
+        assert_eq!(NeuralNetwork::from_flatbuffer(&model.to_flatbuffer().unwrap()).err(),
+                   Some(DnnError::InvalidFormat("activation 0 (batchnorm) cannot take 2 features".to_string())));
+    }
+}
@@ -0,0 +1,136 @@
+use nalgebra::{DMatrix};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::nn::activation::ActivationFunction;
+
+/**
+    * Dropout
+    *
+    * Dropout is a regularizer that randomly sets each unit of its input to
+    * zero with probability p during training, so the network cannot rely on
+    * any single unit. This implementation uses inverted dropout: the units
+    * that survive are scaled by 1 / (1 - p), so the expected output equals
+    * the input and nothing has to change at inference.
+    *
+    *   training:  A = Z ⊙ M / (1 - p),  M_ij ~ Bernoulli(1 - p)
+    *   inference: A = Z
+    *
+    * backward applies the same cached mask (and scale) to ∂L/∂A.
+    *
+    * Dropout has no parameters and acts element-wise, so it implements the
+    * ActivationFunction trait and can be placed after any layer. It starts in
+    * training mode; NeuralNetwork::train and NeuralNetwork::eval switch it
+    * through set_training.
+    *
+**/
+
+
+#[derive(Clone)]
+pub struct Dropout {
+    pub p : f64, // Probability of dropping a unit
+    pub training : bool, // Whether units are dropped (training) or passed through (inference)
+    M : DMatrix<f64>, // Mask of the last forward pass, already scaled by 1 / (1 - p)
+    rng : StdRng
+}
+
+impl Dropout {
+    pub fn new(p : f64) -> Self {
+        return Dropout::with_rng(p, crate::random::rng());
+    }
+
+    // Dropout whose masks are reproducible for a given seed
+    pub fn with_seed(p : f64, seed : u64) -> Self {
+        return Dropout::with_rng(p, StdRng::seed_from_u64(seed));
+    }
+
+    fn with_rng(p : f64, rng : StdRng) -> Self {
+        assert!((0.0..1.0).contains(&p), "Dropout probability must be in [0, 1), got {}", p);
+        Dropout {
+            p : p,
+            training : true,
+            M : DMatrix::zeros(0, 0),
+            rng : rng
+        }
+    }
+
+    pub fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        if !self.training {
+            self.M = DMatrix::from_element(Z.nrows(), Z.ncols(), 1.0);
+            return Z.clone();
+        }
+        let scale = 1.0 / (1.0 - self.p);
+        let p = self.p;
+        let rng = &mut self.rng;
+        self.M = DMatrix::from_fn(Z.nrows(), Z.ncols(), |_, _| if rng.gen::<f64>() < p { 0.0 } else { scale });
+        return Z.component_mul(&self.M);
+    }
+
+    // dA/dZ = M
+    pub fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.M.is_empty(), "Forward pass not called before backward pass");
+        return dLdA.component_mul(&self.M);
+    }
+}
+
+impl ActivationFunction for Dropout {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return Dropout::forward(self, Z);
+    }
+
+    // Inference never drops units, whatever the mode
+    fn predict(&self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return Z.clone();
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return Dropout::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "dropout";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![self.p];
+    }
+
+    // Surviving units are scaled by 1 / (1 - p) during training
+    fn lipschitz_constant(&self) -> f64 {
+        return if self.training { 1.0 / (1.0 - self.p) } else { 1.0 };
+    }
+
+    fn set_training(&mut self, training : bool) {
+        self.training = training;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_dropout_masks_and_scales() {
+        let mut dropout = Dropout::with_seed(0.5, 0);
+        let Z = DMatrix::from_element(20, 10, 3.0);
+        let A = dropout.forward(&Z);
+        // Every unit is either dropped or scaled by 1 / (1 - p) = 2
+        assert!(A.iter().all(|a| *a == 0.0 || *a == 6.0));
+        let dropped = A.iter().filter(|a| **a == 0.0).count();
+        assert!(dropped > 70 && dropped < 130, "dropped {} of 200", dropped);
+
+        // The gradient flows through exactly the surviving units
+        let dLdZ = dropout.backward(&DMatrix::from_element(20, 10, 1.0));
+        assert_abs_diff_eq!(dLdZ, A / 3.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_dropout_inference_is_identity() {
+        let mut dropout = Dropout::with_seed(0.9, 1);
+        dropout.set_training(false);
+        let Z = DMatrix::from_row_slice(2, 2, &[1.0, -2.0,
+                                                3.0, 4.0]);
+        assert_eq!(dropout.forward(&Z), Z);
+        assert_eq!(dropout.backward(&Z), Z);
+    }
+}
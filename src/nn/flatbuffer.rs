@@ -0,0 +1,311 @@
+use nalgebra::{DMatrix};
+use std::fs;
+use std::path::Path;
+use crate::error::DnnError;
+use crate::nn::activation::{activation_from_config, ActivationFunction};
+use crate::nn::layers::Linear;
+use crate::nn::loss::MSE;
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Portable Model Format
+    *
+    * A versioned binary format for long-term storage of sequential models.
+    * It is a flat buffer in the literal sense, a fixed little-endian layout
+    * with no pointers, but not the Google FlatBuffers wire format:
+    *
+    *   header       magic "DNNF" (4 bytes), format version (u32),
+    *                number of layers L (u32), number of activations K (u32)
+    *   L layers     C_out (u32), C_in (u32),
+    *                W as C_out * C_in f64 values (column-major), b as C_out f64 values
+    *   K activations   name length n (u32), config length m (u32),
+    *                   config as m f64 values, n bytes of UTF-8 name
+    *                   padded with zeros to a multiple of 8 bytes
+    *
+    * Every section has a size that is a multiple of 8 bytes, so all f64
+    * arrays are 8-byte aligned relative to the start of the buffer and can be
+    * read in place.
+    *
+    * The version is bumped on every incompatible change, and loading a buffer
+    * of another version fails with DnnError::UnsupportedVersion instead of
+    * silently misreading it. Activations are stored by name with their config
+    * (constructor arguments and learned values) and rebuilt with
+    * activation_from_config. Saving a model with an activation that cannot
+    * be rebuilt this way (e.g. a Lambda) fails. The loss is not stored;
+    * loaded models use MSE.
+    *
+**/
+
+
+const MAGIC: &[u8; 4] = b"DNNF";
+pub const FLATBUFFER_VERSION: u32 = 1;
+
+// Sequential reader over a buffer that reports truncation as an error
+struct Reader<'a> {
+    buffer: &'a [u8],
+    offset: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DnnError> {
+        if self.buffer.len() - self.offset < n {
+            return Err(DnnError::InvalidFormat(format!("buffer truncated at byte {}", self.offset)));
+        }
+        let bytes = &self.buffer[self.offset..self.offset + n];
+        self.offset += n;
+        return Ok(bytes);
+    }
+
+    // Fails unless count records of at least record_size bytes each fit in
+    // the rest of the buffer, so that counts read from an untrusted buffer
+    // cannot drive an allocation
+    fn check_remaining(&self, count: usize, record_size: usize) -> Result<(), DnnError> {
+        match count.checked_mul(record_size) {
+            Some(n) if n <= self.buffer.len() - self.offset => return Ok(()),
+            _ => return Err(DnnError::InvalidFormat(format!("{} records do not fit in the buffer", count)))
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DnnError> {
+        return Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()));
+    }
+
+    fn read_f64s(&mut self, n: usize) -> Result<Vec<f64>, DnnError> {
+        return Ok(self.read_matrix(n, 1)?.as_slice().to_vec());
+    }
+
+    fn read_matrix(&mut self, rows: usize, cols: usize) -> Result<DMatrix<f64>, DnnError> {
+        let size = rows.checked_mul(cols).and_then(|n| n.checked_mul(8))
+            .ok_or_else(|| DnnError::InvalidFormat(format!("{} x {} matrix is too large", rows, cols)))?;
+        let bytes = self.take(size)?;
+        let values = bytes.chunks_exact(8).map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()));
+        return Ok(DMatrix::from_iterator(rows, cols, values));
+    }
+}
+
+// Number of zero bytes that pad a name of n bytes to a multiple of 8
+fn padding(n: usize) -> usize {
+    return (8 - n % 8) % 8;
+}
+
+impl NeuralNetwork {
+    // Serializes the model into the portable format described above. Fails
+    // with DnnError::UnknownActivation if an activation cannot be rebuilt
+    // from its name and config.
+    pub fn to_flatbuffer(&self) -> Result<Vec<u8>, DnnError> {
+        let mut buffer = Vec::with_capacity(16 + 8 * (self.layers.len() + self.num_parameters()));
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&FLATBUFFER_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&(self.layers.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&(self.activations.len() as u32).to_le_bytes());
+        for layer in self.layers.iter() {
+            buffer.extend_from_slice(&(layer.W.nrows() as u32).to_le_bytes());
+            buffer.extend_from_slice(&(layer.W.ncols() as u32).to_le_bytes());
+            for value in layer.W.iter().chain(layer.b.iter()) {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        for activation in self.activations.iter() {
+            let config = activation.config();
+            let _ = activation_from_config(activation.name(), &config)?;
+            let name = activation.name().as_bytes();
+            buffer.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(&(config.len() as u32).to_le_bytes());
+            for value in config.iter() {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+            buffer.extend_from_slice(name);
+            buffer.resize(buffer.len() + padding(name.len()), 0);
+        }
+        return Ok(buffer);
+    }
+
+    // Rebuilds a model from a buffer written by to_flatbuffer, checking the
+    // magic bytes, the format version, that the buffer is complete and that
+    // the layers fit together (see NeuralNetwork::check_architecture).
+    pub fn from_flatbuffer(buffer: &[u8]) -> Result<NeuralNetwork, DnnError> {
+        let mut reader = Reader { buffer: buffer, offset: 0 };
+        if reader.take(4).ok() != Some(&MAGIC[..]) {
+            return Err(DnnError::InvalidFormat("missing DNNF magic bytes".to_string()));
+        }
+        let version = reader.read_u32()?;
+        if version != FLATBUFFER_VERSION {
+            return Err(DnnError::UnsupportedVersion { found: version, supported: FLATBUFFER_VERSION });
+        }
+        let num_layers = reader.read_u32()? as usize;
+        let num_activations = reader.read_u32()? as usize;
+
+        // Every layer and every activation takes at least 8 bytes
+        reader.check_remaining(num_layers, 8)?;
+        reader.check_remaining(num_activations, 8)?;
+
+        let mut layers = Vec::with_capacity(num_layers);
+        for _ in 0..num_layers {
+            let output_size = reader.read_u32()? as usize;
+            let input_size = reader.read_u32()? as usize;
+            let W = reader.read_matrix(output_size, input_size)?;
+            let b = reader.read_matrix(output_size, 1)?;
+            layers.push(Box::new(Linear::from_parameters(W, b)));
+        }
+        let mut activations: Vec<Box<dyn ActivationFunction>> = Vec::with_capacity(num_activations);
+        for _ in 0..num_activations {
+            let length = reader.read_u32()? as usize;
+            let config_length = reader.read_u32()? as usize;
+            let config = reader.read_f64s(config_length)?;
+            let name = std::str::from_utf8(reader.take(length)?)
+                .map_err(|_| DnnError::InvalidFormat("activation name is not UTF-8".to_string()))?;
+            let _ = reader.take(padding(length))?;
+            activations.push(activation_from_config(name, &config)?);
+        }
+        if reader.offset != buffer.len() {
+            return Err(DnnError::InvalidFormat(format!("{} trailing bytes", buffer.len() - reader.offset)));
+        }
+        let model = NeuralNetwork::new(layers, activations, Box::new(MSE::new()));
+        model.check_architecture()?;
+        return Ok(model);
+    }
+
+    pub fn save_flatbuffer<P: AsRef<Path>>(&self, path: P) -> Result<(), DnnError> {
+        fs::write(path, self.to_flatbuffer()?)?;
+        return Ok(());
+    }
+
+    pub fn load_flatbuffer<P: AsRef<Path>>(path: P) -> Result<NeuralNetwork, DnnError> {
+        return NeuralNetwork::from_flatbuffer(&fs::read(path)?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{Axis, Lambda, LeakyReLU, ReLU, ScaledTanh, Sigmoid, Softmax, SwishBeta};
+    use crate::nn::dropout::Dropout;
+    use crate::nn::layers::{GradientReversal, LogSumExpPool};
+    use crate::precision::Bf16Cast;
+
+    fn network() -> NeuralNetwork {
+        return NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 2)), Box::new(Linear::new(2, 1))],
+                                  vec![Box::new(ReLU::new()), Box::new(Sigmoid::new())],
+                                  Box::new(MSE::new()));
+    }
+
+    // Saves and loads a model of one layer followed by activation, and checks
+    // that the loaded model computes the same (inference) output
+    fn round_trip(activation: Box<dyn ActivationFunction>) -> NeuralNetwork {
+        let mut original = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![activation], Box::new(MSE::new()));
+        let mut loaded = NeuralNetwork::from_flatbuffer(&original.to_flatbuffer().unwrap()).unwrap();
+        assert_eq!(loaded.flat_parameters(), original.flat_parameters());
+        assert_eq!(loaded.activations[0].name(), original.activations[0].name());
+        original.activations[0].set_training(false);
+        loaded.activations[0].set_training(false);
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(loaded.forward(&x), original.forward(&x));
+        return loaded;
+    }
+
+    #[test]
+    fn test_flatbuffer_round_trip() {
+        let mut original = network();
+        let path = std::env::temp_dir().join(format!("dnn_rs_flatbuffer_{}.bin", std::process::id()));
+        original.save_flatbuffer(&path).unwrap();
+        let mut loaded = NeuralNetwork::load_flatbuffer(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.flat_parameters(), original.flat_parameters());
+        let names: Vec<&str> = loaded.activations.iter().map(|a| a.name()).collect();
+        assert_eq!(names, vec!["relu", "sigmoid"]);
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(loaded.forward(&x), original.forward(&x));
+    }
+
+    #[test]
+    fn test_flatbuffer_rejects_other_versions() {
+        let mut buffer = network().to_flatbuffer().unwrap();
+        buffer[4..8].copy_from_slice(&(FLATBUFFER_VERSION + 1).to_le_bytes());
+        let error = NeuralNetwork::from_flatbuffer(&buffer).err().unwrap();
+        assert_eq!(error, DnnError::UnsupportedVersion { found: FLATBUFFER_VERSION + 1, supported: FLATBUFFER_VERSION });
+        assert!(error.to_string().contains(&format!("unsupported model format version {}", FLATBUFFER_VERSION + 1)));
+    }
+
+    #[test]
+    fn test_flatbuffer_rejects_malformed_buffers() {
+        let buffer = network().to_flatbuffer().unwrap();
+        assert!(matches!(NeuralNetwork::from_flatbuffer(&buffer[..buffer.len() - 3]), Err(DnnError::InvalidFormat(_))));
+        assert!(matches!(NeuralNetwork::from_flatbuffer(b"not a model"), Err(DnnError::InvalidFormat(_))));
+
+        // Counts and sizes larger than the buffer are rejected before allocating
+        let mut huge_counts = buffer.clone();
+        huge_counts[8..16].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(NeuralNetwork::from_flatbuffer(&huge_counts), Err(DnnError::InvalidFormat(_))));
+        let mut huge_layer = buffer.clone();
+        huge_layer[16..24].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(NeuralNetwork::from_flatbuffer(&huge_layer), Err(DnnError::InvalidFormat(_))));
+
+        // The error names the offset of the read that failed
+        assert_eq!(NeuralNetwork::from_flatbuffer(&buffer[..10]).err(),
+                   Some(DnnError::InvalidFormat("buffer truncated at byte 8".to_string())));
+
+        // Activations that cannot be rebuilt are rejected when saving
+        let lambda = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![Box::new(Lambda::new(|z| z, |_| 1.0))], Box::new(MSE::new()));
+        assert_eq!(lambda.to_flatbuffer().err(), Some(DnnError::UnknownActivation("lambda".to_string())));
+    }
+
+    #[test]
+    fn test_flatbuffer_rejects_models_that_do_not_fit_together() {
+        let unchained = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(5, 1))],
+                                           vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        assert_eq!(NeuralNetwork::from_flatbuffer(&unchained.to_flatbuffer().unwrap()).err(),
+                   Some(DnnError::InvalidFormat("layer 1 takes 5 features, but layer 0 gives 4".to_string())));
+
+        let missing_activation = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 4)), Box::new(Linear::new(4, 1))],
+                                                    vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        assert_eq!(NeuralNetwork::from_flatbuffer(&missing_activation.to_flatbuffer().unwrap()).err(),
+                   Some(DnnError::InvalidFormat("1 activations for 3 layers".to_string())));
+    }
+
+    #[test]
+    fn test_flatbuffer_keeps_activation_config() {
+        let mut original = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 4)),
+                                                   Box::new(Linear::new(4, 3)), Box::new(Linear::new(3, 2))],
+                                              vec![Box::new(LeakyReLU::new(0.2)), Box::new(SwishBeta::new(1.7)),
+                                                   Box::new(ScaledTanh::new(0.0, 2.0)), Box::new(Softmax::with_axis(Axis::Col))],
+                                              Box::new(MSE::new()));
+        let mut loaded = NeuralNetwork::from_flatbuffer(&original.to_flatbuffer().unwrap()).unwrap();
+        let configs: Vec<Vec<f64>> = loaded.activations.iter().map(|a| a.config()).collect();
+        assert_eq!(configs, vec![vec![0.2], vec![1.7], vec![0.0, 2.0], vec![1.0]]);
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(loaded.forward(&x), original.forward(&x));
+    }
+
+    #[test]
+    fn test_flatbuffer_keeps_dropout() {
+        let loaded = round_trip(Box::new(Dropout::new(0.3)));
+        assert_eq!(loaded.activations[0].config(), vec![0.3]);
+    }
+
+    #[test]
+    fn test_flatbuffer_keeps_gradient_reversal() {
+        let loaded = round_trip(Box::new(GradientReversal::new(0.7)));
+        assert_eq!(loaded.activations[0].config(), vec![0.7]);
+    }
+
+    #[test]
+    fn test_flatbuffer_keeps_logsumexp_pool() {
+        let loaded = round_trip(Box::new(LogSumExpPool::new(4.0)));
+        assert_eq!(loaded.activations[0].config(), vec![4.0]);
+
+        // The pooled output has a single feature, which the next layer takes
+        let pooled = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(1, 2))],
+                                        vec![Box::new(LogSumExpPool::new(4.0))], Box::new(MSE::new()));
+        assert!(NeuralNetwork::from_flatbuffer(&pooled.to_flatbuffer().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_flatbuffer_keeps_bf16_cast() {
+        let loaded = round_trip(Box::new(Bf16Cast::new()));
+        assert!(loaded.activations[0].config().is_empty());
+    }
+}
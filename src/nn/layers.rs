@@ -1,4 +1,14 @@
-use nalgebra::{DMatrix};
+use alloc::vec;
+use alloc::vec::Vec;
+use nalgebra::{DMatrix, RowDVector};
+// Float math (exp, powf, round, ...) comes from libm without std
+#[cfg(not(feature = "std"))]
+use nalgebra::ComplexField;
+use crate::nn::activation::ActivationFunction;
+use crate::scalar::{from_f64, Real};
+use rand_distr::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 /**
     * Multi-Layer Perceptron (MLP) Layers Module
@@ -9,35 +19,124 @@ use nalgebra::{DMatrix};
     * and biases that are learned during the training process. The weights and
     * biases are updated using an optimization algorithm such as gradient descent.
     *
+    * Every layer follows the same forward/backward contract, captured by the
+    * Layer trait: forward maps the layer input A to its output Z (caching what
+    * backward needs), and backward maps ∂L/∂Z to ∂L/∂A while storing the
+    * gradients of the layer's own parameters. Activation functions follow the
+    * same contract and are Layers without parameters.
+    *
     * Currently, the following layers are implemented:
     * 1. Linear Layer - Applies a linear transformation to the incoming data.
-    *                   The output is computed as Z = A * W^T + ι_N * b.
+    *                   The output is computed as Z = A * W^T + ι_N * b. The
+    *                   initial weights can be chosen with an Init scheme,
+    *                   and decorrelate_init makes the weight rows orthogonal.
+    * 2. GradientReversal - The identity in forward, but multiplies the gradient
+    *                   by -λ in backward. Placed between a feature extractor and
+    *                   a domain classifier, it trains the features to confuse the
+    *                   classifier (domain-adversarial training). It has no
+    *                   parameters and fits in an activation slot of a model.
+    * 3. GroupedLinear - Splits the input and output features into groups and
+    *                   applies a separate linear map per group, i.e. a Linear
+    *                   layer with a block-diagonal W. It has 1/groups of the
+    *                   weights (and multiply-adds) of a dense layer.
+    * 4. LogSumExpPool - Pools the C features of every sample into one value,
+    *                   y = (1/t) * ln Σ_j exp(t * a_j), a smooth maximum that
+    *                   lies between max_j a_j and max_j a_j + ln(C) / t and
+    *                   approaches max pooling as the temperature t grows. Unlike
+    *                   the max, its gradient (softmax(t * a)) reaches every
+    *                   feature. It has no parameters and fits in an activation
+    *                   slot of a model.
     *
 **/
 
 
-pub struct Linear {
-    pub W : DMatrix<f64>, // Weights (C_out x C_in)
-    pub b : DMatrix<f64>, // Bias (C_out x 1)
-    pub A : DMatrix<f64>, // Layer input (pre-activation) (N x C_in)
-    pub dLdW : DMatrix<f64>, // Gradient of the loss with respect to W
-    pub dLdb : DMatrix<f64>, // Gradient of the loss with respect to b
+// Initial parameter values, drawn uniformly from [0, 1). Without std there is
+// no entropy source, so the values come from a fixed seed and every layer of
+// a given shape starts out the same; such builds usually load trained
+// parameters anyway.
+#[cfg(feature = "std")]
+fn random_matrix(rows : usize, cols : usize) -> DMatrix<f64> {
+    let mut rng = crate::random::rng();
+    return DMatrix::from_fn(rows, cols, |_, _| rng.gen::<f64>());
+}
+
+#[cfg(not(feature = "std"))]
+fn random_matrix(rows : usize, cols : usize) -> DMatrix<f64> {
+    let mut rng = StdRng::seed_from_u64(0);
+    return DMatrix::from_fn(rows, cols, |_, _| rng.gen::<f64>());
+}
+
+// Weight initialization schemes for Linear::with_init, based on the fan-in
+// C_in and fan-out C_out of the layer:
+//   Zeros            W = 0
+//   Xavier           W ~ U(-sqrt(6 / (C_in + C_out)), +sqrt(6 / (C_in + C_out))),
+//                    which keeps the variance of signals and gradients constant
+//                    through tanh or sigmoid layers (Glorot & Bengio)
+//   He               W ~ N(0, σ^2) with σ = sqrt(2 / C_in), which compensates
+//                    for ReLU zeroing half of its inputs (He et al.)
+//   Uniform          W ~ U(low, high)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Init {
+    Zeros,
+    Xavier,
+    He,
+    Uniform { low : f64, high : f64 }
+}
+
+pub trait Layer<T: Real = f64> {
+    fn forward(&mut self, A : &DMatrix<T>) -> DMatrix<T>;
+    fn backward(&mut self, dLdZ : &DMatrix<T>) -> DMatrix<T>;
+
+    // Learnable parameters of the layer, and their gradients from the last
+    // backward pass, in matching order. Layers without parameters keep the
+    // default empty lists.
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return Vec::new();
+    }
+    fn gradients(&self) -> Vec<&DMatrix<T>> {
+        return Vec::new();
+    }
+}
+
+
+#[derive(Clone)]
+pub struct Linear<T: Real = f64> {
+    pub W : DMatrix<T>, // Weights (C_out x C_in)
+    pub b : DMatrix<T>, // Bias (C_out x 1)
+    pub A : DMatrix<T>, // Layer input (pre-activation) (N x C_in)
+    pub dLdW : DMatrix<T>, // Gradient of the loss with respect to W
+    pub dLdb : DMatrix<T>, // Gradient of the loss with respect to b
     pub N : usize, // Batch size (number of samples)
-    pub l_N : DMatrix<f64>, // Column vector of ones of size N (N x 1). Used to broadcast bias vector b.
+    pub l_N : DMatrix<T>, // Column vector of ones of size N (N x 1). Used to broadcast bias vector b.
+    pub prune_mask : Option<DMatrix<T>>, // Optional 0/1 mask over W (C_out x C_in). Masked weights get no gradient.
+    pub requires_grad_W : bool, // Whether optimizers update W. Gradients are still computed when false.
+    pub requires_grad_b : bool, // Whether optimizers update b
 }
 
-impl Linear {
+impl<T: Real> Linear<T> {
     // Constructor for the Linear struct. Creates a new Linear layer with
     // C_in input features and C_out output features.
     pub fn new(input_size : usize, output_size : usize) -> Self {
+        let W = random_matrix(output_size, input_size).map(from_f64); // Init param randomly
+        return Linear::from_parameters(W, random_matrix(output_size, 1).map(from_f64));
+    }
+
+    // Creates a Linear layer with the given weights W (C_out x C_in) and
+    // bias b (C_out x 1), e.g. from a custom initialization or a saved model.
+    // Nothing is drawn at random.
+    pub fn from_parameters(W : DMatrix<T>, b : DMatrix<T>) -> Self {
+        assert_eq!(b.shape(), (W.nrows(), 1), "Expected a {} x 1 bias, got {:?}", W.nrows(), b.shape());
         Linear {
-            W : DMatrix::new_random(output_size, input_size), // Init param randomly
-            b : DMatrix::new_random(output_size, 1),
+            W : W,
+            b : b,
             A : DMatrix::zeros(0, 0),
             dLdW : DMatrix::zeros(0, 0),
             dLdb : DMatrix::zeros(0, 0),
             N : 0,
-            l_N : DMatrix::zeros(0, 0)
+            l_N : DMatrix::zeros(0, 0),
+            prune_mask : None,
+            requires_grad_W : true,
+            requires_grad_b : true
         }
     }
 
@@ -46,28 +145,155 @@ impl Linear {
     // W and a bias vector b. That is, Z = A * W^T + ι_N * b. The variable
     // ι_N is a column vector of ones of size N (the batch size), and is used
     // to broadcast the bias vector b across all samples in the batch.
-    pub fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn forward(&mut self, A : &DMatrix<T>) -> DMatrix<T> {
         self.N = A.nrows();
         self.A = A.clone();
-        self.l_N = DMatrix::from_element(self.N, 1, 1.0);
+        self.l_N = DMatrix::from_element(self.N, 1, T::one());
         let Z = &self.A * self.W.transpose() + &self.l_N * self.b.transpose();
         return Z; // Z has shape N x C_out
     }
 
+    // Same output as forward, Z = A * W^T + ι_N * b, without caching A for
+    // the backward pass
+    pub fn predict(&self, A : &DMatrix<T>) -> DMatrix<T> {
+        let mut Z = A * self.W.transpose();
+        for mut row in Z.row_iter_mut() {
+            row += self.b.transpose();
+        }
+        return Z;
+    }
+
     // During backward propagation, we compute the gradients of the loss with
     // respect to pre-activation input (A), the weights W and bias b. Given ∂L/∂Z
     // we can compute ∂L/∂A, ∂L/∂W and ∂L/∂b as follows:
     // ∂L/∂A = ∂L/∂Z * W
     // ∂L/∂W = (∂L/∂Z)^T * A
     // ∂L/∂b = (∂L/∂Z)^T * ι_N
-    pub fn backward(&mut self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+    // If the layer has a prune mask, ∂L/∂W is multiplied by it element-wise so
+    // that pruned weights are never updated and stay zero during training.
+    pub fn backward(&mut self, dLdZ : &DMatrix<T>) -> DMatrix<T> {
         let dLdA = dLdZ * &self.W;
         self.dLdW = dLdZ.transpose() * &self.A;
+        if let Some(mask) = &self.prune_mask {
+            self.dLdW.component_mul_assign(mask);
+        }
         self.dLdb = dLdZ.transpose() * &self.l_N;
         return dLdA;
     }
 
+    // Forward-mode derivative of the layer output in the direction V of the
+    // input (N x C_in): ∂Z/∂A [V] = V * W^T. The bias does not depend on A.
+    pub fn jvp(&self, V : &DMatrix<T>) -> DMatrix<T> {
+        return V * self.W.transpose();
+    }
+
+    // Freezes (or unfreezes) the layer: optimizers leave frozen parameters
+    // untouched. Backward still computes their gradients, as the gradient
+    // with respect to the input is needed by the layers before anyway.
+    pub fn set_frozen(&mut self, frozen : bool) {
+        self.requires_grad_W = !frozen;
+        self.requires_grad_b = !frozen;
+    }
+}
+
+// Constructors with an initialization scheme and magnitude pruning work on
+// f64 layers
+impl Linear {
+    // Creates a Linear layer with weights drawn according to init from an RNG
+    // seeded with seed, so the same seed gives the same layer. The bias
+    // starts at zero.
+    pub fn with_init(input_size : usize, output_size : usize, init : Init, seed : u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (fan_in, fan_out) = (input_size as f64, output_size as f64);
+        let W = match init {
+            Init::Zeros => DMatrix::zeros(output_size, input_size),
+            Init::Xavier => {
+                let limit = (6.0 / (fan_in + fan_out)).sqrt();
+                DMatrix::from_fn(output_size, input_size, |_, _| rng.gen_range(-limit..limit))
+            },
+            Init::He => {
+                let std = (2.0 / fan_in).sqrt();
+                DMatrix::from_fn(output_size, input_size, |_, _| std * rng.sample::<f64, _>(StandardNormal))
+            },
+            Init::Uniform { low, high } => {
+                assert!(low < high, "Uniform init needs low < high, got [{}, {})", low, high);
+                DMatrix::from_fn(output_size, input_size, |_, _| rng.gen_range(low..high))
+            }
+        };
+        return Linear::from_parameters(W, DMatrix::zeros(output_size, 1));
+    }
+
+    // Creates a Linear layer whose weights are a scaled orthogonal matrix,
+    // which preserves the norm of signals (and gradients) passing through it
+    // and helps training deep or recurrent networks. W is obtained from the QR
+    // decomposition of a random Gaussian matrix; for non-square shapes the
+    // larger dimension is orthogonalized, so W^T W = gain^2 * I when
+    // C_out >= C_in and W W^T = gain^2 * I otherwise. The bias starts at zero.
+    #[cfg(feature = "std")]
+    pub fn new_orthogonal(input_size : usize, output_size : usize, gain : f64) -> Self {
+        let rows = input_size.max(output_size);
+        let cols = input_size.min(output_size);
+        let mut rng = crate::random::rng();
+        let G : DMatrix<f64> = DMatrix::from_fn(rows, cols, |_, _| rng.sample(StandardNormal));
+        let qr = G.qr();
+        let R = qr.r();
+        let mut Q = qr.q(); // rows x cols, orthonormal columns
+        // Fix the sign of each column so Q is uniformly distributed
+        for j in 0..cols {
+            if R[(j, j)] < 0.0 {
+                Q.column_mut(j).neg_mut();
+            }
+        }
+        let W = if output_size >= input_size { Q } else { Q.transpose() };
+        return Linear::from_parameters(gain * W, DMatrix::zeros(output_size, 1));
+    }
+
+    // Decorrelates the weight rows (one per output unit) of an initialized
+    // layer by Gram-Schmidt: every row has its projections onto the earlier
+    // rows removed and is then rescaled to its original norm, so the scale of
+    // the initialization is kept while the units start out detecting
+    // different directions. With C_out > C_in only the first C_in rows can be
+    // made orthogonal; a row that lies in the span of the earlier ones is
+    // left as it was.
+    pub fn decorrelate_init(&mut self) {
+        let mut basis : Vec<RowDVector<f64>> = Vec::new(); // Orthonormal rows so far
+        for i in 0..self.W.nrows() {
+            let row = self.W.row(i).into_owned();
+            let norm = row.norm();
+            let mut residual = row.clone();
+            for q in basis.iter() {
+                residual -= q * row.dot(q);
+            }
+            let residual_norm = residual.norm();
+            if residual_norm <= 1e-10 * norm {
+                continue;
+            }
+            let q = residual / residual_norm;
+            self.W.set_row(i, &(&q * norm));
+            basis.push(q);
+        }
+    }
+
+    // Zeros the round(sparsity * C_out * C_in) weights of smallest magnitude
+    // and records them in the prune mask, so they stay zero from then on.
+    // Weights pruned earlier are already zero and are counted towards the
+    // target. The bias is never pruned.
+    pub fn prune(&mut self, sparsity : f64) {
+        assert!((0.0..=1.0).contains(&sparsity), "Sparsity must be in [0, 1], got {}", sparsity);
+        let num_pruned = (sparsity * self.W.len() as f64).round() as usize;
+        let mut order : Vec<usize> = (0..self.W.len()).collect();
+        order.sort_by(|&i, &j| self.W[i].abs().total_cmp(&self.W[j].abs()));
+
+        let mut mask = self.prune_mask.take().unwrap_or_else(|| DMatrix::from_element(self.W.nrows(), self.W.ncols(), 1.0));
+        for &k in &order[..num_pruned] {
+            self.W[k] = 0.0;
+            mask[k] = 0.0;
+        }
+        self.prune_mask = Some(mask);
+    }
+
     // Helpful debug method to print the weights and biases of the layer.
+    #[cfg(feature = "std")]
     pub fn print_layer_params(&self) {
         println!("Linear Layer Parameters:");
         println!("Input Features (C_in): {}", self.W.ncols());
@@ -79,12 +305,352 @@ impl Linear {
     }
 }
 
+#[derive(Clone)]
+pub struct GradientReversal {
+    pub lambda : f64 // Scale of the reversed gradient
+}
+
+impl GradientReversal {
+    pub fn new(lambda : f64) -> Self {
+        GradientReversal {
+            lambda : lambda
+        }
+    }
+
+    // Changes λ, e.g. to ramp it up over the course of training
+    pub fn set_lambda(&mut self, lambda : f64) {
+        self.lambda = lambda;
+    }
+
+    pub fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+        return A.clone();
+    }
+
+    // ∂L/∂A = -λ * ∂L/∂Z
+    pub fn backward(&self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+        return -self.lambda * dLdZ;
+    }
+}
+
+impl ActivationFunction for GradientReversal {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return GradientReversal::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return GradientReversal::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "gradient_reversal";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![self.lambda];
+    }
+
+    // Only the backward pass is scaled, the forward pass is the identity
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+
+    // The forward pass is the identity, only the backward pass is reversed
+    fn jvp(&self, V : &DMatrix<f64>) -> DMatrix<f64> {
+        return V.clone();
+    }
+}
+
+#[derive(Clone)]
+pub struct GroupedLinear {
+    pub groups : usize, // Number of groups G
+    pub W : Vec<DMatrix<f64>>, // Weight block of each group (C_out / G x C_in / G)
+    pub b : DMatrix<f64>, // Bias (C_out x 1)
+    pub A : DMatrix<f64>, // Layer input (N x C_in)
+    pub dLdW : Vec<DMatrix<f64>>, // Gradient of the loss with respect to each block
+    pub dLdb : DMatrix<f64> // Gradient of the loss with respect to b
+}
+
+impl GroupedLinear {
+    // Group g maps input features [g * C_in / G, (g + 1) * C_in / G) to
+    // output features [g * C_out / G, (g + 1) * C_out / G). Both sizes must
+    // be divisible by the number of groups.
+    pub fn new(input_size : usize, output_size : usize, groups : usize) -> Self {
+        assert!(groups > 0, "GroupedLinear needs at least one group");
+        assert!(input_size % groups == 0 && output_size % groups == 0,
+                "Input size {} and output size {} must be divisible by {} groups", input_size, output_size, groups);
+        let W : Vec<DMatrix<f64>> = (0..groups).map(|_| random_matrix(output_size / groups, input_size / groups)).collect();
+        GroupedLinear {
+            groups : groups,
+            dLdW : W.iter().map(|w| DMatrix::zeros(w.nrows(), w.ncols())).collect(),
+            W : W,
+            b : random_matrix(output_size, 1),
+            A : DMatrix::zeros(0, 0),
+            dLdb : DMatrix::zeros(output_size, 1)
+        }
+    }
+
+    // The equivalent dense weight matrix (C_out x C_in), zero off the blocks
+    pub fn dense_weight(&self) -> DMatrix<f64> {
+        let (rows, cols) = self.W[0].shape();
+        let mut W = DMatrix::zeros(rows * self.groups, cols * self.groups);
+        for (g, block) in self.W.iter().enumerate() {
+            W.view_mut((g * rows, g * cols), (rows, cols)).copy_from(block);
+        }
+        return W;
+    }
+
+    // Z_g = A_g * W_g^T for every group g, then Z = [Z_1 ... Z_G] + ι_N * b
+    pub fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+        let (rows, cols) = self.W[0].shape();
+        assert_eq!(A.ncols(), cols * self.groups, "Expected {} input features, got {}", cols * self.groups, A.ncols());
+        self.A = A.clone();
+        let mut Z = DMatrix::zeros(A.nrows(), rows * self.groups);
+        for (g, block) in self.W.iter().enumerate() {
+            let Z_g = A.columns(g * cols, cols) * block.transpose();
+            Z.columns_mut(g * rows, rows).copy_from(&Z_g);
+        }
+        for mut row in Z.row_iter_mut() {
+            row += self.b.transpose();
+        }
+        return Z;
+    }
+
+    // Per group, as for Linear:
+    // ∂L/∂A_g = ∂L/∂Z_g * W_g,  ∂L/∂W_g = (∂L/∂Z_g)^T * A_g
+    // and ∂L/∂b = (∂L/∂Z)^T * ι_N
+    pub fn backward(&mut self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        let (rows, cols) = self.W[0].shape();
+        let mut dLdA = DMatrix::zeros(self.A.nrows(), self.A.ncols());
+        for (g, block) in self.W.iter().enumerate() {
+            let dLdZ_g = dLdZ.columns(g * rows, rows);
+            self.dLdW[g] = dLdZ_g.transpose() * self.A.columns(g * cols, cols);
+            dLdA.columns_mut(g * cols, cols).copy_from(&(dLdZ_g * block));
+        }
+        self.dLdb = dLdZ.transpose() * DMatrix::from_element(dLdZ.nrows(), 1, 1.0);
+        return dLdA;
+    }
+}
+
+impl Layer for GroupedLinear {
+    fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+        return GroupedLinear::forward(self, A);
+    }
+
+    fn backward(&mut self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+        return GroupedLinear::backward(self, dLdZ);
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        let mut parameters : Vec<&mut DMatrix<f64>> = self.W.iter_mut().collect();
+        parameters.push(&mut self.b);
+        return parameters;
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<f64>> {
+        let mut gradients : Vec<&DMatrix<f64>> = self.dLdW.iter().collect();
+        gradients.push(&self.dLdb);
+        return gradients;
+    }
+}
+
+#[derive(Clone)]
+pub struct LogSumExpPool {
+    pub temperature : f64, // Sharpness t, the pooling approaches the max as t grows
+    P : DMatrix<f64> // softmax(t * A) of every row from the last forward pass (N x C)
+}
+
+impl LogSumExpPool {
+    pub fn new(temperature : f64) -> Self {
+        assert!(temperature > 0.0, "temperature must be positive, got {}", temperature);
+        LogSumExpPool {
+            temperature : temperature,
+            P : DMatrix::zeros(0, 0)
+        }
+    }
+
+    // Maps A (N x C) to Z (N x 1). The row max m is factored out,
+    // y = m + (1/t) * ln Σ_j exp(t * (a_j - m)), so exp cannot overflow.
+    pub fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+        let t = self.temperature;
+        let mut P = A.clone();
+        let mut Z = DMatrix::zeros(A.nrows(), 1);
+        for (i, mut row) in P.row_iter_mut().enumerate() {
+            let max = row.max();
+            row.apply(|a| *a = (t * (*a - max)).exp());
+            let sum = row.sum();
+            row /= sum;
+            Z[(i, 0)] = max + sum.ln() / t;
+        }
+        self.P = P;
+        return Z;
+    }
+
+    // ∂y/∂a_j = softmax(t * a)_j, so ∂L/∂A = P ⊙ (∂L/∂Z * ι_C^T)
+    pub fn backward(&self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.P.is_empty(), "Forward pass not called before backward pass");
+        let mut dLdA = self.P.clone();
+        for (i, mut row) in dLdA.row_iter_mut().enumerate() {
+            row *= dLdZ[(i, 0)];
+        }
+        return dLdA;
+    }
+}
+
+impl ActivationFunction for LogSumExpPool {
+    fn forward(&mut self, Z : &DMatrix<f64>) -> DMatrix<f64> {
+        return LogSumExpPool::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA : &DMatrix<f64>) -> DMatrix<f64> {
+        return LogSumExpPool::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "logsumexp_pool";
+    }
+
+    fn config(&self) -> Vec<f64> {
+        return vec![self.temperature];
+    }
+
+    // The gradient softmax(t * a) has L2 norm at most 1
+    fn lipschitz_constant(&self) -> f64 {
+        return 1.0;
+    }
+
+    // Every sample is pooled into a single value
+    fn output_size(&self, _input_size : usize) -> Option<usize> {
+        return Some(1);
+    }
+
+    fn is_elementwise(&self) -> bool {
+        return false;
+    }
+
+    // The Jacobian of each row is the row vector p^T, so (∂Z/∂A) V = (P ⊙ V) * ι_C
+    fn jvp(&self, V : &DMatrix<f64>) -> DMatrix<f64> {
+        return self.P.component_mul(V) * DMatrix::from_element(V.ncols(), 1, 1.0);
+    }
+}
+
+// Activations are Layers over f64. A blanket impl over every scalar type
+// would overlap with the impl for Linear<T>, since other crates could make
+// Linear<T> an activation for their own T.
+impl<A: ActivationFunction> Layer for A {
+    fn forward(&mut self, A : &DMatrix<f64>) -> DMatrix<f64> {
+        return ActivationFunction::forward(self, A);
+    }
+
+    fn backward(&mut self, dLdZ : &DMatrix<f64>) -> DMatrix<f64> {
+        return ActivationFunction::backward_params(self, dLdZ);
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return ActivationFunction::parameters_mut(self);
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<f64>> {
+        return ActivationFunction::gradients(self);
+    }
+}
+
+impl<T: Real> Layer<T> for Linear<T> {
+    fn forward(&mut self, A : &DMatrix<T>) -> DMatrix<T> {
+        return Linear::forward(self, A);
+    }
+
+    fn backward(&mut self, dLdZ : &DMatrix<T>) -> DMatrix<T> {
+        return Linear::backward(self, dLdZ);
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return vec![&mut self.W, &mut self.b];
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<T>> {
+        return vec![&self.dLdW, &self.dLdb];
+    }
+}
+
 // Unit tests
 #[cfg(test)]
 mod tests {
     use super::*;
     use approx::assert_abs_diff_eq;
 
+    // Mean and (population) variance of all entries of m
+    fn moments(m : &DMatrix<f64>) -> (f64, f64) {
+        let mean = m.mean();
+        let variance = m.iter().map(|w| (w - mean) * (w - mean)).sum::<f64>() / m.len() as f64;
+        return (mean, variance);
+    }
+
+    #[test]
+    fn test_init_variances() {
+        // 400 x 250 = 10^5 weights, so the sample variance is within about 1% of the target
+        let (fan_in, fan_out) = (250, 400);
+        let he = Linear::with_init(fan_in, fan_out, Init::He, 0);
+        let (mean, variance) = moments(&he.W);
+        assert!(mean.abs() < 1e-3);
+        assert_abs_diff_eq!(variance, 2.0 / fan_in as f64, epsilon = 0.03 * 2.0 / fan_in as f64);
+
+        let xavier = Linear::with_init(fan_in, fan_out, Init::Xavier, 1);
+        let limit = (6.0 / (fan_in + fan_out) as f64).sqrt();
+        assert!(xavier.W.iter().all(|w| w.abs() <= limit));
+        let (_, variance) = moments(&xavier.W);
+        assert_abs_diff_eq!(variance, 2.0 / (fan_in + fan_out) as f64, epsilon = 0.03 * 2.0 / (fan_in + fan_out) as f64);
+        assert_eq!(xavier.b, DMatrix::zeros(fan_out, 1));
+    }
+
+    #[test]
+    fn test_grouped_linear_parameter_count() {
+        let mut grouped = GroupedLinear::new(12, 8, 4);
+        let dense : Linear = Linear::new(12, 8);
+        let grouped_weights : usize = grouped.W.iter().map(|w| w.len()).sum();
+        assert_eq!(grouped_weights * 4, dense.W.len());
+        assert_eq!(grouped.parameters_mut().len(), 5);
+        assert_eq!(grouped.b.shape(), dense.b.shape());
+    }
+
+    #[test]
+    fn test_grouped_linear_matches_block_diagonal_dense_layer() {
+        let mut grouped = GroupedLinear::new(4, 6, 2);
+        let mut dense = Linear::new(4, 6);
+        dense.W = grouped.dense_weight();
+        dense.b = grouped.b.clone();
+        assert_eq!(dense.W[(0, 3)], 0.0); // Off the blocks
+        let A = DMatrix::from_row_slice(3, 4, &[1.0, -2.0, 0.5, 3.0,
+                                                0.0, 1.0, -1.0, 2.0,
+                                                2.5, 0.5, 1.5, -0.5]);
+        assert_abs_diff_eq!(grouped.forward(&A), dense.forward(&A), epsilon = 1e-12);
+
+        let dLdZ = DMatrix::from_fn(3, 6, |i, j| (i as f64 - 1.0) * 0.5 + j as f64 * 0.1);
+        assert!(crate::debug::check_layer_gradient(&mut grouped, &A, &dLdZ, 1e-6) < 1e-6);
+        assert_abs_diff_eq!(grouped.backward(&dLdZ), dense.backward(&dLdZ), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_init_reproducible_and_ranges() {
+        assert_eq!(Linear::with_init(3, 4, Init::He, 7).W, Linear::with_init(3, 4, Init::He, 7).W);
+        assert_ne!(Linear::with_init(3, 4, Init::He, 7).W, Linear::with_init(3, 4, Init::He, 8).W);
+        assert_eq!(Linear::with_init(3, 4, Init::Zeros, 0).W, DMatrix::zeros(4, 3));
+        let uniform = Linear::with_init(30, 40, Init::Uniform { low: 2.0, high: 3.0 }, 0);
+        assert!(uniform.W.iter().all(|w| (2.0..3.0).contains(w)));
+    }
+
+    #[test]
+    fn test_with_init_leaves_the_master_seed_alone() {
+        // Seeded layers draw nothing from random::rng, so the unseeded layer
+        // built after them gets the same weights as without them
+        crate::random::set_seed(5);
+        let _ = Linear::with_init(3, 4, Init::He, 0);
+        let after : Linear = Linear::new(3, 4);
+        crate::random::set_seed(5);
+        assert_eq!(Linear::<f64>::new(3, 4).W, after.W);
+        crate::random::clear_seed();
+    }
+
     #[test]
     fn test_linear_forward() {
         let mut linear = Linear::new(2, 3);
@@ -140,4 +706,149 @@ mod tests {
         assert_abs_diff_eq!(linear.dLdb, expected_dLdb, epsilon = 1e-12);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_linear_prune() {
+        let mut linear = Linear::new(3, 2);
+        linear.W = DMatrix::from_row_slice(2, 3, &[0.5, -0.1, 2.0,
+                                                   -0.3, 1.0, 0.05]);
+        linear.prune(0.5);
+        let expected_W = DMatrix::from_row_slice(2, 3, &[0.5, 0.0, 2.0,
+                                                         0.0, 1.0, 0.0]);
+        assert_eq!(linear.W, expected_W);
+        assert_eq!(linear.prune_mask, Some(expected_W.map(|w| if w == 0.0 { 0.0 } else { 1.0 })));
+
+        // Pruned weights receive no gradient
+        let A = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                -1.0, 0.5, 2.0]);
+        let _ = linear.forward(&A);
+        let _ = linear.backward(&DMatrix::from_element(2, 2, 1.0));
+        assert_eq!(linear.dLdW, DMatrix::from_row_slice(2, 3, &[0.0, 0.0, 5.0,
+                                                                0.0, 2.5, 0.0]));
+    }
+
+    #[test]
+    fn test_linear_decorrelate_init() {
+        // Mean |cos| over all pairs of weight rows
+        let mean_abs_cosine = |W : &DMatrix<f64>| {
+            let mut total = 0.0;
+            let mut pairs = 0;
+            for i in 0..W.nrows() {
+                for j in (i + 1)..W.nrows() {
+                    total += (W.row(i).dot(&W.row(j)) / (W.row(i).norm() * W.row(j).norm())).abs();
+                    pairs += 1;
+                }
+            }
+            total / pairs as f64
+        };
+        // Uniform [0, 1) weights are all positive, so the rows are strongly correlated
+        let mut linear = Linear::new(6, 4);
+        let before = linear.W.clone();
+        linear.decorrelate_init();
+        assert!(mean_abs_cosine(&before) > 0.5);
+        assert!(mean_abs_cosine(&linear.W) < 1e-12);
+        for i in 0..4 {
+            assert_abs_diff_eq!(linear.W.row(i).norm(), before.row(i).norm(), epsilon = 1e-12);
+        }
+
+        // More output units than inputs: only the first C_in rows can be orthogonal
+        let mut linear = Linear::new(2, 5);
+        let before = mean_abs_cosine(&linear.W);
+        linear.decorrelate_init();
+        assert!(mean_abs_cosine(&linear.W) < before);
+        assert_abs_diff_eq!(linear.W.row(0).dot(&linear.W.row(1)), 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_linear_new_orthogonal_square() {
+        let gain = 2.0;
+        let linear = Linear::new_orthogonal(5, 5, gain);
+        let WtW = linear.W.transpose() * &linear.W;
+        assert_abs_diff_eq!(WtW, DMatrix::identity(5, 5) * gain * gain, epsilon = 1e-10);
+        assert_abs_diff_eq!(linear.b, DMatrix::zeros(5, 1), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_linear_new_orthogonal_rectangular() {
+        // Tall W has orthonormal columns, wide W has orthonormal rows
+        let tall = Linear::new_orthogonal(3, 6, 1.0);
+        assert_eq!(tall.W.shape(), (6, 3));
+        assert_abs_diff_eq!(tall.W.transpose() * &tall.W, DMatrix::identity(3, 3), epsilon = 1e-10);
+
+        let wide = Linear::new_orthogonal(6, 3, 1.0);
+        assert_eq!(wide.W.shape(), (3, 6));
+        assert_abs_diff_eq!(&wide.W * wide.W.transpose(), DMatrix::identity(3, 3), epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_linear_new_orthogonal_draws_one_generator() {
+        // new_orthogonal takes a single generator from random::rng, so the
+        // next generator is the second one derived from the seed
+        crate::random::set_seed(3);
+        let _ = Linear::new_orthogonal(4, 4, 1.0);
+        let next : u64 = crate::random::rng().gen();
+        crate::random::set_seed(3);
+        let _ = crate::random::rng();
+        assert_eq!(crate::random::rng().gen::<u64>(), next);
+        crate::random::clear_seed();
+    }
+
+    #[test]
+    fn test_gradient_reversal() {
+        let mut reversal = GradientReversal::new(0.5);
+        let A = DMatrix::from_row_slice(2, 2, &[1.0, -2.0,
+                                                3.0, 0.25]);
+        assert_eq!(reversal.forward(&A), A);
+
+        let dLdZ = DMatrix::from_row_slice(2, 2, &[2.0, -4.0,
+                                                   0.0, 1.0]);
+        assert_abs_diff_eq!(reversal.backward(&dLdZ), -0.5 * &dLdZ, epsilon = 1e-12);
+        reversal.set_lambda(2.0);
+        assert_abs_diff_eq!(reversal.backward(&dLdZ), -2.0 * &dLdZ, epsilon = 1e-12);
+        assert_eq!(ActivationFunction::jvp(&reversal, &dLdZ), dLdZ);
+    }
+
+    #[test]
+    fn test_logsumexp_pool_approaches_max() {
+        let A = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                3.0, 3.0, -1.0]);
+        let max = DMatrix::from_row_slice(2, 1, &[1.0, 3.0]);
+        let mut previous = f64::INFINITY;
+        for t in [1.0, 10.0, 100.0, 1000.0] {
+            let Z = LogSumExpPool::new(t).forward(&A);
+            // max <= y <= max + ln(C) / t
+            for i in 0..2 {
+                assert!(Z[(i, 0)] >= max[(i, 0)] && Z[(i, 0)] <= max[(i, 0)] + (3.0f64).ln() / t + 1e-12);
+            }
+            let gap = (Z - &max).amax();
+            assert!(gap < previous);
+            previous = gap;
+        }
+        assert!(previous < 1e-3);
+    }
+
+    #[test]
+    fn test_logsumexp_pool_gradient_check() {
+        let A = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.2, 0.3, -1.0]);
+        let dLdZ = DMatrix::from_row_slice(2, 1, &[2.0, -0.5]);
+        let mut pool = LogSumExpPool::new(2.0);
+        let _ = pool.forward(&A);
+        let dLdA = pool.backward(&dLdZ);
+
+        // L = Σ_i dLdZ_i * y_i, differentiated by central differences
+        let h = 1e-6;
+        for i in 0..2 {
+            for j in 0..3 {
+                let mut plus = A.clone();
+                plus[(i, j)] += h;
+                let mut minus = A.clone();
+                minus[(i, j)] -= h;
+                let L_plus = pool.forward(&plus).dot(&dLdZ);
+                let L_minus = pool.forward(&minus).dot(&dLdZ);
+                assert_abs_diff_eq!(dLdA[(i, j)], (L_plus - L_minus) / (2.0 * h), epsilon = 1e-8);
+            }
+        }
+        // Each row of the gradient sums to the upstream gradient
+        assert_abs_diff_eq!(dLdA.row(0).sum(), 2.0, epsilon = 1e-12);
+    }
+}
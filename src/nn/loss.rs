@@ -1,4 +1,5 @@
 use nalgebra::{DMatrix};
+use crate::scalar::{from_f64, Real};
 
 /**
     * Loss Functions
@@ -9,21 +10,67 @@ use nalgebra::{DMatrix};
     *
     * Currently, the following loss functions are implemented:
     * 1. Mean Squared Error (MSE) - L = 1/N * Σ_i (A_i - Y_i)^2
+    * 2. Cross Entropy (CrossEntropyLoss) - L = -1/N * Σ_i Σ_c Y_ic * log(A_ic), for
+    *    class probabilities A from a softmax and one-hot (or soft) targets Y
+    * 3. Generalized Cross Entropy (GeneralizedCrossEntropyLoss) -
+    *    L = 1/N * Σ_i (1 - p_i^q) / q, where p_i = Σ_c Y_ic * A_ic is the
+    *    probability of the target class. It tends to cross entropy as q → 0
+    *    and equals the mean absolute error of the target probability for
+    *    q = 1, which is robust to noisy labels.
+    *
+    * All of them implement the Loss trait, so a NeuralNetwork holds its loss
+    * as a Box<dyn Loss> and the loss can be chosen at construction time.
+    * MSELoss is another name for MSE. They are generic over the scalar type,
+    * like NeuralNetwork. Losses must be Send + Sync, like activations.
     *
 **/
 
 
+pub trait Loss<T: Real = f64>: LossClone<T> + Send + Sync {
+    // Computes the loss of predictions A (N x C) against targets Y, caching
+    // what backward needs
+    fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T;
+    // Gradient of the last forward's loss with respect to A
+    fn backward(&self) -> DMatrix<T>;
+    // Gradient of the last forward's loss with respect to the input of a
+    // row-wise softmax that produced A, for losses with a simpler fused
+    // form. The network uses it and skips the backward pass of its final
+    // softmax when it ends with one. None if the loss has no fused form.
+    fn backward_fused(&self) -> Option<DMatrix<T>> {
+        return None;
+    }
+}
+
+// Lets a boxed loss be cloned, like ActivationClone for activations
+pub trait LossClone<T: Real = f64> {
+    fn clone_box(&self) -> Box<dyn Loss<T>>;
+}
+
+impl<T: Real, L: Loss<T> + Clone + 'static> LossClone<T> for L {
+    fn clone_box(&self) -> Box<dyn Loss<T>> {
+        return Box::new(self.clone());
+    }
+}
+
+impl<T: Real> Clone for Box<dyn Loss<T>> {
+    fn clone(&self) -> Self {
+        return self.clone_box();
+    }
+}
+
+
 // Mean Squared Error Loss
-pub struct MSE {
-    A: DMatrix<f64>, // Model prediction
-    Y: DMatrix<f64>, // Desired output
+#[derive(Clone)]
+pub struct MSE<T: Real = f64> {
+    A: DMatrix<T>, // Model prediction
+    Y: DMatrix<T>, // Desired output
     N: usize, // Batch size
     C: usize, // Number of features in each sample
-    l_N: DMatrix<f64>, // Column vector of ones of size N (N x 1)
-    l_C: DMatrix<f64> // Column vector of ones of size C (C x 1)
+    l_N: DMatrix<T>, // Column vector of ones of size N (N x 1)
+    l_C: DMatrix<T> // Column vector of ones of size C (C x 1)
 }
 
-impl MSE {
+impl<T: Real> MSE<T> {
     pub fn new() -> Self {
         MSE {
             A : DMatrix::zeros(0, 0),
@@ -36,13 +83,13 @@ impl MSE {
     }
 
     // MSE Loss = 1/N * Σ_i (A_i - Y_i)^2
-    pub fn forward(&mut self, A: &DMatrix<f64>, Y: &DMatrix<f64>) -> f64 {
+    pub fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
         self.N = A.nrows();
         self.C = A.ncols();
         self.A = A.clone();
         self.Y = Y.clone();
-        self.l_N = DMatrix::from_element(self.N, 1, 1.0);
-        self.l_C = DMatrix::from_element(self.C, 1, 1.0);
+        self.l_N = DMatrix::from_element(self.N, 1, T::one());
+        self.l_C = DMatrix::from_element(self.C, 1, T::one());
 
         // Calculate the sum of squared errors
         let square_error = (&self.A - &self.Y).component_mul(&(&self.A - &self.Y));
@@ -51,20 +98,178 @@ impl MSE {
         // Then, the post multiplication of this product with l_N sums
         //  the row sums across columns to give the final sum as a single number.
         let sum_square_error = &self.l_N.transpose() * &square_error * &self.l_C;
-        let loss = sum_square_error[(0, 0)] / (self.N * self.C) as f64;
+        let loss = sum_square_error[(0, 0)] / from_f64((self.N * self.C) as f64);
         return loss;
     }
 
     // dLdA = 2 * (A - Y) / (N * C)
-    pub fn backward(&mut self) -> DMatrix<f64> {
-        let dLdA = 2.0 * (&self.A - &self.Y) / (self.N * self.C) as f64;
+    pub fn backward(&self) -> DMatrix<T> {
+        let dLdA = (&self.A - &self.Y) * from_f64::<T>(2.0) / from_f64::<T>((self.N * self.C) as f64);
         return dLdA;
     }
 }
 
+pub type MSELoss = MSE;
+
+impl<T: Real> Loss<T> for MSE<T> {
+    fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
+        return MSE::forward(self, A, Y);
+    }
+
+    fn backward(&self) -> DMatrix<T> {
+        return MSE::backward(self);
+    }
+}
+
+// Cross Entropy Loss. The softmax Jacobian is a full C x C matrix per sample,
+// but composed with cross entropy it collapses: for rows of Y that sum to 1,
+// ∂L/∂Z = (A - Y) / N with respect to the softmax input Z. backward_fused
+// returns this fused gradient, which must be passed to the layer before the
+// softmax, skipping softmax's own backward. NeuralNetwork does so when its
+// last activation is a row-wise softmax, and uses backward otherwise.
+#[derive(Clone)]
+pub struct CrossEntropyLoss<T: Real = f64> {
+    A: DMatrix<T>, // Predicted class probabilities (softmax output)
+    Y: DMatrix<T>, // Target class probabilities
+    N: usize // Batch size
+}
+
+impl<T: Real> CrossEntropyLoss<T> {
+    pub fn new() -> Self {
+        CrossEntropyLoss {
+            A : DMatrix::zeros(0, 0),
+            Y : DMatrix::zeros(0, 0),
+            N : 0
+        }
+    }
+
+    // L = -1/N * Σ_i Σ_c Y_ic * log(A_ic). Probabilities are clamped away
+    // from zero so that a confident wrong prediction gives a large but
+    // finite loss.
+    pub fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
+        assert_eq!(A.shape(), Y.shape(), "Predictions and targets must have the same shape");
+        self.N = A.nrows();
+        self.A = A.clone();
+        self.Y = Y.clone();
+        let eps: T = from_f64(1e-12);
+        let log_likelihood = A.iter().zip(Y.iter()).fold(T::zero(), |sum, (a, y)| sum + *y * a.max(eps).ln());
+        return -log_likelihood / from_f64(self.N as f64);
+    }
+
+    // dLdA = -Y / (N * A), with A clamped like in forward
+    pub fn backward(&self) -> DMatrix<T> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        let eps: T = from_f64(1e-12);
+        let N: T = from_f64(self.N as f64);
+        let dLdA = self.Y.zip_map(&self.A, |y, a| -y / (N * a.max(eps)));
+        return dLdA;
+    }
+
+    // Fused softmax + cross entropy gradient dLdZ = (A - Y) / N
+    pub fn backward_fused(&self) -> DMatrix<T> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        let dLdZ = (&self.A - &self.Y) / from_f64::<T>(self.N as f64);
+        return dLdZ;
+    }
+}
+
+impl<T: Real> Loss<T> for CrossEntropyLoss<T> {
+    fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
+        return CrossEntropyLoss::forward(self, A, Y);
+    }
+
+    fn backward(&self) -> DMatrix<T> {
+        return CrossEntropyLoss::backward(self);
+    }
+
+    fn backward_fused(&self) -> Option<DMatrix<T>> {
+        return Some(CrossEntropyLoss::backward_fused(self));
+    }
+}
+
+// Generalized Cross Entropy Loss. Like CrossEntropyLoss, it has a form fused
+// with a row-wise softmax: the gradient with respect to the softmax input is
+// the cross entropy gradient of each sample scaled by p_i^q (for one-hot
+// targets), so samples the model gives a low probability to, which are
+// likely mislabeled, contribute less than with cross entropy.
+#[derive(Clone)]
+pub struct GeneralizedCrossEntropyLoss<T: Real = f64> {
+    pub q: T, // Robustness parameter in (0, 1]
+    A: DMatrix<T>, // Predicted class probabilities (softmax output)
+    Y: DMatrix<T>, // Target class probabilities
+    P: DMatrix<T> // Probability of the target class of each sample (N x 1)
+}
+
+impl<T: Real> GeneralizedCrossEntropyLoss<T> {
+    pub fn new(q: T) -> Self {
+        assert!(q > T::zero() && q <= T::one(), "q must be in (0, 1], got {}", q);
+        GeneralizedCrossEntropyLoss {
+            q : q,
+            A : DMatrix::zeros(0, 0),
+            Y : DMatrix::zeros(0, 0),
+            P : DMatrix::zeros(0, 0)
+        }
+    }
+
+    // L = 1/N * Σ_i (1 - p_i^q) / q with p_i = Σ_c Y_ic * A_ic. The target
+    // probabilities are clamped away from zero like in CrossEntropyLoss.
+    pub fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
+        assert_eq!(A.shape(), Y.shape(), "Predictions and targets must have the same shape");
+        let eps: T = from_f64(1e-12);
+        self.A = A.clone();
+        self.Y = Y.clone();
+        // Post multiplying with a column of ones sums each row
+        let l_C = DMatrix::from_element(A.ncols(), 1, T::one());
+        self.P = (A.component_mul(Y) * l_C).map(|p| p.max(eps));
+        let q = self.q;
+        let loss = self.P.iter().fold(T::zero(), |sum, p| sum + (T::one() - p.powf(q)) / q);
+        return loss / from_f64(A.nrows() as f64);
+    }
+
+    // dLdA_ic = -Y_ic * p_i^(q-1) / N
+    pub fn backward(&self) -> DMatrix<T> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        let N: T = from_f64(self.A.nrows() as f64);
+        let q = self.q;
+        let dLdA = DMatrix::from_fn(self.A.nrows(), self.A.ncols(), |i, c| {
+            -self.Y[(i, c)] * self.P[i].powf(q - T::one()) / N
+        });
+        return dLdA;
+    }
+
+    // Fused softmax + generalized cross entropy gradient
+    // dLdZ_ic = p_i^(q-1) * A_ic * (p_i - Y_ic) / N,
+    // i.e. p_i^q * (A_ic - Y_ic) / N for one-hot targets
+    pub fn backward_fused(&self) -> DMatrix<T> {
+        assert!(!self.A.is_empty(), "Forward pass not called before backward pass");
+        let N: T = from_f64(self.A.nrows() as f64);
+        let q = self.q;
+        let dLdZ = DMatrix::from_fn(self.A.nrows(), self.A.ncols(), |i, c| {
+            let p = self.P[i];
+            p.powf(q - T::one()) * self.A[(i, c)] * (p - self.Y[(i, c)]) / N
+        });
+        return dLdZ;
+    }
+}
+
+impl<T: Real> Loss<T> for GeneralizedCrossEntropyLoss<T> {
+    fn forward(&mut self, A: &DMatrix<T>, Y: &DMatrix<T>) -> T {
+        return GeneralizedCrossEntropyLoss::forward(self, A, Y);
+    }
+
+    fn backward(&self) -> DMatrix<T> {
+        return GeneralizedCrossEntropyLoss::backward(self);
+    }
+
+    fn backward_fused(&self) -> Option<DMatrix<T>> {
+        return Some(GeneralizedCrossEntropyLoss::backward_fused(self));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nn::activation::Softmax;
     use approx::assert_abs_diff_eq;
 
     #[test]
@@ -105,4 +310,89 @@ mod tests {
 
         assert_abs_diff_eq!(dLdA, expected_dLdA, epsilon = 1e-8);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cross_entropy_forward() {
+        let mut ce = CrossEntropyLoss::new();
+        let A = DMatrix::from_row_slice(2, 3, &[0.7, 0.2, 0.1,
+                                                0.25, 0.25, 0.5]);
+        let Y = DMatrix::from_row_slice(2, 3, &[1.0, 0.0, 0.0,
+                                                0.0, 0.0, 1.0]);
+        let loss = ce.forward(&A, &Y);
+        assert_abs_diff_eq!(loss, -(0.7f64.ln() + 0.5f64.ln()) / 2.0, epsilon = 1e-12);
+        // A zero probability on the target class stays finite
+        assert!(ce.forward(&DMatrix::from_row_slice(1, 2, &[1.0, 0.0]), &DMatrix::from_row_slice(1, 2, &[0.0, 1.0])).is_finite());
+    }
+
+    #[test]
+    fn test_cross_entropy_fused_gradient_matches_softmax_backward() {
+        let mut softmax = Softmax::new();
+        let Z = DMatrix::from_row_slice(2, 3, &[1.0, -0.5, 2.0,
+                                                0.0, 3.0, -1.0]);
+        let Y = DMatrix::from_row_slice(2, 3, &[0.0, 0.0, 1.0,
+                                                1.0, 0.0, 0.0]);
+        let A = softmax.forward(&Z);
+        let mut ce = CrossEntropyLoss::new();
+        let _ = ce.forward(&A, &Y);
+        let fused = ce.backward_fused();
+
+        // Unfused: ∂L/∂A = -Y / (N * A), then through the softmax Jacobian
+        let dLdA = ce.backward();
+        assert_abs_diff_eq!(dLdA, -Y.component_div(&A) / 2.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(fused, softmax.backward(&dLdA), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_cross_entropy_with_large_equal_logits() {
+        let mut softmax: Softmax = Softmax::new();
+        let A = softmax.forward(&DMatrix::from_row_slice(1, 3, &[1000.0, 1000.0, 1000.0]));
+        assert!(A.iter().all(|a| a.is_finite()));
+        assert_abs_diff_eq!(A, DMatrix::from_element(1, 3, 1.0 / 3.0), epsilon = 1e-12);
+
+        let mut ce = CrossEntropyLoss::new();
+        let loss = ce.forward(&A, &DMatrix::from_row_slice(1, 3, &[0.0, 1.0, 0.0]));
+        assert_abs_diff_eq!(loss, 3f64.ln(), epsilon = 1e-12);
+        assert!(ce.backward().iter().all(|g| g.is_finite()));
+    }
+
+    #[test]
+    fn test_generalized_cross_entropy_small_q_matches_cross_entropy() {
+        let mut softmax = Softmax::new();
+        let A = softmax.forward(&DMatrix::from_row_slice(2, 3, &[1.0, -0.5, 2.0,
+                                                                 0.0, 3.0, -1.0]));
+        let Y = DMatrix::from_row_slice(2, 3, &[0.0, 0.0, 1.0,
+                                                1.0, 0.0, 0.0]);
+        let mut ce = CrossEntropyLoss::new();
+        let mut gce = GeneralizedCrossEntropyLoss::new(1e-6);
+        assert_abs_diff_eq!(gce.forward(&A, &Y), ce.forward(&A, &Y), epsilon = 1e-4);
+        assert_abs_diff_eq!(gce.backward(), ce.backward(), epsilon = 1e-4);
+        assert_abs_diff_eq!(gce.backward_fused(), ce.backward_fused(), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_generalized_cross_entropy_downweights_low_confidence() {
+        let mut softmax = Softmax::new();
+        // The first sample is predicted confidently, the second one is not
+        let A = softmax.forward(&DMatrix::from_row_slice(2, 3, &[3.0, 0.0, 0.0,
+                                                                 0.0, 2.0, 0.0]));
+        let Y = DMatrix::from_row_slice(2, 3, &[1.0, 0.0, 0.0,
+                                                1.0, 0.0, 0.0]);
+        let mut gce: GeneralizedCrossEntropyLoss = GeneralizedCrossEntropyLoss::new(0.7);
+        let _ = gce.forward(&A, &Y);
+        let fused = gce.backward_fused();
+
+        // Unfused: ∂L/∂A = -Y p^(q-1) / N, then through the softmax Jacobian
+        let p = DMatrix::from_row_slice(2, 1, &[A[(0, 0)], A[(1, 0)]]);
+        let dLdA = gce.backward();
+        assert_abs_diff_eq!(dLdA, DMatrix::from_fn(2, 3, |i, c| -Y[(i, c)] * p[i].powf(0.7 - 1.0) / 2.0), epsilon = 1e-12);
+        assert_abs_diff_eq!(fused, softmax.backward(&dLdA), epsilon = 1e-12);
+
+        // Relative to the confident sample, the unconfident one gets a
+        // smaller gradient than with cross entropy
+        let mut ce = CrossEntropyLoss::new();
+        let _ = ce.forward(&A, &Y);
+        let ce_grad = ce.backward_fused();
+        let ratio = |G: &DMatrix<f64>| G.row(1).norm() / G.row(0).norm();
+        assert!(ratio(&fused) < 0.5 * ratio(&ce_grad), "{} vs {}", ratio(&fused), ratio(&ce_grad));
+    }
+}
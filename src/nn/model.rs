@@ -1,7 +1,14 @@
 use nalgebra::{DMatrix};
+use std::collections::HashMap;
+use crate::error::DnnError;
+use crate::data::DataLoader;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
 use crate::nn::layers::Linear;
-use crate::nn::loss::MSE;
-use crate::nn::activation::ReLU;
+use crate::nn::loss::Loss;
+use crate::nn::activation::ActivationFunction;
+use crate::scalar::{to_f64, Real};
 
 /**
     * We can think of a neural network (NN) as a mathematical function
@@ -23,42 +30,181 @@ use crate::nn::activation::ReLU;
     * where each layer is a linear layer followed by an activation function.
 **/
 
-pub struct NeuralNetwork {
-    pub layers: Vec<Box<Linear>>,
-    pub activations: Vec<Box<ReLU>>, // Invariant: activations.len() == layers.len()
-    pub loss: Box<MSE>,
+// Summary of the outputs of one activation function over a batch
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActivationStats {
+    pub name: String, // Name of the activation function
+    pub mean: f64, // Mean of the activated outputs
+    pub std: f64, // Standard deviation of the activated outputs
+    pub saturated_fraction: f64, // Fraction of outputs in a saturated region (always 0 for unbounded activations)
 }
 
-impl NeuralNetwork {
+// Estimated memory use of a model in bytes, see memory_footprint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryReport {
+    pub parameter_bytes: usize, // Weights and biases
+    pub gradient_bytes: usize, // ∂L/∂W and ∂L/∂b of every layer
+    pub optimizer_state_bytes: usize, // Two moment buffers per parameter, as kept by Adam-style optimizers
+    pub activation_bytes: usize // Values cached during forward for the backward pass
+}
+
+impl MemoryReport {
+    pub fn total(&self) -> usize {
+        return self.parameter_bytes + self.gradient_bytes + self.optimizer_state_bytes + self.activation_bytes;
+    }
+}
+
+#[derive(Clone)]
+pub struct NeuralNetwork<T: Real = f64> {
+    pub layers: Vec<Box<Linear<T>>>,
+    pub activations: Vec<Box<dyn ActivationFunction<T>>>, // Invariant: activations.len() <= layers.len()
+    pub loss: Box<dyn Loss<T>>,
+    training: bool, // Whether stochastic activations (e.g. Dropout) are in training mode
+    collect_stats: bool, // Whether forward records activation statistics
+    stats: Vec<ActivationStats>, // Statistics of the most recent forward pass
+    output: DMatrix<T>, // Output of the most recent forward pass
+}
+
+impl<T: Real> NeuralNetwork<T> {
     // Constructor for the NeuralNetwork struct. Creates a new NeuralNetwork
     // model with the specified layers and loss function.
-    pub fn new(layers: Vec<Box<Linear>>, activations: Vec<Box<ReLU>>, loss: Box<MSE>) -> Self {
+    pub fn new(layers: Vec<Box<Linear<T>>>, activations: Vec<Box<dyn ActivationFunction<T>>>, loss: Box<dyn Loss<T>>) -> Self {
         NeuralNetwork {
             layers: layers,
             activations: activations,
             loss: loss,
+            training: true,
+            collect_stats: false,
+            stats: Vec::new(),
+            output: DMatrix::zeros(0, 0),
+        }
+    }
+
+    // Turns the collection of activation statistics on or off. While on,
+    // every forward pass records the ActivationStats of each activation,
+    // which helps diagnose saturated or dead units. Collection is off by
+    // default since it costs an extra pass over every activation output.
+    pub fn set_collect_stats(&mut self, collect: bool) {
+        self.collect_stats = collect;
+        if !collect {
+            self.stats.clear();
         }
     }
 
+    // Activation statistics of the most recent forward pass, one entry per
+    // activation in order. Empty unless collection is turned on.
+    pub fn activation_stats(&self) -> &[ActivationStats] {
+        return &self.stats;
+    }
+
 
     // During forward propagation, we apply a sequence of linear transformations
     // and activation functions to the input data x to obtain the output data y.
     // That is, y = fNN (x) = fL (fL-1 ( ... f2 (f1 (x)) ... )). The forward
     // method computes the output of the neural network given the input data x.
-    pub fn forward(&mut self, x: &DMatrix<f64>) -> DMatrix<f64> {
+    pub fn forward(&mut self, x: &DMatrix<T>) -> DMatrix<T> {
+        if self.collect_stats {
+            self.stats.clear();
+        }
         let mut A = x.clone();
         for i in 0..self.layers.len() {
             A = self.layers[i].forward(&A);
             // If there is no activation function, we return the output
             // of the previous layer as the network's output
-            if i > self.activations.len() - 1 {
-                return A;
+            if i >= self.activations.len() {
+                break;
             }
             A = self.activations[i].forward(&A);
+            if self.collect_stats {
+                let stats = NeuralNetwork::compute_stats(self.activations[i].as_ref(), &A);
+                self.stats.push(stats);
+            }
+        }
+        self.output = A.clone();
+        return A;
+    }
+
+    // Inference-only forward pass for serving: computes the same output as
+    // forward in eval mode (see eval), but takes &self and caches nothing
+    // for backward, so it can be called through a shared reference, e.g. by
+    // serving threads sharing one model behind an Arc. Dropout passes its
+    // input through regardless of the mode.
+    pub fn predict(&self, x: &DMatrix<T>) -> DMatrix<T> {
+        let mut A = x.clone();
+        for i in 0..self.layers.len() {
+            A = self.layers[i].predict(&A);
+            if i < self.activations.len() {
+                A = self.activations[i].predict(&A);
+            }
         }
         return A;
     }
 
+    // Jacobian-vector product of the network output with respect to its
+    // input at x, in the direction v_input (shaped like x): the directional
+    // derivative lim (f(x + h v) - f(x)) / h. The tangent is propagated
+    // forward alongside the activations, layer by layer, so the cost is
+    // about one extra forward pass and no backward pass.
+    pub fn jvp(&mut self, x: &DMatrix<T>, v_input: &DMatrix<T>) -> DMatrix<T> {
+        assert_eq!(x.shape(), v_input.shape(), "The tangent must have the shape of the input");
+        let mut A = x.clone();
+        let mut V = v_input.clone();
+        for i in 0..self.layers.len() {
+            A = self.layers[i].forward(&A);
+            V = self.layers[i].jvp(&V);
+            if i >= self.activations.len() {
+                break;
+            }
+            A = self.activations[i].forward(&A);
+            V = self.activations[i].jvp(&V);
+        }
+        return V;
+    }
+
+    // Mean, (population) standard deviation and saturated fraction of A
+    fn compute_stats(activation: &dyn ActivationFunction<T>, A: &DMatrix<T>) -> ActivationStats {
+        let n = A.len().max(1) as f64;
+        let mean = A.iter().map(|a| to_f64(*a)).sum::<f64>() / n;
+        let variance = A.iter().map(|a| (to_f64(*a) - mean) * (to_f64(*a) - mean)).sum::<f64>() / n;
+        let saturated = A.iter().filter(|a| activation.is_saturated(**a)).count();
+        return ActivationStats {
+            name: activation.name().to_string(),
+            mean: mean,
+            std: variance.sqrt(),
+            saturated_fraction: saturated as f64 / n,
+        };
+    }
+
+    // Training mode, the default: stochastic activations such as Dropout
+    // are active
+    pub fn train(&mut self) {
+        self.set_training(true);
+    }
+
+    // Inference mode: stochastic activations are deterministic pass-throughs
+    pub fn eval(&mut self) {
+        self.set_training(false);
+    }
+
+    pub fn is_training(&self) -> bool {
+        return self.training;
+    }
+
+    // Puts every activation in training or inference mode
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+        for activation in self.activations.iter_mut() {
+            activation.set_training(training);
+        }
+    }
+
+    // Computes the loss of the network on a batch without touching any
+    // gradients. Lower is better.
+    pub fn evaluate(&mut self, x: &DMatrix<T>, y: &DMatrix<T>) -> T {
+        let y_pred = self.forward(x);
+        return self.loss.forward(&y_pred, y);
+    }
+
     // During backward propagation, we compute the gradients of the loss with
     // respect to the parameters of the neural network. Given the gradients
     // of the loss with respect to the output of the neural network, we can
@@ -66,14 +212,1706 @@ impl NeuralNetwork {
     // each layer in the neural network. The backward method computes the
     // gradients of the loss with respect to the parameters of the neural
     // network using the chain rule of calculus.
+    //
+    // If the network ends with a row-wise softmax and the loss has a form
+    // fused with it (cross entropy), the fused gradient with respect to the
+    // softmax input is used and the final softmax is skipped.
     pub fn backward(&mut self) {
-        let mut dLdA = self.loss.backward();
-        let mut dLdZ = DMatrix::zeros(0,0);
-        for i in (0..self.layers.len()).rev() {
-            if i <= self.activations.len() - 1 {
-                dLdZ = self.activations[i].backward(&dLdA);
+        if self.ends_with_row_softmax() {
+            if let Some(dLdZ) = self.loss.backward_fused() {
+                let _ = self.propagate(&dLdZ, true);
+                return;
             }
+        }
+        let dLdA = self.loss.backward();
+        let _ = self.propagate(&dLdA, false);
+    }
+
+    // Whether the activation after the last layer is a row-wise softmax
+    fn ends_with_row_softmax(&self) -> bool {
+        return self.activations.len() == self.layers.len()
+            && self.activations.last().is_some_and(|a| a.is_row_softmax());
+    }
+
+    // Backpropagates a given gradient of the loss with respect to the
+    // network output through every layer, storing the parameter gradients,
+    // and returns the gradient with respect to the network input. This lets
+    // the network be used as a part of a larger model, whose loss is not the
+    // network's own.
+    pub fn backward_from(&mut self, dLdA: &DMatrix<T>) -> DMatrix<T> {
+        return self.propagate(dLdA, false);
+    }
+
+    // Backpropagation through all layers, optionally starting below the
+    // final activation
+    fn propagate(&mut self, dLdA: &DMatrix<T>, skip_last_activation: bool) -> DMatrix<T> {
+        let mut dLdA = dLdA.clone();
+        for i in (0..self.layers.len()).rev() {
+            let skip = skip_last_activation && i + 1 == self.layers.len();
+            // Layers without an activation pass the gradient straight through
+            let dLdZ = if i < self.activations.len() && !skip {
+                self.activations[i].backward_params(&dLdA)
+            } else {
+                dLdA.clone()
+            };
             dLdA = self.layers[i].backward(&dLdZ);
         }
+        return dLdA;
+    }
+
+    // Runs the forward pass and the loss on a batch, and returns the gradient
+    // of the loss with respect to the network's output, ∂L/∂y. Unlike backward,
+    // nothing is propagated through the layers, so the layer gradients are left
+    // untouched. Useful when the network is one piece of a larger custom graph.
+    pub fn output_grad(&mut self, x: &DMatrix<T>, y: &DMatrix<T>) -> DMatrix<T> {
+        let y_pred = self.forward(x);
+        let _ = self.loss.forward(&y_pred, y);
+        return self.loss.backward();
+    }
+
+    // Total number of learnable parameters (see parameters)
+    pub fn num_parameters(&self) -> usize {
+        return self.parameters().iter().map(|param| param.len()).sum();
+    }
+
+    // Every learnable parameter of the model: the weights and biases of each
+    // layer (W, b, in layer order), followed by the parameters of each
+    // activation (e.g. the β of SwishBeta, the γ and β of BatchNorm).
+    // gradients (and requires_grad) follow the same order, so optimizers and
+    // other code that treats all parameters alike can zip them.
+    pub fn parameters(&self) -> Vec<&DMatrix<T>> {
+        let mut params = Vec::new();
+        for layer in self.layers.iter() {
+            params.push(&layer.W);
+            params.push(&layer.b);
+        }
+        for activation in self.activations.iter() {
+            params.extend(activation.parameters());
+        }
+        return params;
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        let mut params = Vec::new();
+        for layer in self.layers.iter_mut() {
+            let layer = &mut **layer;
+            params.push(&mut layer.W);
+            params.push(&mut layer.b);
+        }
+        for activation in self.activations.iter_mut() {
+            params.extend(activation.parameters_mut());
+        }
+        return params;
+    }
+
+    // Gradients of the last backward pass, in the order of parameters
+    pub fn gradients(&self) -> Vec<&DMatrix<T>> {
+        let mut grads = Vec::new();
+        for layer in self.layers.iter() {
+            grads.push(&layer.dLdW);
+            grads.push(&layer.dLdb);
+        }
+        for activation in self.activations.iter() {
+            grads.extend(activation.gradients());
+        }
+        return grads;
+    }
+
+    pub fn gradients_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        let mut grads = Vec::new();
+        for layer in self.layers.iter_mut() {
+            let layer = &mut **layer;
+            grads.push(&mut layer.dLdW);
+            grads.push(&mut layer.dLdb);
+        }
+        for activation in self.activations.iter_mut() {
+            grads.extend(activation.gradients_mut());
+        }
+        return grads;
+    }
+
+    // Non-learnable state of the activations (see
+    // ActivationFunction::buffers), in activation order
+    pub fn buffers(&self) -> Vec<&DMatrix<T>> {
+        return self.activations.iter().flat_map(|activation| activation.buffers()).collect();
+    }
+
+    pub fn buffers_mut(&mut self) -> Vec<&mut DMatrix<T>> {
+        return self.activations.iter_mut().flat_map(|activation| activation.buffers_mut()).collect();
+    }
+
+    // Whether optimizers should update each parameter, in the order of
+    // parameters. False for the frozen parameters of layers and activations
+    // (see set_requires_grad).
+    pub fn requires_grad(&self) -> Vec<bool> {
+        let mut flags = Vec::new();
+        for layer in self.layers.iter() {
+            flags.push(layer.requires_grad_W);
+            flags.push(layer.requires_grad_b);
+        }
+        for activation in self.activations.iter() {
+            flags.extend(activation.requires_grad());
+        }
+        return flags;
+    }
+
+    // Clips the gradients of the last backward pass by their global L2 norm:
+    // if the norm over all gradients together (of the layers and of the
+    // activations) exceeds max_norm, every gradient is scaled by the same
+    // factor max_norm / norm, so the direction of the update is kept.
+    // Returns the norm before clipping.
+    pub fn clip_grad_norm(&mut self, max_norm: T) -> T {
+        assert!(max_norm > T::zero(), "max_norm must be positive, got {}", max_norm);
+        let norm = self.gradients().iter()
+            .fold(T::zero(), |sum, grad| sum + grad.norm_squared())
+            .sqrt();
+        if norm > max_norm {
+            let scale = max_norm / norm;
+            for grad in self.gradients_mut() {
+                *grad *= scale;
+            }
+        }
+        return norm;
+    }
+}
+
+// Training utilities and analysis tools work on f64 models
+impl NeuralNetwork {
+    // Pearson correlation between the units of layer layer_index (after its
+    // activation) across the samples of the last forward pass, as a
+    // C_out x C_out matrix. Pairs of units with a correlation near ±1 carry
+    // the same information and one of them can be pruned. Units that are
+    // constant over the batch (e.g. dead ReLUs) have no defined correlation;
+    // their off-diagonal entries are 0 and the diagonal is always 1.
+    pub fn activation_correlation(&self, layer_index: usize) -> DMatrix<f64> {
+        assert!(layer_index < self.layers.len(), "Layer index {} out of range ({} layers)", layer_index, self.layers.len());
+        // The output of a layer is cached as the input of the next one
+        let A = if layer_index + 1 < self.layers.len() { &self.layers[layer_index + 1].A } else { &self.output };
+        assert!(!A.is_empty(), "Forward pass not called before activation_correlation");
+        let n = A.nrows() as f64;
+        let mean = A.row_sum() / n;
+        let centered = DMatrix::from_fn(A.nrows(), A.ncols(), |i, j| A[(i, j)] - mean[(0, j)]);
+        let covariance = centered.transpose() * &centered / n;
+        let eps = 1e-12;
+        return DMatrix::from_fn(A.ncols(), A.ncols(), |j, k| {
+            if j == k {
+                return 1.0;
+            }
+            let scale = (covariance[(j, j)] * covariance[(k, k)]).sqrt();
+            if scale > eps { covariance[(j, k)] / scale } else { 0.0 }
+        });
+    }
+
+    // Monte Carlo dropout: runs n_samples forward passes with dropout active
+    // (whatever the current mode) and returns the mean prediction and the
+    // per-entry variance across the passes, an estimate of the model's
+    // uncertainty. The previous mode is restored afterwards.
+    pub fn mc_dropout_predict(&mut self, x: &DMatrix<f64>, n_samples: usize) -> (DMatrix<f64>, DMatrix<f64>) {
+        assert!(n_samples > 0, "mc_dropout_predict needs at least one sample");
+        let predictions = self.mc_dropout_samples(x, n_samples);
+
+        let n = n_samples as f64;
+        let (rows, cols) = predictions[0].shape();
+        let mean = predictions.iter().fold(DMatrix::zeros(rows, cols), |sum, p| sum + p) / n;
+        let mut variance = DMatrix::zeros(mean.nrows(), mean.ncols());
+        for p in predictions.iter() {
+            let diff = p - &mean;
+            variance += diff.component_mul(&diff);
+        }
+        return (mean, variance / n);
+    }
+
+    // Prediction interval for regression from Monte Carlo dropout: runs
+    // n_samples forward passes with dropout active and returns, per output
+    // entry, the alpha/2 and 1 - alpha/2 empirical quantiles of the passes
+    // as (lower, upper). E.g. alpha = 0.1 gives a 90% interval. Without
+    // dropout both bounds equal the point prediction.
+    pub fn regression_interval(&mut self, x: &DMatrix<f64>, n_samples: usize, alpha: f64) -> (DMatrix<f64>, DMatrix<f64>) {
+        assert!(n_samples > 0, "regression_interval needs at least one sample");
+        assert!(alpha > 0.0 && alpha < 1.0, "alpha must be in (0, 1), got {}", alpha);
+        let predictions = self.mc_dropout_samples(x, n_samples);
+
+        let (rows, cols) = predictions[0].shape();
+        let rank = |q: f64| ((q * (n_samples - 1) as f64).round() as usize).min(n_samples - 1);
+        let (lower_rank, upper_rank) = (rank(alpha / 2.0), rank(1.0 - alpha / 2.0));
+        let mut lower = DMatrix::zeros(rows, cols);
+        let mut upper = DMatrix::zeros(rows, cols);
+        for i in 0..rows {
+            for j in 0..cols {
+                let mut values: Vec<f64> = predictions.iter().map(|p| p[(i, j)]).collect();
+                values.sort_by(|a, b| a.total_cmp(b));
+                lower[(i, j)] = values[lower_rank];
+                upper[(i, j)] = values[upper_rank];
+            }
+        }
+        return (lower, upper);
+    }
+
+    // n_samples forward passes with dropout active, restoring the previous
+    // mode afterwards
+    fn mc_dropout_samples(&mut self, x: &DMatrix<f64>, n_samples: usize) -> Vec<DMatrix<f64>> {
+        let was_training = self.training;
+        self.set_training(true);
+        let predictions: Vec<DMatrix<f64>> = (0..n_samples).map(|_| self.forward(x)).collect();
+        self.set_training(was_training);
+        return predictions;
+    }
+
+    // Returns, for each layer, the ratio between the size of a plain gradient
+    // descent update and the size of the weights, ||lr * ∂L/∂W|| / (||W|| + eps),
+    // using Frobenius norms. Valid after a backward pass. A healthy learning
+    // rate gives ratios around 1e-3; much larger means the weights are being
+    // overwritten each step, much smaller means they are barely learning.
+    pub fn update_ratios(&self, lr: f64) -> Vec<f64> {
+        let eps = 1e-12;
+        return self.layers.iter()
+            .map(|layer| (lr * &layer.dLdW).norm() / (layer.W.norm() + eps))
+            .collect();
+    }
+
+    // Estimates the memory needed to train the model on batches of
+    // batch_size samples, with every value an f64 (8 bytes). Each layer
+    // caches its N x C_in input and its activation the N x C_out output, so
+    // the activation estimate grows linearly with the batch size. Optimizer
+    // state assumes two buffers per parameter (Adam, AdaBelief, RAdam); SGD
+    // with momentum needs half of it. Temporaries of the backward pass and
+    // the matrix headers are not counted.
+    pub fn memory_footprint(&self, batch_size: usize) -> MemoryReport {
+        let value_bytes = std::mem::size_of::<f64>();
+        let parameter_bytes = self.num_parameters() * value_bytes;
+        let cached_values: usize = self.layers.iter()
+            .map(|layer| batch_size * (layer.W.ncols() + layer.W.nrows()))
+            .sum();
+        return MemoryReport {
+            parameter_bytes: parameter_bytes,
+            gradient_bytes: parameter_bytes,
+            optimizer_state_bytes: 2 * parameter_bytes,
+            activation_bytes: cached_values * value_bytes
+        };
+    }
+
+    // All parameters flattened into a single vector, in the order of
+    // parameters: for each layer the entries of W followed by those of b,
+    // then the parameters of the activations (each matrix in nalgebra's
+    // column-major storage order).
+    pub fn flat_parameters(&self) -> Vec<f64> {
+        let mut params = Vec::with_capacity(self.num_parameters());
+        for param in self.parameters() {
+            params.extend(param.iter());
+        }
+        return params;
+    }
+
+    // Inverse of flat_parameters: overwrites every parameter from a flat vector
+    // laid out in the same order.
+    pub fn set_flat_parameters(&mut self, params: &[f64]) {
+        assert_eq!(params.len(), self.num_parameters(),
+                   "Expected {} parameters, got {}", self.num_parameters(), params.len());
+        let mut offset = 0;
+        for param in self.parameters_mut() {
+            let n = param.len();
+            param.as_mut_slice().copy_from_slice(&params[offset..offset + n]);
+            offset += n;
+        }
+    }
+
+    // All gradients of the last backward pass as a single vector, in the
+    // same order as flat_parameters.
+    pub fn flat_gradients(&self) -> Vec<f64> {
+        let mut grads = Vec::with_capacity(self.num_parameters());
+        for grad in self.gradients() {
+            grads.extend(grad.iter());
+        }
+        return grads;
+    }
+
+    // Gradient of the loss of every single sample (row) of a batch, each as
+    // a flat vector in the order of flat_parameters. Runs one forward and
+    // backward pass per sample, so it is only meant for small batches or
+    // analysis, not for training.
+    pub fn per_sample_gradients(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> Vec<Vec<f64>> {
+        let mut grads = Vec::with_capacity(x.nrows());
+        for i in 0..x.nrows() {
+            let _ = self.output_grad(&x.rows(i, 1).into_owned(), &y.rows(i, 1).into_owned());
+            self.backward();
+            grads.push(self.flat_gradients());
+        }
+        return grads;
+    }
+
+    // Flat gradient of the loss on (x, y) at the current parameters
+    pub(crate) fn loss_gradient(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> Vec<f64> {
+        let _ = self.output_grad(x, y);
+        self.backward();
+        return self.flat_gradients();
+    }
+
+    // Hessian-vector product H v of the loss on (x, y), with v in the layout
+    // of flat_parameters. Approximated by central differences of gradients,
+    // H v ≈ (∇L(θ + εv) - ∇L(θ - εv)) / (2ε), which needs two backward
+    // passes and never forms H. The parameters are restored afterwards.
+    pub fn hessian_vector_product(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>, v: &[f64]) -> Vec<f64> {
+        assert_eq!(v.len(), self.num_parameters(), "Expected one entry per parameter");
+        let params = self.flat_parameters();
+        let v_norm = v.iter().map(|v_k| v_k * v_k).sum::<f64>().sqrt();
+        if v_norm == 0.0 {
+            return vec![0.0; v.len()];
+        }
+        // Step along the unit vector v / ‖v‖ and rescale, for a well-sized ε
+        let eps = 1e-5 * (1.0 + params.iter().map(|p| p * p).sum::<f64>().sqrt());
+        let shifted = |sign: f64| -> Vec<f64> {
+            params.iter().zip(v.iter()).map(|(p, v_k)| p + sign * eps * v_k / v_norm).collect()
+        };
+        self.set_flat_parameters(&shifted(1.0));
+        let grad_plus = self.loss_gradient(x, y);
+        self.set_flat_parameters(&shifted(-1.0));
+        let grad_minus = self.loss_gradient(x, y);
+        self.set_flat_parameters(&params);
+        return grad_plus.iter().zip(grad_minus.iter())
+            .map(|(g_plus, g_minus)| (g_plus - g_minus) / (2.0 * eps) * v_norm)
+            .collect();
+    }
+
+    // Estimates the largest eigenvalue (in magnitude) of the Hessian of the
+    // loss on (x, y), often called the sharpness of the minimum, by n_iter
+    // iterations of power iteration on Hessian-vector products. Returns the
+    // Rayleigh quotient v^T H v of the final unit vector v. Near a minimum
+    // the Hessian is positive semi-definite, so this is the top eigenvalue.
+    pub fn top_hessian_eigenvalue(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>, n_iter: usize) -> f64 {
+        // A fixed random start, almost surely not orthogonal to the top eigenvector
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut v: Vec<f64> = (0..self.num_parameters()).map(|_| StandardNormal.sample(&mut rng)).collect();
+        let mut eigenvalue = 0.0;
+        for _ in 0..n_iter.max(1) {
+            let norm = v.iter().map(|v_k| v_k * v_k).sum::<f64>().sqrt();
+            if norm == 0.0 {
+                return 0.0;
+            }
+            v.iter_mut().for_each(|v_k| *v_k /= norm);
+            let Hv = self.hessian_vector_product(x, y, &v);
+            eigenvalue = v.iter().zip(Hv.iter()).map(|(a, b)| a * b).sum();
+            v = Hv;
+        }
+        return eigenvalue;
+    }
+
+    // Empirical gradient noise scale B_noise = tr(Σ) / ‖G‖^2 (McCandlish et
+    // al., 2018), the batch size above which larger batches stop speeding up
+    // training. G is the true gradient and Σ the per-sample gradient
+    // covariance. Both are estimated from the squared gradient norms of two
+    // batches of sizes B_small < B_big, using E‖G_B‖^2 = ‖G‖^2 + tr(Σ) / B:
+    //   ‖G‖^2 ≈ (B_big ‖G_big‖^2 - B_small ‖G_small‖^2) / (B_big - B_small)
+    //   tr(Σ) ≈ (‖G_small‖^2 - ‖G_big‖^2) / (1 / B_small - 1 / B_big)
+    // Both estimates are unbiased but noisy, so results from a single pair of
+    // batches are best averaged over several pairs (the result can even be
+    // negative for an unlucky pair).
+    pub fn gradient_noise_scale(&mut self, small_batch: (&DMatrix<f64>, &DMatrix<f64>),
+                                large_batch: (&DMatrix<f64>, &DMatrix<f64>)) -> f64 {
+        let B_small = small_batch.0.nrows() as f64;
+        let B_big = large_batch.0.nrows() as f64;
+        assert!(B_small < B_big, "The small batch must have fewer samples than the large batch");
+        let squared_norm = |grad: Vec<f64>| grad.iter().map(|g| g * g).sum::<f64>();
+        let G_small = squared_norm(self.loss_gradient(small_batch.0, small_batch.1));
+        let G_big = squared_norm(self.loss_gradient(large_batch.0, large_batch.1));
+
+        let true_grad = (B_big * G_big - B_small * G_small) / (B_big - B_small);
+        let trace_sigma = (G_small - G_big) / (1.0 / B_small - 1.0 / B_big);
+        return trace_sigma / true_grad;
+    }
+
+    // Diagonal of the empirical Fisher information over a dataset, i.e. the
+    // mean over all samples of the squared per-sample gradient, one entry per
+    // parameter in the order of flat_parameters. It measures how sensitive
+    // the loss is to each parameter, e.g. for elastic weight consolidation.
+    pub fn fisher_diagonal(&mut self, loader: &mut DataLoader) -> Vec<f64> {
+        let mut fisher = vec![0.0; self.num_parameters()];
+        for (x, y) in loader.batches() {
+            for grad in self.per_sample_gradients(&x, &y) {
+                for (f, g) in fisher.iter_mut().zip(grad.iter()) {
+                    *f += g * g;
+                }
+            }
+        }
+        let n = loader.num_samples() as f64;
+        return fisher.into_iter().map(|f| f / n).collect();
+    }
+
+    // Gradient signal-to-noise ratio of every layer over one epoch of the
+    // loader. With g_j the per-batch gradients of the layer's parameters
+    // (weights and bias together), the signal is the norm of their mean over
+    // the batches and the noise the root of their summed variances:
+    //   SNR = ‖mean_b g‖ / sqrt(Σ_j var_b(g_j) + ε)
+    // Layers whose gradient points the same way in every batch get a high
+    // SNR, layers dominated by batch-to-batch noise a low one. ε keeps the
+    // ratio finite when the gradients do not vary at all, e.g. for identical
+    // batches, and a layer without any gradient reports 0. Needs at least
+    // two batches.
+    pub fn grad_snr(&mut self, loader: &mut DataLoader) -> Vec<f64> {
+        let grads: Vec<Vec<f64>> = loader.batches().iter().map(|(x, y)| self.loss_gradient(x, y)).collect();
+        assert!(grads.len() > 1, "grad_snr needs at least two batches, got {}", grads.len());
+        let n = grads.len() as f64;
+        let mut mean = vec![0.0; self.num_parameters()];
+        for grad in grads.iter() {
+            for (m, g) in mean.iter_mut().zip(grad.iter()) {
+                *m += g / n;
+            }
+        }
+        let mut variance = vec![0.0; self.num_parameters()];
+        for grad in grads.iter() {
+            for ((v, m), g) in variance.iter_mut().zip(mean.iter()).zip(grad.iter()) {
+                *v += (g - m) * (g - m) / (n - 1.0);
+            }
+        }
+
+        let eps = 1e-12;
+        let mut snr = Vec::with_capacity(self.layers.len());
+        let mut offset = 0;
+        for layer in self.layers.iter() {
+            let size = layer.W.len() + layer.b.len();
+            let signal = mean[offset..offset + size].iter().map(|m| m * m).sum::<f64>().sqrt();
+            let noise = (variance[offset..offset + size].iter().sum::<f64>() + eps).sqrt();
+            snr.push(signal / noise);
+            offset += size;
+        }
+        return snr;
+    }
+
+    // Applies f in place to every weight and bias matrix, layer by layer (W
+    // then b), e.g. for pruning, noise injection or rescaling.
+    pub fn map_parameters<F: Fn(&mut DMatrix<f64>)>(&mut self, f: F) {
+        for layer in self.layers.iter_mut() {
+            f(&mut layer.W);
+            f(&mut layer.b);
+        }
+    }
+
+    // Condition number σ_max / σ_min of each weight matrix, from its singular
+    // values. Large values mean the layer stretches some input directions far
+    // more than others, which slows down gradient descent. Matrices that are
+    // singular up to rounding (σ_min <= σ_max * max(C_out, C_in) * machine
+    // epsilon) report infinity.
+    pub fn weight_condition_numbers(&self) -> Vec<f64> {
+        return self.layers.iter().map(|layer| {
+            let singular_values = layer.W.clone().svd(false, false).singular_values;
+            let max = singular_values.max();
+            let min = singular_values.min();
+            let tolerance = max * layer.W.nrows().max(layer.W.ncols()) as f64 * f64::EPSILON;
+            if min <= tolerance { f64::INFINITY } else { max / min }
+        }).collect();
+    }
+
+    // Indices of the layers whose gradient norm ‖(∂L/∂W, ∂L/∂b)‖ is below
+    // threshold times the gradient norm of the output (last) layer, i.e.
+    // where the gradient has vanished on its way back through the network.
+    // Uses the gradients of the last backward pass. Empty for a model
+    // without layers.
+    pub fn vanishing_gradient_report(&self, threshold: f64) -> Vec<usize> {
+        if self.layers.is_empty() {
+            return Vec::new();
+        }
+        let norms: Vec<f64> = self.layers.iter()
+            .map(|layer| (layer.dLdW.norm_squared() + layer.dLdb.norm_squared()).sqrt())
+            .collect();
+        let output_norm = norms[norms.len() - 1];
+        return norms.iter().enumerate()
+            .filter(|(_, norm)| **norm < threshold * output_norm)
+            .map(|(i, _)| i)
+            .collect();
+    }
+
+    // Checks that the model is well-formed: there is an activation after
+    // every layer, or after every layer but the last, and the output of
+    // every layer (after its activation) has as many features as the next
+    // layer takes. Loaders call it so that a corrupt file is rejected
+    // instead of panicking in forward.
+    pub(crate) fn check_architecture(&self) -> Result<(), DnnError> {
+        let (num_layers, num_activations) = (self.layers.len(), self.activations.len());
+        if num_activations > num_layers || num_activations + 1 < num_layers {
+            return Err(DnnError::InvalidFormat(format!("{} activations for {} layers", num_activations, num_layers)));
+        }
+        for i in 0..num_layers.saturating_sub(1) {
+            let size = self.layers[i].W.nrows();
+            let output_size = self.activations[i].output_size(size).ok_or_else(|| DnnError::InvalidFormat(
+                format!("activation {} ({}) cannot take {} features", i, self.activations[i].name(), size)))?;
+            let next_size = self.layers[i + 1].W.ncols();
+            if output_size != next_size {
+                return Err(DnnError::InvalidFormat(
+                    format!("layer {} takes {} features, but layer {} gives {}", i + 1, next_size, i, output_size)));
+            }
+        }
+        if num_activations == num_layers && num_layers > 0 {
+            let (i, size) = (num_layers - 1, self.layers[num_layers - 1].W.nrows());
+            if self.activations[i].output_size(size).is_none() {
+                return Err(DnnError::InvalidFormat(
+                    format!("activation {} ({}) cannot take {} features", i, self.activations[i].name(), size)));
+            }
+        }
+        return Ok(());
+    }
+
+    // Certified upper bound on the Lipschitz constant of the network in the
+    // L2 norm: the product of the spectral norms σ_max(W) of the layers and
+    // of the Lipschitz constants of the activations. Biases do not change
+    // the constant. The bound is infinite if an activation has no known
+    // finite constant, and usually far from tight for deep networks.
+    pub fn lipschitz_upper_bound(&self) -> f64 {
+        let layers: f64 = self.layers.iter()
+            .map(|layer| layer.W.clone().svd(false, false).singular_values.max())
+            .product();
+        let activations: f64 = self.activations.iter().map(|a| a.lipschitz_constant()).product();
+        return layers * activations;
+    }
+
+    // Histogram of the magnitudes |g| of every parameter gradient (see
+    // gradients) of the last backward pass. The range [0, max |g|] is split
+    // into bins of equal width; each entry is (lower edge of the bin, count).
+    // The last bin includes max |g|. A spike at zero points to dead units, a
+    // second mode far from the bulk to a few exploding parameters.
+    pub fn gradient_histogram(&self, bins: usize) -> Vec<(f64, usize)> {
+        assert!(bins > 0, "gradient_histogram needs at least one bin");
+        let magnitudes: Vec<f64> = self.flat_gradients().iter().map(|g| g.abs()).collect();
+        let max = magnitudes.iter().cloned().fold(0.0, f64::max);
+        let width = if max > 0.0 { max / bins as f64 } else { 1.0 };
+        let mut counts = vec![0; bins];
+        for g in magnitudes.iter() {
+            let bin = ((g / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        return counts.into_iter().enumerate().map(|(k, count)| (k as f64 * width, count)).collect();
+    }
+
+    // Net2Net widening: grows layer index from C_out to new_width output
+    // units without changing the function the network computes. Each new
+    // unit j copies the incoming weights and bias of unit j mod C_out, and
+    // the outgoing weights (columns of the next layer) of every unit and its
+    // copies are divided by the number of copies, so the next layer receives
+    // exactly the same input as before. The activation after the layer must
+    // act element-wise (see ActivationFunction::is_elementwise), which rules
+    // out e.g. Softmax. Optimizers hold per-parameter state, so a model
+    // should be widened before an optimizer is created for it.
+    pub fn widen_layer(&mut self, index: usize, new_width: usize) {
+        assert!(index + 1 < self.layers.len(), "Layer {} has no next layer to rescale", index);
+        if let Some(activation) = self.activations.get(index) {
+            assert!(activation.is_elementwise(), "Cannot widen layer {} followed by {}, which is not element-wise", index, activation.name());
+        }
+        let old_width = self.layers[index].W.nrows();
+        assert!(new_width >= old_width, "Cannot narrow layer {} from {} to {} units", index, old_width, new_width);
+        let source: Vec<usize> = (0..new_width).map(|j| j % old_width).collect();
+        let mut copies = vec![0usize; old_width];
+        for &u in &source {
+            copies[u] += 1;
+        }
+
+        let layer = &mut self.layers[index];
+        layer.W = layer.W.select_rows(&source);
+        layer.b = layer.b.select_rows(&source);
+        layer.prune_mask = layer.prune_mask.as_ref().map(|mask| mask.select_rows(&source));
+        layer.dLdW = DMatrix::zeros(0, 0);
+        layer.dLdb = DMatrix::zeros(0, 0);
+
+        let next = &mut self.layers[index + 1];
+        next.W = next.W.select_columns(&source);
+        for (j, &u) in source.iter().enumerate() {
+            next.W.column_mut(j).unscale_mut(copies[u] as f64);
+        }
+        next.prune_mask = next.prune_mask.as_ref().map(|mask| mask.select_columns(&source));
+        next.dLdW = DMatrix::zeros(0, 0);
+    }
+
+    // Magnitude pruning: zeros the given fraction of smallest-magnitude weights
+    // in every layer and masks their gradients, so they stay zero during
+    // subsequent training. See Linear::prune.
+    pub fn prune_weights(&mut self, sparsity: f64) {
+        for layer in self.layers.iter_mut() {
+            layer.prune(sparsity);
+        }
+    }
+
+    // Blends the parameters of two models with the same architecture,
+    // θ = (1 - t) * θ_a + t * θ_b, e.g. to average fine-tuned models ("model
+    // soups") or to probe the loss along the line between two solutions.
+    // The result is a copy of a (activations and loss included) with the
+    // blended parameters, those of the activations included (see
+    // parameters). Models with different layer shapes or activations are
+    // rejected.
+    pub fn interpolate(a: &NeuralNetwork, b: &NeuralNetwork, t: f64) -> Result<NeuralNetwork, DnnError> {
+        if a.layers.len() != b.layers.len() {
+            return Err(DnnError::ArchitectureMismatch(
+                format!("{} layers vs {} layers", a.layers.len(), b.layers.len())));
+        }
+        let names_a: Vec<&str> = a.activations.iter().map(|act| act.name()).collect();
+        let names_b: Vec<&str> = b.activations.iter().map(|act| act.name()).collect();
+        if names_a != names_b {
+            return Err(DnnError::ArchitectureMismatch(
+                format!("activations {:?} vs {:?}", names_a, names_b)));
+        }
+        for (i, (layer_a, layer_b)) in a.layers.iter().zip(b.layers.iter()).enumerate() {
+            if layer_a.W.shape() != layer_b.W.shape() {
+                return Err(DnnError::ShapeMismatch {
+                    key: format!("layer{}.weight", i), expected: layer_a.W.shape(), found: layer_b.W.shape() });
+            }
+        }
+
+        let mut blended = a.clone();
+        for (param, param_b) in blended.parameters_mut().into_iter().zip(b.parameters()) {
+            *param = (1.0 - t) * &*param + t * param_b;
+        }
+        return Ok(blended);
+    }
+
+    // Exports the parameters and buffers as a state dict, using the same
+    // keys as load_state_dict. The matrices are copies, so modifying them
+    // does not affect the model until the dict is loaded back.
+    pub fn state_dict(&self) -> HashMap<String, DMatrix<f64>> {
+        let values = self.parameters().into_iter().chain(self.buffers());
+        return self.state_keys().into_iter().zip(values).map(|(key, value)| (key, value.clone())).collect();
+    }
+
+    // State dict key of every parameter, in the order of parameters, and
+    // then of every buffer, in the order of buffers: "layer{i}.weight" and
+    // "layer{i}.bias" for the W and b of layer i, "activation{i}.param{j}"
+    // and "activation{i}.buffer{j}" for the j-th parameter and buffer of
+    // activation i
+    fn state_keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        for i in 0..self.layers.len() {
+            keys.push(format!("layer{}.weight", i));
+            keys.push(format!("layer{}.bias", i));
+        }
+        for (i, activation) in self.activations.iter().enumerate() {
+            keys.extend((0..activation.parameters().len()).map(|j| format!("activation{}.param{}", i, j)));
+        }
+        for (i, activation) in self.activations.iter().enumerate() {
+            keys.extend((0..activation.buffers().len()).map(|j| format!("activation{}.buffer{}", i, j)));
+        }
+        return keys;
+    }
+
+    // Loads parameters and buffers from a state dict, a map from name to
+    // value, using the same keys as state_dict: "layer{i}.weight" and
+    // "layer{i}.bias" for the W and b of layer i, "activation{i}.param{j}"
+    // and "activation{i}.buffer{j}" for the parameters and buffers (e.g. the
+    // running statistics of BatchNorm) of activation i. Every key of the
+    // model must be present with a matching shape and no other keys may
+    // appear; otherwise an error naming the offending keys is returned and
+    // the model is left unchanged.
+    pub fn load_state_dict(&mut self, mut dict: HashMap<String, DMatrix<f64>>) -> Result<(), DnnError> {
+        let keys = self.state_keys();
+        let mut missing = Vec::new();
+        for (key, current) in keys.iter().zip(self.parameters().into_iter().chain(self.buffers())) {
+            match dict.get(key) {
+                None => missing.push(key.clone()),
+                Some(value) if value.shape() != current.shape() => {
+                    return Err(DnnError::ShapeMismatch { key: key.clone(), expected: current.shape(), found: value.shape() });
+                }
+                Some(_) => {}
+            }
+        }
+        if !missing.is_empty() {
+            return Err(DnnError::MissingKeys(missing));
+        }
+        if dict.len() > keys.len() {
+            let mut unexpected: Vec<String> = dict.keys()
+                .filter(|key| !keys.contains(key))
+                .cloned()
+                .collect();
+            unexpected.sort();
+            return Err(DnnError::UnexpectedKeys(unexpected));
+        }
+
+        // The buffer keys follow the parameter keys
+        let (param_keys, buffer_keys) = keys.split_at(self.parameters().len());
+        for (key, param) in param_keys.iter().zip(self.parameters_mut()) {
+            *param = dict.remove(key).unwrap();
+        }
+        for (key, buffer) in buffer_keys.iter().zip(self.buffers_mut()) {
+            *buffer = dict.remove(key).unwrap();
+        }
+        return Ok(());
+    }
+
+    // Sets whether the optimizers update every parameter whose state dict key
+    // matches pattern, where * matches any run of characters. E.g.
+    // "layer0.*" selects both parameters of the first layer, "*.bias" every
+    // bias, "activation1.*" the parameters of the second activation (e.g.
+    // the γ and β of a BatchNorm, "activation1.param0" and
+    // "activation1.param1") and "*" the whole model. Returns the number of
+    // matched parameters.
+    pub fn set_requires_grad(&mut self, pattern: &str, requires: bool) -> usize {
+        let mut matched = 0;
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            if glob_match(pattern, &format!("layer{}.weight", i)) {
+                layer.requires_grad_W = requires;
+                matched += 1;
+            }
+            if glob_match(pattern, &format!("layer{}.bias", i)) {
+                layer.requires_grad_b = requires;
+                matched += 1;
+            }
+        }
+        for (i, activation) in self.activations.iter_mut().enumerate() {
+            for j in 0..activation.parameters().len() {
+                if glob_match(pattern, &format!("activation{}.param{}", i, j)) {
+                    activation.set_requires_grad(j, requires);
+                    matched += 1;
+                }
+            }
+        }
+        return matched;
+    }
+
+    // Returns references to the gradients (∂L/∂W, ∂L/∂b) of the layer at the
+    // given index. These are only meaningful after a backward pass, and are
+    // exactly the values an optimizer uses to update that layer.
+    pub fn grad_at_layer(&self, index: usize) -> (&DMatrix<f64>, &DMatrix<f64>) {
+        assert!(index < self.layers.len(), "Layer index {} out of range ({} layers)", index, self.layers.len());
+        let layer = &self.layers[index];
+        return (&layer.dLdW, &layer.dLdb);
+    }
+}
+
+// Whether text matches pattern, where * matches any (possibly empty) run of
+// characters and every other character matches itself.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    // Match the middle parts greedily, left to right, between the fixed ends
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false
+        }
+    }
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{Axis, Identity, ReLU, ScaledTanh, Sigmoid, Softmax, SwishBeta, Tanh};
+    use crate::nn::batchnorm::BatchNorm;
+    use crate::nn::dropout::Dropout;
+    use crate::nn::loss::{CrossEntropyLoss, MSE};
+    use crate::optim::adam::Adam;
+    use crate::optim::sgd::SGD;
+    use approx::assert_abs_diff_eq;
+
+    fn small_network() -> NeuralNetwork {
+        let layers = vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 2))];
+        let activations: Vec<Box<dyn ActivationFunction>> = vec![Box::new(ReLU::new()), Box::new(ReLU::new())];
+        return NeuralNetwork::new(layers, activations, Box::new(MSE::new()));
+    }
+
+    #[test]
+    fn test_grad_at_layer_matches_sgd_update() {
+        let x = DMatrix::from_row_slice(4, 2, &[-4.0, -3.0,
+                                                11.8, 3.2,
+                                                -7.13, 1.56,
+                                                0.132, 4.5896]);
+        let y = DMatrix::from_row_slice(4, 2, &[0.0, 1.0,
+                                                0.0, 1.0,
+                                                1.0, 0.0,
+                                                0.0, 1.0]);
+        let lr = 0.01;
+        let mut optim = SGD::new(small_network(), lr, 0.0);
+        let W_before: Vec<DMatrix<f64>> = optim.model.layers.iter().map(|l| l.W.clone()).collect();
+        let b_before: Vec<DMatrix<f64>> = optim.model.layers.iter().map(|l| l.b.clone()).collect();
+        optim.update(&x, &y);
+
+        for i in 0..optim.model.layers.len() {
+            let (dLdW, dLdb) = optim.model.grad_at_layer(i);
+            assert_abs_diff_eq!(optim.model.layers[i].W, &W_before[i] - lr * dLdW, epsilon = 1e-12);
+            assert_abs_diff_eq!(optim.model.layers[i].b, &b_before[i] - lr * dLdb, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_output_grad_matches_loss_backward() {
+        let mut network = small_network();
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 3.0,
+                                                -1.5, 0.25]);
+        let y = DMatrix::from_row_slice(3, 2, &[0.0, 1.0,
+                                                1.0, 0.0,
+                                                0.5, 0.5]);
+        let dLdy = network.output_grad(&x, &y);
+
+        let y_pred = network.forward(&x);
+        let mut mse = MSE::new();
+        let _ = mse.forward(&y_pred, &y);
+        assert_abs_diff_eq!(dLdy, mse.backward(), epsilon = 1e-12);
+        assert_eq!(dLdy.shape(), (3, 2));
+    }
+
+    #[test]
+    fn test_update_ratios() {
+        let mut network = small_network();
+        network.layers[0].W = DMatrix::from_row_slice(3, 2, &[3.0, 0.0,
+                                                              0.0, 4.0,
+                                                              0.0, 0.0]); // ||W|| = 5
+        network.layers[0].dLdW = DMatrix::from_row_slice(3, 2, &[0.0, 6.0,
+                                                                 8.0, 0.0,
+                                                                 0.0, 0.0]); // ||dLdW|| = 10
+        network.layers[1].W = DMatrix::from_element(2, 3, 1.0);
+        network.layers[1].dLdW = DMatrix::zeros(2, 3);
+
+        let ratios = network.update_ratios(1e-3);
+        assert_eq!(ratios.len(), 2);
+        assert_abs_diff_eq!(ratios[0], 1e-3 * 10.0 / 5.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(ratios[1], 0.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_flat_parameters_round_trip() {
+        let mut network = small_network();
+        assert_eq!(network.num_parameters(), (2 * 3 + 3) + (3 * 2 + 2));
+        let params = network.flat_parameters();
+        assert_eq!(params.len(), network.num_parameters());
+        assert_eq!(params[0], network.layers[0].W[(0, 0)]);
+        assert_eq!(params[6], network.layers[0].b[0]);
+
+        let shifted: Vec<f64> = params.iter().map(|p| p + 1.0).collect();
+        network.set_flat_parameters(&shifted);
+        assert_eq!(network.flat_parameters(), shifted);
+    }
+
+    #[test]
+    fn test_flat_parameters_include_activation_parameters() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                             vec![Box::new(SwishBeta::new(1.5))], Box::new(MSE::new()));
+        assert_eq!(network.num_parameters(), (2 * 3 + 3) + (3 + 1) + 1);
+        let params = network.flat_parameters();
+        assert_eq!(params[params.len() - 1], 1.5);
+
+        let mut shifted = params.clone();
+        shifted[params.len() - 1] = 0.5;
+        network.set_flat_parameters(&shifted);
+        assert_eq!(network.activations[0].parameters()[0][(0, 0)], 0.5);
+
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, -0.5,
+                                                0.3, 2.0]);
+        let y = DMatrix::from_row_slice(2, 1, &[1.0, -1.0]);
+        let _ = network.output_grad(&x, &y);
+        network.backward();
+        let grads = network.flat_gradients();
+        assert_eq!(grads.len(), network.num_parameters());
+        assert_eq!(grads[grads.len() - 1], network.activations[0].gradients()[0][(0, 0)]);
+    }
+
+    #[test]
+    fn test_map_parameters_prunes_small_weights() {
+        let mut network = small_network();
+        let params: Vec<f64> = (0..network.num_parameters()).map(|k| (k as f64 - 8.0) / 10.0).collect();
+        network.set_flat_parameters(&params);
+
+        let threshold = 0.35;
+        network.map_parameters(|M| M.apply(|w| if w.abs() < threshold { *w = 0.0; }));
+
+        let pruned = network.flat_parameters();
+        let expected: Vec<f64> = params.iter().map(|w| if w.abs() < threshold { 0.0 } else { *w }).collect();
+        assert_eq!(pruned, expected);
+        // Parameters range over -0.8, -0.7, ..., 0.8, of which -0.3, ..., 0.3 are pruned
+        assert_eq!(pruned.iter().filter(|w| **w == 0.0).count(), 7);
+    }
+
+    #[test]
+    fn test_prune_weights_survive_training() {
+        let mut network = small_network();
+        network.prune_weights(0.5);
+        // 3 of the 6 weights of each layer are pruned
+        for layer in network.layers.iter() {
+            assert_eq!(layer.W.iter().filter(|w| **w == 0.0).count(), 3);
+        }
+
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let y = DMatrix::from_row_slice(3, 2, &[1.0, 0.0,
+                                                0.0, 1.0,
+                                                2.0, 2.0]);
+        let masks: Vec<DMatrix<f64>> = network.layers.iter().map(|l| l.prune_mask.clone().unwrap()).collect();
+        let mut optim = SGD::new(network, 0.1, 0.9);
+        for _ in 0..5 {
+            optim.update(&x, &y);
+        }
+        for (layer, mask) in optim.model.layers.iter().zip(masks.iter()) {
+            for k in 0..mask.len() {
+                if mask[k] == 0.0 {
+                    assert_eq!(layer.W[k], 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_activation_stats_report_saturation() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 1))],
+                                             vec![Box::new(Tanh::new()), Box::new(ReLU::new())],
+                                             Box::new(MSE::new()));
+        // Large weights drive every tanh unit deep into saturation
+        network.layers[0].W = DMatrix::from_row_slice(4, 2, &[50.0, 0.0,
+                                                              0.0, -50.0,
+                                                              30.0, 30.0,
+                                                              -40.0, 10.0]);
+        network.layers[0].b = DMatrix::zeros(4, 1);
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, 2.0,
+                                                -1.0, 0.5,
+                                                2.0, -0.5]);
+
+        let _ = network.forward(&x);
+        assert!(network.activation_stats().is_empty());
+
+        network.set_collect_stats(true);
+        let _ = network.forward(&x);
+        let stats = network.activation_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].name, "tanh");
+        assert!(stats[0].saturated_fraction > 0.9, "{:?}", stats[0]);
+        assert_eq!(stats[1].name, "relu");
+        assert_eq!(stats[1].saturated_fraction, 0.0);
+
+        // Statistics are recomputed, not appended, on every forward pass
+        let _ = network.forward(&x);
+        assert_eq!(network.activation_stats().len(), 2);
+    }
+
+    #[test]
+    fn test_activation_stats_mean_and_std() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        network.layers[0].W = DMatrix::from_element(1, 1, 1.0);
+        network.layers[0].b = DMatrix::zeros(1, 1);
+        network.set_collect_stats(true);
+        let _ = network.forward(&DMatrix::from_row_slice(4, 1, &[-1.0, 1.0, 2.0, 3.0]));
+        // ReLU outputs 0, 1, 2, 3
+        let stats = &network.activation_stats()[0];
+        assert_abs_diff_eq!(stats.mean, 1.5, epsilon = 1e-12);
+        assert_abs_diff_eq!(stats.std, 1.25f64.sqrt(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_fisher_diagonal_matches_manual_accumulation() {
+        let mut network = small_network();
+        let x = DMatrix::from_row_slice(5, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0,
+                                                2.0, 1.0,
+                                                0.0, -1.0]);
+        let y = DMatrix::from_fn(5, 2, |i, j| (i + j) as f64 * 0.5);
+        let mut loader = DataLoader::new(x.clone(), y.clone(), 2, true, 0);
+        let fisher = network.fisher_diagonal(&mut loader);
+        assert_eq!(fisher.len(), network.num_parameters());
+        assert!(fisher.iter().all(|f| *f >= 0.0));
+
+        // Manual accumulation, one sample at a time
+        let mut expected = vec![0.0; network.num_parameters()];
+        for i in 0..5 {
+            let _ = network.output_grad(&x.rows(i, 1).into_owned(), &y.rows(i, 1).into_owned());
+            network.backward();
+            for (k, layer_grad) in network.flat_gradients().iter().enumerate() {
+                expected[k] += layer_grad * layer_grad / 5.0;
+            }
+        }
+        for (f, e) in fisher.iter().zip(expected.iter()) {
+            assert_abs_diff_eq!(f, e, epsilon = 1e-12);
+        }
+    }
+
+    fn dropout_network(p: f64) -> NeuralNetwork {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 16)), Box::new(Linear::new(16, 2))],
+                                             vec![Box::new(Dropout::with_seed(p, 0))],
+                                             Box::new(MSE::new()));
+        let params: Vec<f64> = (0..network.num_parameters()).map(|k| ((k * 7) % 11) as f64 / 10.0 - 0.5).collect();
+        network.set_flat_parameters(&params);
+        return network;
+    }
+
+    #[test]
+    fn test_train_and_eval_modes() {
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, -2.0,
+                                                0.5, 0.5]);
+        let mut reference = dropout_network(0.5);
+        reference.activations[0] = Box::new(Identity::new());
+        let expected = reference.forward(&x);
+
+        let mut network = dropout_network(0.5);
+        assert!(network.is_training());
+        assert_ne!(network.forward(&x), expected);
+        network.eval();
+        assert!(!network.is_training());
+        assert_eq!(network.forward(&x), expected);
+        assert_eq!(network.forward(&x), expected);
+        network.train();
+        assert_ne!(network.forward(&x), expected);
+    }
+
+    #[test]
+    fn test_predict_matches_forward() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 4)),
+                                                  Box::new(Linear::new(4, 3)), Box::new(Linear::new(3, 2))],
+                                             vec![Box::new(ReLU::new()), Box::new(Dropout::with_seed(0.5, 0)), Box::new(Sigmoid::new())],
+                                             Box::new(MSE::new()));
+        network.eval();
+        let expected = network.forward(&x);
+        let shared = &network;
+        let predicted = shared.predict(&x);
+        for (p, e) in predicted.iter().zip(expected.iter()) {
+            assert_eq!(p, e);
+        }
+
+        // predict gives the same output as forward for activations that
+        // rely on the default predict (here Softmax), not only for those
+        // that override it
+        let mut network = small_network();
+        network.activations[1] = Box::new(Softmax::new());
+        assert_eq!(network.predict(&x), network.forward(&x));
+    }
+
+    #[test]
+    fn test_predict_from_shared_model_across_threads() {
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, -2.0,
+                                                0.5, 0.5]);
+        let mut network = small_network();
+        let expected = network.forward(&x);
+        let shared = std::sync::Arc::new(network);
+        let handles: Vec<_> = (0..2).map(|_| {
+            let model = std::sync::Arc::clone(&shared);
+            let x = x.clone();
+            std::thread::spawn(move || model.predict(&x))
+        }).collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_mc_dropout_predict() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+
+        // Without dropout every pass is the same
+        let mut network = dropout_network(0.0);
+        let (mean, variance) = network.mc_dropout_predict(&x, 10);
+        assert_abs_diff_eq!(mean, network.forward(&x), epsilon = 1e-12);
+        assert_abs_diff_eq!(variance, DMatrix::zeros(3, 2), epsilon = 1e-20);
+
+        let mut network = dropout_network(0.5);
+        let (mean, variance) = network.mc_dropout_predict(&x, 50);
+        assert_eq!(mean.shape(), (3, 2));
+        assert!(variance.iter().all(|v| *v > 0.0), "{}", variance);
+    }
+
+    #[test]
+    fn test_regression_interval_widens_with_dropout() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+
+        // Without dropout the interval collapses to the point prediction
+        let mut network = dropout_network(0.0);
+        let (lower, upper) = network.regression_interval(&x, 20, 0.1);
+        let prediction = network.forward(&x);
+        assert_eq!(lower, prediction);
+        assert_eq!(upper, prediction);
+
+        let mut widths = Vec::new();
+        for p in [0.1, 0.3, 0.6] {
+            let mut network = dropout_network(p);
+            network.eval();
+            let (lower, upper) = network.regression_interval(&x, 200, 0.1);
+            assert!(lower.iter().zip(upper.iter()).all(|(l, u)| l <= u));
+            assert!(!network.is_training()); // The mode is restored
+            widths.push((upper - lower).mean());
+        }
+        assert!(widths[0] > 0.0 && widths[0] < widths[1] && widths[1] < widths[2], "{:?}", widths);
+    }
+
+    #[test]
+    fn test_weight_condition_numbers() {
+        let mut network = small_network();
+        // Orthogonal columns of norms 1 and 2: σ = {2, 1}
+        network.layers[0].W = DMatrix::from_row_slice(3, 2, &[0.0, 2.0,
+                                                              1.0, 0.0,
+                                                              0.0, 0.0]);
+        // Rows nearly parallel: σ_max / σ_min ≈ 4e6
+        network.layers[1].W = DMatrix::from_row_slice(2, 3, &[1.0, 1.0, 0.0,
+                                                              1.0, 1.0 + 1e-6, 0.0]);
+        let conditions = network.weight_condition_numbers();
+        assert_abs_diff_eq!(conditions[0], 2.0, epsilon = 1e-12);
+        assert!(conditions[1] > 1e6 && conditions[1] < 1e7, "{}", conditions[1]);
+
+        // Exactly singular
+        network.layers[1].W = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                              2.0, 4.0, 6.0]);
+        assert_eq!(network.weight_condition_numbers()[1], f64::INFINITY);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("layer0.*", "layer0.weight"));
+        assert!(glob_match("layer0.*", "layer0.bias"));
+        assert!(!glob_match("layer0.*", "layer10.bias"));
+        assert!(glob_match("*.bias", "layer3.bias"));
+        assert!(glob_match("layer*.w*t", "layer12.weight"));
+        assert!(glob_match("*", "layer1.weight"));
+        assert!(glob_match("layer1.weight", "layer1.weight"));
+        assert!(!glob_match("layer1.weigh", "layer1.weight"));
+        assert!(!glob_match("layer1*1", "layer1"));
+    }
+
+    #[test]
+    fn test_set_requires_grad_fine_tunes_last_layer() {
+        let mut network = small_network();
+        assert_eq!(network.set_requires_grad("*", false), 4);
+        assert_eq!(network.set_requires_grad("layer1.*", true), 2);
+
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let y = DMatrix::from_row_slice(3, 2, &[1.0, 0.0,
+                                                0.0, 1.0,
+                                                2.0, 2.0]);
+        let frozen_W = network.layers[0].W.clone();
+        let frozen_b = network.layers[0].b.clone();
+        let trained_W = network.layers[1].W.clone();
+        let mut optim = SGD::new(network, 0.1, 0.9);
+        for _ in 0..5 {
+            optim.update(&x, &y);
+        }
+        assert_eq!(optim.model.layers[0].W, frozen_W);
+        assert_eq!(optim.model.layers[0].b, frozen_b);
+        assert_ne!(optim.model.layers[1].W, trained_W);
+    }
+
+    #[test]
+    fn test_set_requires_grad_freezes_activation_parameters() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                             vec![Box::new(SwishBeta::new(1.0)), Box::new(SwishBeta::new(1.0))],
+                                             Box::new(MSE::new()));
+        assert_eq!(network.set_requires_grad("activation0.*", false), 1);
+        assert_eq!(network.set_requires_grad("*.param*", false), 2);
+        assert_eq!(network.set_requires_grad("activation1.param0", true), 1);
+        assert_eq!(network.requires_grad(), vec![true, true, true, true, false, true]);
+
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let y = DMatrix::from_row_slice(3, 1, &[1.0, 0.0, 2.0]);
+        let mut optim = Adam::new(network, 0.1, 0.9, 0.999, 1e-8);
+        for _ in 0..5 {
+            optim.update(&x, &y);
+        }
+        assert_eq!(optim.model.activations[0].config(), vec![1.0]);
+        assert_ne!(optim.model.activations[1].config(), vec![1.0]);
+    }
+
+    #[test]
+    fn test_top_hessian_eigenvalue_of_linear_regression() {
+        // For a linear model with MSE the loss is quadratic in θ = (w, b):
+        // L = 1/N ‖X̃ θ - y‖^2 with X̃ = [X 1], so H = 2/N X̃^T X̃ exactly
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(4, 2, &[1.0, 2.0,
+                                                -1.0, 0.5,
+                                                3.0, -1.0,
+                                                0.0, 1.0]);
+        let y = DMatrix::from_row_slice(4, 1, &[1.0, 0.0, 2.0, -1.0]);
+        let X_tilde = DMatrix::from_fn(4, 3, |i, j| if j < 2 { x[(i, j)] } else { 1.0 });
+        let H: DMatrix<f64> = 2.0 / 4.0 * X_tilde.transpose() * &X_tilde;
+        let expected = H.clone().symmetric_eigen().eigenvalues.max();
+
+        let before = network.flat_parameters();
+        let estimate = network.top_hessian_eigenvalue(&x, &y, 100);
+        assert_abs_diff_eq!(estimate, expected, epsilon = 1e-5 * expected);
+        assert_eq!(network.flat_parameters(), before);
+
+        // The Hessian-vector product itself matches H v
+        let v = [0.5, -1.0, 2.0];
+        let Hv = network.hessian_vector_product(&x, &y, &v);
+        let expected_Hv = &H * DMatrix::from_column_slice(3, 1, &v);
+        for k in 0..3 {
+            assert_abs_diff_eq!(Hv[k], expected_Hv[k], epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_widen_layer_preserves_output() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 2))],
+                                             vec![Box::new(Tanh::new()), Box::new(ReLU::new())],
+                                             Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let before = network.forward(&x);
+
+        network.widen_layer(0, 7);
+        network.widen_layer(1, 5);
+        assert_eq!(network.layers[0].W.shape(), (7, 2));
+        assert_eq!(network.layers[0].b.shape(), (7, 1));
+        assert_eq!(network.layers[1].W.shape(), (5, 7));
+        assert_eq!(network.layers[2].W.shape(), (2, 5));
+        assert_abs_diff_eq!(network.forward(&x), before, epsilon = 1e-12);
+
+        // The widened network still trains
+        let mut optim = SGD::new(network, 0.01, 0.0);
+        optim.update(&x, &before.map(|v| v + 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "not element-wise")]
+    fn test_widen_layer_rejects_softmax() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 2))],
+                                             vec![Box::new(Softmax::new())],
+                                             Box::new(MSE::new()));
+        network.widen_layer(0, 5);
+    }
+
+    #[test]
+    fn test_jvp_of_linear_network() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 2))],
+                                             vec![Box::new(Identity::new())],
+                                             Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, -2.0,
+                                                0.5, 0.5]);
+        let v = DMatrix::from_row_slice(2, 2, &[1.0, 0.0,
+                                                -0.5, 2.0]);
+        // The network is affine in x, so its Jacobian is W_total = W_2 W_1
+        let W_total = &network.layers[1].W * &network.layers[0].W;
+        assert_abs_diff_eq!(network.jvp(&x, &v), &v * W_total.transpose(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_jvp_matches_finite_differences() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 2))],
+                                             vec![Box::new(ScaledTanh::new(-1.0, 1.0)), Box::new(Softmax::new())],
+                                             Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(2, 2, &[0.3, -0.2,
+                                                0.1, 0.4]);
+        let v = DMatrix::from_row_slice(2, 2, &[1.0, 0.5,
+                                                -0.5, 2.0]);
+        let h = 1e-6;
+        let numeric = (network.forward(&(&x + h * &v)) - network.forward(&(&x - h * &v))) / (2.0 * h);
+        assert_abs_diff_eq!(network.jvp(&x, &v), numeric, epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = small_network();
+        let b = small_network();
+        let params_a = a.flat_parameters();
+        let params_b = b.flat_parameters();
+
+        assert_eq!(NeuralNetwork::interpolate(&a, &b, 0.0).unwrap().flat_parameters(), params_a);
+        assert_eq!(NeuralNetwork::interpolate(&a, &b, 1.0).unwrap().flat_parameters(), params_b);
+        let midpoint = NeuralNetwork::interpolate(&a, &b, 0.5).unwrap().flat_parameters();
+        for k in 0..midpoint.len() {
+            assert_abs_diff_eq!(midpoint[k], (params_a[k] + params_b[k]) / 2.0, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_interpolate_rejects_different_architectures() {
+        let a = small_network();
+        let wider = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 2))],
+                                       vec![Box::new(ReLU::new()), Box::new(ReLU::new())],
+                                       Box::new(MSE::new()));
+        assert_eq!(NeuralNetwork::interpolate(&a, &wider, 0.5).err(),
+                   Some(DnnError::ShapeMismatch { key: "layer0.weight".to_string(), expected: (3, 2), found: (4, 2) }));
+
+        let mut tanh = small_network();
+        tanh.activations[1] = Box::new(Tanh::new());
+        assert!(matches!(NeuralNetwork::interpolate(&a, &tanh, 0.5), Err(DnnError::ArchitectureMismatch(_))));
+    }
+
+    #[test]
+    fn test_gradient_noise_scale_on_noisy_problem() {
+        // A linear model far from the noisy targets
+        let (x, y) = crate::data::make_regression(1024, 2, 1.0, 0);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        network.set_flat_parameters(&[1.0, -1.0, 2.0]);
+
+        let mut estimates = Vec::new();
+        for k in 0..8 {
+            let small = (x.rows(8 * k, 8).into_owned(), y.rows(8 * k, 8).into_owned());
+            let big = (x.rows(512, 512).into_owned(), y.rows(512, 512).into_owned());
+            estimates.push(network.gradient_noise_scale((&small.0, &small.1), (&big.0, &big.1)));
+        }
+        let mean = estimates.iter().sum::<f64>() / estimates.len() as f64;
+        assert!(estimates.iter().all(|b| b.is_finite()));
+        assert!(mean > 0.0, "{:?}", estimates);
+    }
+
+    #[test]
+    fn test_vanishing_gradient_report_flags_early_sigmoid_layers() {
+        // Eight small sigmoid layers: every layer scales the gradient by at
+        // most 0.25 * ‖W‖, so it shrinks geometrically toward the input
+        let mut layers = Vec::new();
+        let mut activations: Vec<Box<dyn ActivationFunction>> = Vec::new();
+        for _ in 0..8 {
+            layers.push(Box::new(Linear::new(4, 4)));
+            activations.push(Box::new(Sigmoid::new()));
+        }
+        let mut network = NeuralNetwork::new(layers, activations, Box::new(MSE::new()));
+        let params: Vec<f64> = (0..network.num_parameters()).map(|k| ((k * 7) % 11) as f64 / 11.0 - 0.5).collect();
+        network.set_flat_parameters(&params);
+
+        let x = DMatrix::from_row_slice(2, 4, &[1.0, -1.0, 0.5, 2.0,
+                                                -0.5, 0.0, 1.5, -2.0]);
+        let y = DMatrix::from_element(2, 4, 1.0);
+        let _ = network.output_grad(&x, &y);
+        network.backward();
+
+        let flagged = network.vanishing_gradient_report(1e-2);
+        assert!(flagged.contains(&0) && flagged.contains(&1) && flagged.contains(&2), "{:?}", flagged);
+        assert!(!flagged.contains(&7));
+        // The flagged layers are the earliest ones
+        assert!(flagged.iter().enumerate().all(|(k, i)| k == *i), "{:?}", flagged);
+    }
+
+    #[test]
+    fn test_vanishing_gradient_report_without_layers() {
+        let network = NeuralNetwork::new(Vec::new(), Vec::new(), Box::new(MSE::new()));
+        assert!(network.vanishing_gradient_report(1e-2).is_empty());
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        let network = small_network();
+        let report = network.memory_footprint(16);
+        assert_eq!(report.parameter_bytes, network.num_parameters() * 8);
+        assert_eq!(report.gradient_bytes, report.parameter_bytes);
+        // Cached inputs and outputs: 16 * ((2 + 3) + (3 + 2)) values
+        assert_eq!(report.activation_bytes, 16 * 10 * 8);
+        assert_eq!(network.memory_footprint(32).activation_bytes, 2 * report.activation_bytes);
+        assert_eq!(report.total(), 4 * report.parameter_bytes + report.activation_bytes);
+    }
+
+    #[test]
+    fn test_grad_snr_separates_consistent_and_noisy_layers() {
+        // The input features have mixed signs, so the weight gradients of
+        // the first layer change sign from batch to batch. Its output is
+        // ≈ 3 for every sample and the target is far above the prediction,
+        // so the gradients of the second layer always point the same way.
+        let x = DMatrix::from_fn(64, 8, |i, j| if (i * 7 + j * 3) % 5 < 2 { 1.0 } else { -0.5 } * (1.0 + (i % 3) as f64));
+        let y = DMatrix::from_element(64, 1, 10.0);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(8, 1)), Box::new(Linear::new(1, 1))],
+                                             vec![Box::new(Identity::new())], Box::new(MSE::new()));
+        network.layers[0].W = DMatrix::from_element(1, 8, 0.05);
+        network.layers[0].b = DMatrix::from_element(1, 1, 3.0);
+        network.layers[1].W = DMatrix::from_element(1, 1, 1.0);
+        network.layers[1].b = DMatrix::from_element(1, 1, 0.0);
+
+        let mut loader = DataLoader::new(x, y, 4, true, 0);
+        let snr = network.grad_snr(&mut loader);
+        assert_eq!(snr.len(), 2);
+        assert!(snr.iter().all(|s| s.is_finite() && *s > 0.0));
+        assert!(snr[1] > 2.0 * snr[0], "{:?}", snr);
+    }
+
+    #[test]
+    fn test_cross_entropy_loss_gradients_through_softmax() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -0.5,
+                                                0.2, 2.0,
+                                                -1.0, 0.3]);
+        let y = DMatrix::from_row_slice(3, 3, &[1.0, 0.0, 0.0,
+                                                0.0, 0.0, 1.0,
+                                                0.0, 1.0, 0.0]);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 3))],
+                                             vec![Box::new(ScaledTanh::new(-1.0, 1.0)), Box::new(Softmax::new())],
+                                             Box::new(CrossEntropyLoss::new()));
+        let _ = network.output_grad(&x, &y);
+        network.backward();
+        let grad = network.flat_gradients();
+
+        // The fused gradient skips the final softmax and matches finite differences
+        let params = network.flat_parameters();
+        let h = 1e-6;
+        for k in 0..params.len() {
+            let mut shifted = params.clone();
+            shifted[k] += h;
+            network.set_flat_parameters(&shifted);
+            let plus = network.evaluate(&x, &y);
+            shifted[k] -= 2.0 * h;
+            network.set_flat_parameters(&shifted);
+            let minus = network.evaluate(&x, &y);
+            assert_abs_diff_eq!(grad[k], (plus - minus) / (2.0 * h), epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_cross_entropy_loss_gradients_without_row_softmax() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -0.5,
+                                                0.2, 2.0,
+                                                -1.0, 0.3]);
+        let y = DMatrix::from_row_slice(3, 3, &[1.0, 0.0, 0.0,
+                                                0.0, 0.0, 1.0,
+                                                0.0, 1.0, 0.0]);
+        // Neither final activation may be skipped, so the unfused gradient is used
+        let finals: Vec<Box<dyn ActivationFunction>> = vec![Box::new(Softmax::with_axis(Axis::Col)), Box::new(ScaledTanh::new(0.1, 1.0))];
+        for last in finals {
+            let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 4)), Box::new(Linear::new(4, 3))],
+                                                 vec![Box::new(ScaledTanh::new(-1.0, 1.0)), last],
+                                                 Box::new(CrossEntropyLoss::new()));
+            let _ = network.output_grad(&x, &y);
+            network.backward();
+            let grad = network.flat_gradients();
+
+            let params = network.flat_parameters();
+            let h = 1e-6;
+            for k in 0..params.len() {
+                let mut shifted = params.clone();
+                shifted[k] += h;
+                network.set_flat_parameters(&shifted);
+                let plus = network.evaluate(&x, &y);
+                shifted[k] -= 2.0 * h;
+                network.set_flat_parameters(&shifted);
+                let minus = network.evaluate(&x, &y);
+                assert_abs_diff_eq!(grad[k], (plus - minus) / (2.0 * h), epsilon = 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backward_without_layers() {
+        let mut network = NeuralNetwork::new(Vec::new(), Vec::new(), Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                3.0, 4.0]);
+        let _ = network.output_grad(&x, &DMatrix::zeros(2, 2));
+        network.backward();
+        let dLdx = network.backward_from(&x);
+        assert_eq!(dLdx, x);
+    }
+
+    #[test]
+    fn test_loss_is_swappable() {
+        let x = DMatrix::from_row_slice(1, 2, &[0.5, -1.0]);
+        let y = DMatrix::from_row_slice(1, 3, &[0.0, 1.0, 0.0]);
+        let mut mse: NeuralNetwork = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3))], vec![Box::new(Softmax::new())], Box::new(MSE::new()));
+        let mut ce = mse.clone();
+        ce.loss = Box::new(CrossEntropyLoss::new());
+
+        let A = mse.forward(&x);
+        assert_abs_diff_eq!(mse.evaluate(&x, &y), (&A - &y).norm_squared() / 3.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(ce.evaluate(&x, &y), -A[(0, 1)].ln(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_activation_correlation_of_duplicated_units() {
+        let mut network = small_network();
+        // Units 0 and 1 of the first layer are copies, unit 2 is always dead
+        network.layers[0].W = DMatrix::from_row_slice(3, 2, &[1.0, -0.5,
+                                                              1.0, -0.5,
+                                                              0.0, 0.0]);
+        network.layers[0].b = DMatrix::from_row_slice(3, 1, &[0.2, 0.2, -1.0]);
+        let x = DMatrix::from_row_slice(4, 2, &[1.0, 0.0,
+                                                2.0, 1.0,
+                                                0.5, -1.0,
+                                                3.0, 0.5]);
+        let _ = network.forward(&x);
+
+        let C = network.activation_correlation(0);
+        assert_eq!(C.shape(), (3, 3));
+        assert_abs_diff_eq!(C[(0, 1)], 1.0, epsilon = 1e-9);
+        assert_abs_diff_eq!(C[(1, 0)], 1.0, epsilon = 1e-9);
+        assert_eq!(C[(0, 2)], 0.0);
+        assert_eq!(C[(2, 2)], 1.0);
+        // The output layer is readable too
+        let C_out = network.activation_correlation(1);
+        assert_eq!(C_out.shape(), (2, 2));
+        assert!(C_out.iter().all(|c| c.abs() <= 1.0 + 1e-12));
+    }
+
+    #[test]
+    fn test_lipschitz_upper_bound() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 2)), Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        // Spectral norms 3 (diagonal) and 5 (a single row of norm 5)
+        network.layers[0].W = DMatrix::from_row_slice(2, 2, &[3.0, 0.0,
+                                                              0.0, -1.0]);
+        network.layers[1].W = DMatrix::from_row_slice(1, 2, &[3.0, 4.0]);
+        assert_abs_diff_eq!(network.lipschitz_upper_bound(), 15.0, epsilon = 1e-9);
+
+        // Activations multiply in their constants
+        network.activations = vec![Box::new(ReLU::new()), Box::new(Sigmoid::new())];
+        assert_abs_diff_eq!(network.lipschitz_upper_bound(), 15.0 * 0.25, epsilon = 1e-9);
+        network.activations[0] = Box::new(crate::nn::activation::Lambda::new(|z| z, |_| 1.0));
+        assert_eq!(network.lipschitz_upper_bound(), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_gradient_histogram() {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![], Box::new(MSE::new()));
+        network.layers[0].dLdW = DMatrix::from_row_slice(2, 3, &[0.0, -0.5, 1.9,
+                                                                 0.1, 2.0, -1.0]);
+        network.layers[0].dLdb = DMatrix::from_row_slice(2, 1, &[-0.4, 1.2]);
+        // Magnitudes 0, 0.1, 0.4 | 0.5 | 1.0, 1.2 | 1.9, 2.0 in bins of width 0.5
+        let histogram = network.gradient_histogram(4);
+        assert_eq!(histogram, vec![(0.0, 3), (0.5, 1), (1.0, 2), (1.5, 2)]);
+        let total: usize = network.gradient_histogram(7).iter().map(|(_, count)| count).sum();
+        assert_eq!(total, 8);
+    }
+
+    fn state_for(network: &NeuralNetwork) -> HashMap<String, DMatrix<f64>> {
+        let mut dict = HashMap::new();
+        for (i, layer) in network.layers.iter().enumerate() {
+            dict.insert(format!("layer{}.weight", i), layer.W.map(|w| w + 1.0));
+            dict.insert(format!("layer{}.bias", i), layer.b.map(|b| b - 1.0));
+        }
+        return dict;
+    }
+
+    #[test]
+    fn test_load_state_dict() {
+        let mut network = small_network();
+        let mut dict = HashMap::new();
+        dict.insert("layer0.weight".to_string(), DMatrix::from_row_slice(3, 2, &[1.0, 0.0,
+                                                                                0.0, 1.0,
+                                                                                1.0, 1.0]));
+        dict.insert("layer0.bias".to_string(), DMatrix::from_row_slice(3, 1, &[0.0, 0.0, -10.0]));
+        dict.insert("layer1.weight".to_string(), DMatrix::from_row_slice(2, 3, &[1.0, 0.0, 0.0,
+                                                                                0.0, 2.0, 1.0]));
+        dict.insert("layer1.bias".to_string(), DMatrix::from_row_slice(2, 1, &[0.5, 0.0]));
+        network.load_state_dict(dict).unwrap();
+
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                -1.0, 3.0]);
+        // Hidden: relu([1, 2, 3 - 10]) = [1, 2, 0], relu([-1, 3, 2 - 10]) = [0, 3, 0]
+        let expected = DMatrix::from_row_slice(2, 2, &[1.5, 4.0,
+                                                       0.5, 6.0]);
+        assert_abs_diff_eq!(network.forward(&x), expected, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_state_dict_round_trip() {
+        let mut network = small_network();
+        let dict = network.state_dict();
+
+        let mut keys: Vec<&String> = dict.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["layer0.bias", "layer0.weight", "layer1.bias", "layer1.weight"]);
+        assert_eq!(dict["layer0.weight"].shape(), (3, 2));
+        assert_eq!(dict["layer0.bias"].shape(), (3, 1));
+        assert_eq!(dict["layer1.weight"].shape(), (2, 3));
+        assert_eq!(dict["layer1.bias"].shape(), (2, 1));
+
+        let before = network.flat_parameters();
+        network.load_state_dict(dict).unwrap();
+        assert_eq!(network.flat_parameters(), before);
+    }
+
+    #[test]
+    fn test_state_dict_includes_activation_state() {
+        let mut batchnorm = BatchNorm::new(3);
+        batchnorm.gamma = DMatrix::from_row_slice(1, 3, &[1.0, 2.0, 3.0]);
+        batchnorm.running_var = DMatrix::from_row_slice(1, 3, &[0.5, 0.6, 0.7]);
+        let network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                         vec![Box::new(batchnorm), Box::new(SwishBeta::new(1.5))],
+                                         Box::new(MSE::new()));
+        let dict = network.state_dict();
+        let mut keys: Vec<&String> = dict.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["activation0.buffer0", "activation0.buffer1", "activation0.param0", "activation0.param1",
+                              "activation1.param0", "layer0.bias", "layer0.weight", "layer1.bias", "layer1.weight"]);
+        assert_eq!(dict["activation0.param0"], DMatrix::from_row_slice(1, 3, &[1.0, 2.0, 3.0]));
+        assert_eq!(dict["activation0.buffer1"], DMatrix::from_row_slice(1, 3, &[0.5, 0.6, 0.7]));
+        assert_eq!(dict["activation1.param0"], DMatrix::from_element(1, 1, 1.5));
+    }
+
+    #[test]
+    fn test_load_state_dict_restores_activation_state() {
+        let network = || NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                            vec![Box::new(BatchNorm::new(3)), Box::new(SwishBeta::new(1.0))],
+                                            Box::new(MSE::new()));
+        let mut source = network();
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let y = DMatrix::from_row_slice(3, 1, &[1.0, 0.0, 2.0]);
+        let mut optim = SGD::new(source, 0.1, 0.9);
+        for _ in 0..3 {
+            optim.update(&x, &y);
+        }
+        source = optim.model;
+
+        let mut target = network();
+        target.load_state_dict(source.state_dict()).unwrap();
+        assert_eq!(target.flat_parameters(), source.flat_parameters());
+        assert_eq!(target.buffers(), source.buffers());
+        source.eval();
+        target.eval();
+        assert_eq!(target.forward(&x), source.forward(&x));
+
+        let mut dict = source.state_dict();
+        dict.insert("activation0.buffer1".to_string(), DMatrix::zeros(1, 2));
+        assert_eq!(target.load_state_dict(dict),
+                   Err(DnnError::ShapeMismatch { key: "activation0.buffer1".to_string(), expected: (1, 3), found: (1, 2) }));
+        let mut dict = source.state_dict();
+        dict.insert("activation1.buffer0".to_string(), DMatrix::zeros(1, 1));
+        assert_eq!(target.load_state_dict(dict), Err(DnnError::UnexpectedKeys(vec!["activation1.buffer0".to_string()])));
+        let mut dict = source.state_dict();
+        dict.remove("activation1.param0");
+        assert_eq!(target.load_state_dict(dict), Err(DnnError::MissingKeys(vec!["activation1.param0".to_string()])));
+    }
+
+    #[test]
+    fn test_load_state_dict_shape_mismatch() {
+        let mut network = small_network();
+        let before = network.flat_parameters();
+        let mut dict = state_for(&network);
+        dict.insert("layer1.bias".to_string(), DMatrix::zeros(3, 1));
+        assert_eq!(network.load_state_dict(dict),
+                   Err(DnnError::ShapeMismatch { key: "layer1.bias".to_string(), expected: (2, 1), found: (3, 1) }));
+        assert_eq!(network.flat_parameters(), before);
+    }
+
+    #[test]
+    fn test_grad_snr_without_noise() {
+        // Every batch is the same sample, so the gradients never change. The
+        // second layer gets no gradient at all, as its input is always zero
+        // and the prediction already matches the target through its bias.
+        let x = DMatrix::from_element(8, 1, 1.0);
+        let y = DMatrix::from_element(8, 1, 2.0);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1)), Box::new(Linear::new(1, 1))],
+                                             vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        network.layers[0].W = DMatrix::from_element(1, 1, -1.0);
+        network.layers[0].b = DMatrix::from_element(1, 1, 0.0);
+        network.layers[1].W = DMatrix::from_element(1, 1, 1.0);
+        network.layers[1].b = DMatrix::from_element(1, 1, 2.0);
+
+        let mut loader = DataLoader::new(x, y, 1, false, 0);
+        let snr = network.grad_snr(&mut loader);
+        assert_eq!(snr, vec![0.0, 0.0]);
+
+        // A consistent, noise-free gradient gives a large but finite SNR
+        network.layers[1].b = DMatrix::from_element(1, 1, 1.0);
+        let snr = network.grad_snr(&mut loader);
+        assert!(snr[1].is_finite() && snr[1] > 1e5, "{:?}", snr);
+    }
+
+    #[test]
+    fn test_load_state_dict_missing_and_unexpected_keys() {
+        let mut network = small_network();
+        let mut dict = state_for(&network);
+        dict.remove("layer0.bias");
+        assert_eq!(network.load_state_dict(dict), Err(DnnError::MissingKeys(vec!["layer0.bias".to_string()])));
+
+        let mut dict = state_for(&network);
+        dict.insert("layer2.weight".to_string(), DMatrix::zeros(1, 1));
+        assert_eq!(network.load_state_dict(dict), Err(DnnError::UnexpectedKeys(vec!["layer2.weight".to_string()])));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_grad_at_layer_out_of_range() {
+        let network = small_network();
+        let _ = network.grad_at_layer(2);
     }
 }
@@ -0,0 +1,144 @@
+use nalgebra::{DMatrix};
+use crate::nn::layers::Layer;
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Multi-Head Container
+    *
+    * Multi-task models often share a trunk that computes features of the
+    * input, followed by one head per task. MultiHead runs several heads on
+    * the same input and concatenates their outputs column-wise:
+    *
+    *   Z = [ head_1(A) | head_2(A) | ... | head_k(A) ]   (N x Σ_i C_out_i)
+    *
+    * In backward, ∂L/∂Z is split into the column blocks belonging to each
+    * head, each block is backpropagated through its own head, and since the
+    * input is shared the input gradient is the sum over heads:
+    *
+    *   ∂L/∂A = Σ_i head_i.backward(∂L/∂Z_i)
+    *
+    * Each head is a NeuralNetwork; only its layers and activations are used,
+    * its loss is ignored. MultiHead implements the Layer trait.
+    *
+**/
+
+
+pub struct MultiHead {
+    pub heads: Vec<NeuralNetwork>,
+    widths: Vec<usize> // Number of output columns of each head in the last forward pass
+}
+
+impl MultiHead {
+    pub fn new(heads: Vec<NeuralNetwork>) -> Self {
+        assert!(!heads.is_empty(), "MultiHead needs at least one head");
+        MultiHead {
+            heads: heads,
+            widths: Vec::new()
+        }
+    }
+
+    // Runs every head on A and stacks the outputs side by side
+    pub fn forward(&mut self, A: &DMatrix<f64>) -> DMatrix<f64> {
+        let outputs: Vec<DMatrix<f64>> = self.heads.iter_mut().map(|head| head.forward(A)).collect();
+        self.widths = outputs.iter().map(|Z| Z.ncols()).collect();
+
+        let mut Z = DMatrix::zeros(A.nrows(), self.widths.iter().sum());
+        let mut offset = 0;
+        for output in outputs.iter() {
+            Z.columns_mut(offset, output.ncols()).copy_from(output);
+            offset += output.ncols();
+        }
+        return Z;
+    }
+
+    // Routes each head's block of columns of ∂L/∂Z to that head and sums the
+    // resulting input gradients.
+    pub fn backward(&mut self, dLdZ: &DMatrix<f64>) -> DMatrix<f64> {
+        assert!(!self.widths.is_empty(), "Forward pass not called before backward pass");
+        assert_eq!(dLdZ.ncols(), self.widths.iter().sum::<usize>(), "Gradient does not match the stacked output");
+        let mut dLdA: Option<DMatrix<f64>> = None;
+        let mut offset = 0;
+        for (head, width) in self.heads.iter_mut().zip(self.widths.iter()) {
+            let grad = head.backward_from(&dLdZ.columns(offset, *width).into_owned());
+            dLdA = Some(match dLdA {
+                Some(sum) => sum + grad,
+                None => grad
+            });
+            offset += width;
+        }
+        return dLdA.unwrap();
+    }
+}
+
+impl Layer for MultiHead {
+    fn forward(&mut self, A: &DMatrix<f64>) -> DMatrix<f64> {
+        return MultiHead::forward(self, A);
+    }
+
+    fn backward(&mut self, dLdZ: &DMatrix<f64>) -> DMatrix<f64> {
+        return MultiHead::backward(self, dLdZ);
+    }
+
+    fn parameters_mut(&mut self) -> Vec<&mut DMatrix<f64>> {
+        return self.heads.iter_mut()
+            .flat_map(|head| head.layers.iter_mut())
+            .flat_map(|layer| [&mut layer.W, &mut layer.b])
+            .collect();
+    }
+
+    fn gradients(&self) -> Vec<&DMatrix<f64>> {
+        return self.heads.iter()
+            .flat_map(|head| head.layers.iter())
+            .flat_map(|layer| [&layer.dLdW, &layer.dLdb])
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::check_layer_gradient;
+    use crate::nn::activation::{ActivationFunction, ScaledTanh};
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    fn linear_head(input_size: usize, output_size: usize, activations: Vec<Box<dyn ActivationFunction>>) -> NeuralNetwork {
+        return NeuralNetwork::new(vec![Box::new(Linear::new(input_size, output_size))], activations, Box::new(MSE::new()));
+    }
+
+    #[test]
+    fn test_multihead_routes_gradients() {
+        let mut multihead = MultiHead::new(vec![linear_head(3, 2, vec![]), linear_head(3, 1, vec![])]);
+        let A = DMatrix::from_row_slice(2, 3, &[1.0, 2.0, 3.0,
+                                                -1.0, 0.5, 0.0]);
+        let Z = multihead.forward(&A);
+        assert_eq!(Z.shape(), (2, 3));
+        assert_abs_diff_eq!(Z.columns(0, 2).into_owned(), multihead.heads[0].forward(&A), epsilon = 1e-12);
+        assert_abs_diff_eq!(Z.columns(2, 1).into_owned(), multihead.heads[1].forward(&A), epsilon = 1e-12);
+
+        let dLdZ = DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 2.0,
+                                                   0.5, 0.0, -3.0]);
+        let _ = multihead.forward(&A);
+        let dLdA = multihead.backward(&dLdZ);
+
+        // Each head only sees its own block of the output gradient
+        let dLdZ_0 = dLdZ.columns(0, 2).into_owned();
+        let dLdZ_1 = dLdZ.columns(2, 1).into_owned();
+        assert_abs_diff_eq!(multihead.heads[0].layers[0].dLdW, dLdZ_0.transpose() * &A, epsilon = 1e-12);
+        assert_abs_diff_eq!(multihead.heads[1].layers[0].dLdW, dLdZ_1.transpose() * &A, epsilon = 1e-12);
+        let expected_dLdA = &dLdZ_0 * &multihead.heads[0].layers[0].W + &dLdZ_1 * &multihead.heads[1].layers[0].W;
+        assert_abs_diff_eq!(dLdA, expected_dLdA, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_multihead_gradient_check() {
+        let mut multihead = MultiHead::new(vec![linear_head(2, 3, vec![Box::new(ScaledTanh::new(-1.0, 1.0))]), linear_head(2, 2, vec![])]);
+        let input = DMatrix::from_row_slice(3, 2, &[0.1, -0.2,
+                                                    0.3, 0.05,
+                                                    -0.15, 0.2]);
+        let upstream_grad = DMatrix::from_fn(3, 5, |i, j| (i as f64 - j as f64) / 4.0);
+        let error = check_layer_gradient(&mut multihead, &input, &upstream_grad, 1e-5);
+        assert!(error < 1e-7, "max relative error {}", error);
+    }
+}
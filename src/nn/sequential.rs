@@ -0,0 +1,128 @@
+use crate::nn::activation::{ActivationFunction, Identity, ReLU, Sigmoid, Softmax, Tanh};
+use crate::nn::dropout::Dropout;
+use crate::nn::layers::Linear;
+use crate::nn::loss::{Loss, MSE};
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Sequential Model Builder
+    *
+    * Sequential assembles a NeuralNetwork from a chain of calls, e.g.
+    *
+    *   Sequential::new().linear(784, 128).relu().linear(128, 10).softmax().build()
+    *
+    * Every linear call adds a randomly initialized Linear layer, and every
+    * activation call sets the activation that follows the last layer. This
+    * produces the same layers and activations vectors as NeuralNetwork::new:
+    * a layer that is directly followed by another layer gets an Identity
+    * activation, and a final layer without an activation is left without
+    * one. The loss is MSE unless another one is set with loss.
+    *
+    * The builder panics with a descriptive message when the input size of a
+    * layer does not match the output size of the previous one, when an
+    * activation comes before the first layer, or when a layer is given two
+    * activations.
+    *
+**/
+
+
+pub struct Sequential {
+    layers: Vec<Linear>,
+    activations: Vec<Box<dyn ActivationFunction>>,
+    loss: Box<dyn Loss>
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential {
+            layers: Vec::new(),
+            activations: Vec::new(),
+            loss: Box::new(MSE::new())
+        }
+    }
+
+    // Adds a Linear layer from input_size to output_size features
+    pub fn linear(mut self, input_size: usize, output_size: usize) -> Self {
+        if let Some(previous) = self.layers.last() {
+            assert_eq!(input_size, previous.W.nrows(),
+                       "Layer {} takes {} inputs, but the previous layer has {} outputs",
+                       self.layers.len(), input_size, previous.W.nrows());
+        }
+        // Keep activation i after layer i
+        if self.activations.len() < self.layers.len() {
+            self.activations.push(Box::new(Identity::new()));
+        }
+        self.layers.push(Linear::new(input_size, output_size));
+        return self;
+    }
+
+    // Sets the activation after the last layer
+    pub fn activation(mut self, activation: Box<dyn ActivationFunction>) -> Self {
+        assert!(!self.layers.is_empty(), "The activation {} must follow a linear layer", activation.name());
+        assert!(self.activations.len() < self.layers.len(),
+                "Layer {} already has the activation {}", self.layers.len() - 1, self.activations[self.layers.len() - 1].name());
+        self.activations.push(activation);
+        return self;
+    }
+
+    pub fn relu(self) -> Self {
+        return self.activation(Box::new(ReLU::new()));
+    }
+
+    pub fn sigmoid(self) -> Self {
+        return self.activation(Box::new(Sigmoid::new()));
+    }
+
+    pub fn tanh(self) -> Self {
+        return self.activation(Box::new(Tanh::new()));
+    }
+
+    pub fn softmax(self) -> Self {
+        return self.activation(Box::new(Softmax::new()));
+    }
+
+    pub fn dropout(self, p: f64) -> Self {
+        return self.activation(Box::new(Dropout::new(p)));
+    }
+
+    // Sets the loss of the built network
+    pub fn loss(mut self, loss: Box<dyn Loss>) -> Self {
+        self.loss = loss;
+        return self;
+    }
+
+    pub fn build(self) -> NeuralNetwork {
+        assert!(!self.layers.is_empty(), "A Sequential model needs at least one linear layer");
+        let layers = self.layers.into_iter().map(Box::new).collect();
+        return NeuralNetwork::new(layers, self.activations, self.loss);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{DMatrix};
+
+    #[test]
+    fn test_sequential_builds_interleaved_network() {
+        let mut network = Sequential::new().linear(4, 8).relu().linear(8, 6).linear(6, 3).softmax().build();
+        let names: Vec<&str> = network.activations.iter().map(|a| a.name()).collect();
+        assert_eq!(names, vec!["relu", "identity", "softmax"]);
+        let shapes: Vec<(usize, usize)> = network.layers.iter().map(|l| l.W.shape()).collect();
+        assert_eq!(shapes, vec![(8, 4), (6, 8), (3, 6)]);
+
+        let A = network.forward(&DMatrix::from_element(5, 4, 0.5));
+        assert_eq!(A.shape(), (5, 3));
+
+        // A final layer without an activation outputs its pre-activations
+        let network = Sequential::new().linear(2, 3).tanh().linear(3, 1).build();
+        assert_eq!(network.layers.len(), 2);
+        assert_eq!(network.activations.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Layer 1 takes 5 inputs, but the previous layer has 8 outputs")]
+    fn test_sequential_rejects_dimension_mismatch() {
+        let _ = Sequential::new().linear(4, 8).relu().linear(5, 2);
+    }
+}
@@ -0,0 +1,201 @@
+use nalgebra::{DMatrix};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::error::DnnError;
+use crate::nn::activation::{activation_from_config, ActivationFunction};
+use crate::nn::layers::Linear;
+use crate::nn::loss::MSE;
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * JSON Model Files
+    *
+    * NeuralNetwork::save writes a model to a human-readable JSON file that
+    * NeuralNetwork::load reads back, e.g. to train once and reuse the model
+    * for inference later:
+    *
+    *   {
+    *     "version": 1,
+    *     "layers": [ { "W": [[C_out, C_in], [w_11, w_21, ...]],
+    *                   "b": [[C_out, 1], [...]] }, ... ],
+    *     "activations": [ { "name": "relu", "config": [] }, ... ]
+    *   }
+    *
+    * The matrices use nalgebra's serde layout, their shape followed by the
+    * entries in column-major order. The layer sizes are implied by the
+    * shapes. f64 values are written with enough digits and parsed exactly
+    * (serde_json with float_roundtrip), so a loaded model computes the same
+    * outputs as the saved one. As in the portable binary format (see
+    * flatbuffer), activations are stored by name with their config and
+    * rebuilt with activation_from_config, saving an activation that cannot
+    * be rebuilt fails, and the loss is not stored; loaded models use MSE.
+    *
+    * JSON support is opt-in through the serde feature, so that std users
+    * who do not need it do not pull in serde and serde_json.
+    *
+**/
+
+
+pub const JSON_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct LayerRecord {
+    W: DMatrix<f64>,
+    b: DMatrix<f64>
+}
+
+#[derive(Serialize, Deserialize)]
+struct ActivationRecord {
+    name: String,
+    config: Vec<f64>
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModelRecord {
+    version: u32,
+    layers: Vec<LayerRecord>,
+    activations: Vec<ActivationRecord>
+}
+
+impl NeuralNetwork {
+    // Writes the parameters and the architecture of the model to a JSON file.
+    // Fails with DnnError::UnknownActivation, without writing anything, if an
+    // activation cannot be rebuilt from its name and config.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DnnError> {
+        let mut activations = Vec::with_capacity(self.activations.len());
+        for activation in self.activations.iter() {
+            let config = activation.config();
+            let _ = activation_from_config(activation.name(), &config)?;
+            activations.push(ActivationRecord { name: activation.name().to_string(), config: config });
+        }
+        let record = ModelRecord {
+            version: JSON_FORMAT_VERSION,
+            layers: self.layers.iter().map(|layer| LayerRecord { W: layer.W.clone(), b: layer.b.clone() }).collect(),
+            activations: activations
+        };
+        let json = serde_json::to_string(&record).map_err(|e| DnnError::InvalidFormat(e.to_string()))?;
+        fs::write(path, json)?;
+        return Ok(());
+    }
+
+    // Rebuilds a model from a JSON file written by save, checking that the
+    // layers fit together (see NeuralNetwork::check_architecture)
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<NeuralNetwork, DnnError> {
+        let json = fs::read_to_string(path)?;
+        let record: ModelRecord = serde_json::from_str(&json).map_err(|e| DnnError::InvalidFormat(e.to_string()))?;
+        if record.version != JSON_FORMAT_VERSION {
+            return Err(DnnError::UnsupportedVersion { found: record.version, supported: JSON_FORMAT_VERSION });
+        }
+
+        let mut layers = Vec::with_capacity(record.layers.len());
+        for (i, saved) in record.layers.into_iter().enumerate() {
+            if saved.b.shape() != (saved.W.nrows(), 1) {
+                return Err(DnnError::ShapeMismatch {
+                    key: format!("layer{}.bias", i), expected: (saved.W.nrows(), 1), found: saved.b.shape() });
+            }
+            layers.push(Box::new(Linear::from_parameters(saved.W, saved.b)));
+        }
+        let mut activations: Vec<Box<dyn ActivationFunction>> = Vec::with_capacity(record.activations.len());
+        for saved in record.activations.iter() {
+            activations.push(activation_from_config(&saved.name, &saved.config)?);
+        }
+        let model = NeuralNetwork::new(layers, activations, Box::new(MSE::new()));
+        model.check_architecture()?;
+        return Ok(model);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{Axis, Lambda, ReLU, Sigmoid, Softmax, SwishBeta};
+    use crate::nn::dropout::Dropout;
+    use crate::optim::sgd::SGD;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        return std::env::temp_dir().join(format!("dnn_rs_{}_{}.json", name, std::process::id()));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 2))],
+                                       vec![Box::new(ReLU::new()), Box::new(Sigmoid::new())],
+                                       Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        let y = DMatrix::from_row_slice(2, 2, &[1.0, 0.0,
+                                                0.0, 1.0]);
+        let mut optim = SGD::new(model, 0.1, 0.9);
+        for _ in 0..3 {
+            let _ = optim.update(&x, &y);
+        }
+
+        let path = temp_path("save_load");
+        optim.model.save(&path).unwrap();
+        let mut loaded = NeuralNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.flat_parameters(), optim.model.flat_parameters());
+        let names: Vec<&str> = loaded.activations.iter().map(|a| a.name()).collect();
+        assert_eq!(names, vec!["relu", "sigmoid"]);
+        assert_eq!(loaded.forward(&x), optim.model.forward(&x));
+    }
+
+    #[test]
+    fn test_load_rejects_invalid_files() {
+        let path = temp_path("invalid");
+        std::fs::write(&path, "{\"version\": 2, \"layers\": [], \"activations\": []}").unwrap();
+        assert_eq!(NeuralNetwork::load(&path).err(),
+                   Some(DnnError::UnsupportedVersion { found: 2, supported: JSON_FORMAT_VERSION }));
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(NeuralNetwork::load(&path), Err(DnnError::InvalidFormat(_))));
+        let unchained = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(2, 1))],
+                                           vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        unchained.save(&path).unwrap();
+        assert_eq!(NeuralNetwork::load(&path).err(),
+                   Some(DnnError::InvalidFormat("layer 1 takes 2 features, but layer 0 gives 4".to_string())));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_keeps_activation_config() {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 2))],
+                                           vec![Box::new(SwishBeta::new(0.6)), Box::new(Softmax::with_axis(Axis::Col))],
+                                           Box::new(MSE::new()));
+        let path = temp_path("config");
+        model.save(&path).unwrap();
+        let mut loaded = NeuralNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.activations[0].config(), vec![0.6]);
+        assert!(!loaded.activations[1].is_row_softmax());
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(loaded.forward(&x), model.forward(&x));
+
+        // Activations that cannot be rebuilt are rejected before writing
+        let lambda = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![Box::new(Lambda::new(|z| z, |_| 1.0))], Box::new(MSE::new()));
+        let path = temp_path("lambda");
+        assert_eq!(lambda.save(&path).err(), Some(DnnError::UnknownActivation("lambda".to_string())));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_save_and_load_dropout() {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 1))],
+                                           vec![Box::new(Dropout::new(0.25))],
+                                           Box::new(MSE::new()));
+        let path = temp_path("dropout");
+        model.save(&path).unwrap();
+        let mut loaded = NeuralNetwork::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.activations[0].name(), "dropout");
+        assert_eq!(loaded.activations[0].config(), vec![0.25]);
+
+        model.eval();
+        loaded.eval();
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(loaded.forward(&x), model.forward(&x));
+    }
+}
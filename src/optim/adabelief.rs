@@ -0,0 +1,137 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{zeros_like_parameters, Optimizer};
+
+
+/**
+    * AdaBelief Optimizer
+    *
+    * AdaBelief is a variant of Adam. Both keep an exponential moving average
+    * (EMA) of the gradient, m, as the update direction, and scale the step of
+    * each parameter by a second moment estimate. Adam uses the EMA of the raw
+    * squared gradient, g^2. AdaBelief instead uses the EMA of (g - m)^2, the
+    * variance of the gradient around its own EMA, i.e. how much the observed
+    * gradient deviates from the "belief" m:
+    *
+    *   m = β1 * m + (1 - β1) * g
+    *   s = β2 * s + (1 - β2) * (g - m)^2
+    *   W -= lr * m_hat / (sqrt(s_hat) + eps)
+    *
+    * where m_hat = m / (1 - β1^t) and s_hat = s / (1 - β2^t) correct the bias
+    * of the zero initialization. When the gradient is consistent, s is small
+    * and AdaBelief takes large steps; when it is noisy, s is large and the
+    * steps shrink. This tends to generalize better than Adam.
+    *
+**/
+
+
+pub struct AdaBelief {
+    pub model: NeuralNetwork,
+    pub lr: f64, // Learning Rate
+    pub beta1: f64, // Decay rate of the gradient EMA
+    pub beta2: f64, // Decay rate of the gradient variance EMA
+    pub eps: f64, // Added to the denominator for numerical stability
+    pub m: Vec<DMatrix<f64>>, // Gradient EMA of each parameter (see NeuralNetwork::parameters)
+    pub s: Vec<DMatrix<f64>>, // Gradient variance EMA of each parameter
+    pub t: i32 // Number of steps taken, used for bias correction
+}
+
+impl AdaBelief {
+    // Constructor for the AdaBelief struct. The moment buffers start at zero
+    // with the shapes of each layer's parameters.
+    pub fn new(model: NeuralNetwork, lr: f64, beta1: f64, beta2: f64, eps: f64) -> Self {
+        let m = zeros_like_parameters(&model);
+        AdaBelief {
+            model: model,
+            lr: lr,
+            beta1: beta1,
+            beta2: beta2,
+            eps: eps,
+            s: m.clone(),
+            m: m,
+            t: 0
+        }
+    }
+
+    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        return Optimizer::update(self, x, y);
+    }
+}
+
+impl Optimizer for AdaBelief {
+    fn model(&self) -> &NeuralNetwork {
+        return &self.model;
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return &mut self.model;
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self) {
+        self.t += 1;
+        let bias_correction1 = 1.0 - self.beta1.powi(self.t);
+        let bias_correction2 = 1.0 - self.beta2.powi(self.t);
+
+        let gradients: Vec<DMatrix<f64>> = self.model.gradients().into_iter().cloned().collect();
+        let requires_grad = self.model.requires_grad();
+        for (k, param) in self.model.parameters_mut().into_iter().enumerate() {
+            // Frozen parameters are skipped, including their moment estimates
+            if !requires_grad[k] {
+                continue;
+            }
+            let g = &gradients[k];
+            self.m[k] = self.beta1 * &self.m[k] + (1.0 - self.beta1) * g;
+            // Second moment of the deviation from the EMA, not of the raw gradient
+            let dev = g - &self.m[k];
+            self.s[k] = self.beta2 * &self.s[k] + (1.0 - self.beta2) * dev.component_mul(&dev);
+            let step = (&self.m[k] / bias_correction1)
+                .zip_map(&self.s[k], |m, s| m / ((s / bias_correction2).sqrt() + self.eps));
+            *param -= self.lr * step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::optimizer::fixtures;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+
+    #[test]
+    fn test_adabelief_denominator_smaller_than_adam_for_consistent_gradients() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let (beta1, beta2) = (0.9, 0.999);
+        let mut optim = AdaBelief::new(model, 0.01, beta1, beta2, 1e-8);
+
+        // Gradients with a nonzero mean and a little jitter
+        let jitter = [0.0, 0.2, -0.1, 0.1, -0.2];
+        let mut adam_v = 0.0;
+        for t in 0..200 {
+            let g = 1.0 + jitter[t % jitter.len()];
+            optim.model.layers[0].dLdW = DMatrix::from_element(1, 2, g);
+            optim.model.layers[0].dLdb = DMatrix::from_element(1, 1, g);
+            optim.step();
+            adam_v = beta2 * adam_v + (1.0 - beta2) * g * g; // Adam's raw second moment
+        }
+
+        // AdaBelief only sees the small deviations from the mean gradient,
+        // while Adam's second moment is dominated by the mean itself
+        let s = optim.s[0][(0, 0)];
+        assert!(s > 0.0);
+        assert!(s < 0.1 * adam_v, "AdaBelief s = {}, Adam v = {}", s, adam_v);
+    }
+
+    #[test]
+    fn test_adabelief_converges() {
+        fixtures::assert_fits_line(&mut AdaBelief::new(fixtures::linear_model(), 0.05, 0.9, 0.999, 1e-8));
+    }
+}
@@ -0,0 +1,138 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{zeros_like_parameters, Optimizer};
+
+
+/**
+    * Adam Optimizer
+    *
+    * Adam (adaptive moment estimation) keeps exponential moving averages of
+    * the gradient, m, and of the squared gradient, v, for every parameter:
+    *
+    *   m = β1 * m + (1 - β1) * g
+    *   v = β2 * v + (1 - β2) * g^2
+    *   W -= lr * m_hat / (sqrt(v_hat) + eps)
+    *
+    * where m_hat = m / (1 - β1^t) and v_hat = v / (1 - β2^t) correct the bias
+    * of the zero initialization in the first steps. Dividing by sqrt(v_hat)
+    * gives every parameter its own step size, roughly lr whatever the scale
+    * of its gradient.
+    *
+**/
+
+
+pub struct Adam {
+    pub model: NeuralNetwork,
+    pub lr: f64, // Learning Rate
+    pub beta1: f64, // Decay rate of the gradient EMA
+    pub beta2: f64, // Decay rate of the squared gradient EMA
+    pub eps: f64, // Added to the denominator for numerical stability
+    pub m: Vec<DMatrix<f64>>, // Gradient EMA of each parameter (see NeuralNetwork::parameters)
+    pub v: Vec<DMatrix<f64>>, // Squared gradient EMA of each parameter
+    pub t: i32 // Number of steps taken, used for bias correction
+}
+
+impl Adam {
+    // Constructor for the Adam struct. The moment buffers start at zero with
+    // the shapes of the model's parameters, as SGD's velocity.
+    pub fn new(model: NeuralNetwork, lr: f64, beta1: f64, beta2: f64, eps: f64) -> Self {
+        let m = zeros_like_parameters(&model);
+        Adam {
+            model: model,
+            lr: lr,
+            beta1: beta1,
+            beta2: beta2,
+            eps: eps,
+            v: m.clone(),
+            m: m,
+            t: 0
+        }
+    }
+
+    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        return Optimizer::update(self, x, y);
+    }
+}
+
+impl Optimizer for Adam {
+    fn model(&self) -> &NeuralNetwork {
+        return &self.model;
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return &mut self.model;
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self) {
+        self.t += 1;
+        let bias_correction1 = 1.0 - self.beta1.powi(self.t);
+        let bias_correction2 = 1.0 - self.beta2.powi(self.t);
+
+        let gradients: Vec<DMatrix<f64>> = self.model.gradients().into_iter().cloned().collect();
+        let requires_grad = self.model.requires_grad();
+        for (k, param) in self.model.parameters_mut().into_iter().enumerate() {
+            // Frozen parameters are skipped, including their moment estimates
+            if !requires_grad[k] {
+                continue;
+            }
+            let g = &gradients[k];
+            self.m[k] = self.beta1 * &self.m[k] + (1.0 - self.beta1) * g;
+            self.v[k] = self.beta2 * &self.v[k] + (1.0 - self.beta2) * g.component_mul(g);
+            let step = (&self.m[k] / bias_correction1)
+                .zip_map(&self.v[k], |m, v| m / ((v / bias_correction2).sqrt() + self.eps));
+            *param -= self.lr * step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::optimizer::fixtures;
+    use crate::nn::activation::SwishBeta;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_adam_first_step_has_size_lr() {
+        // After bias correction m_hat = g and v_hat = g^2, so the first step
+        // is lr * sign(g) whatever the gradient scale
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = Adam::new(model, 0.01, 0.9, 0.999, 1e-12);
+        let W = optim.model.layers[0].W.clone();
+        optim.model.layers[0].dLdW = DMatrix::from_row_slice(1, 2, &[250.0, -0.003]);
+        optim.model.layers[0].dLdb = DMatrix::from_element(1, 1, 1.0);
+        optim.step();
+        assert_abs_diff_eq!(&W - &optim.model.layers[0].W, DMatrix::from_row_slice(1, 2, &[0.01, -0.01]), epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_adam_updates_activation_parameters() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 2))], vec![Box::new(SwishBeta::new(1.0))], Box::new(MSE::new()));
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                -1.0, 0.5]);
+        let y = DMatrix::from_row_slice(2, 2, &[3.0, 0.0,
+                                                0.0, 1.0]);
+        let mut optim = Adam::new(model, 0.01, 0.9, 0.999, 1e-12);
+        let _ = optim.update(&x, &y);
+        // Like every other parameter, β moves by lr against its gradient
+        let dLdbeta = optim.model.activations[0].gradients()[0][(0, 0)];
+        assert!(dLdbeta != 0.0);
+        let beta = optim.model.activations[0].parameters()[0][(0, 0)];
+        assert_abs_diff_eq!(beta, 1.0 - 0.01 * dLdbeta.signum(), epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_adam_converges() {
+        fixtures::assert_fits_line(&mut Adam::new(fixtures::linear_model(), 0.05, 0.9, 0.999, 1e-8));
+    }
+}
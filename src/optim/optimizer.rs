@@ -0,0 +1,412 @@
+use nalgebra::{DMatrix};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::nn::model::NeuralNetwork;
+use crate::data::DataLoader;
+use crate::scalar::Real;
+
+/**
+    * Optimizer Interface
+    *
+    * Every optimizer owns the model it trains and knows how to turn the
+    * gradients stored in the model (the dLdW and dLdb of the layers and the
+    * gradients of learnable activations, see NeuralNetwork::parameters)
+    * into a parameter update.
+    * That update is the only thing an optimizer has to implement (step);
+    * the training loop on top of it (update, fit) is shared by all of them.
+    *
+    * fit trains for a number of epochs over the mini-batches of a DataLoader
+    * and returns the History of the run, i.e. the average training loss of
+    * every epoch, the validation loss (when a validation loader is given) and
+    * the learning rate that was used. The History can be exported as JSON
+    * lines for downstream tooling. fit_arrays trains on an (X, Y) pair held
+    * in memory directly and only returns the per-epoch losses.
+    *
+**/
+
+
+// Per-epoch record of a training run
+pub struct History {
+    pub loss: Vec<f64>, // Average training loss of each epoch
+    pub val_loss: Vec<f64>, // Validation loss after each epoch (empty without a validation loader)
+    pub lr: Vec<f64>, // Learning rate used in each epoch
+    pub metrics: BTreeMap<String, Vec<f64>> // Any extra per-epoch metrics, by name
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            loss: Vec::new(),
+            val_loss: Vec::new(),
+            lr: Vec::new(),
+            metrics: BTreeMap::new()
+        }
+    }
+
+    // Writes the history to path in JSON lines format: one JSON object per
+    // epoch with the keys "epoch", "loss", "val_loss", "lr" and "metrics".
+    // Values that were not recorded for an epoch (e.g. val_loss without a
+    // validation loader) and non-finite values are written as null.
+    pub fn to_jsonl<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for epoch in 0..self.loss.len() {
+            let metrics: Vec<String> = self.metrics.iter()
+                .map(|(name, values)| format!("{}:{}", json_string(name), json_number(values.get(epoch))))
+                .collect();
+            writeln!(writer, "{{\"epoch\":{},\"loss\":{},\"val_loss\":{},\"lr\":{},\"metrics\":{{{}}}}}",
+                     epoch,
+                     json_number(self.loss.get(epoch)),
+                     json_number(self.val_loss.get(epoch)),
+                     json_number(self.lr.get(epoch)),
+                     metrics.join(","))?;
+        }
+        return writer.flush();
+    }
+}
+
+// Formats an optional value as a JSON number, or null when it is missing
+// or not finite (JSON has no NaN or infinity).
+fn json_number(value: Option<&f64>) -> String {
+    return match value {
+        Some(v) if v.is_finite() => format!("{}", v),
+        _ => "null".to_string()
+    };
+}
+
+// Formats a string as a quoted JSON string. Quotes and backslashes are
+// escaped, and control characters are written as \uXXXX escapes.
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    return escaped;
+}
+
+pub trait Optimizer {
+    fn model(&self) -> &NeuralNetwork;
+    fn model_mut(&mut self) -> &mut NeuralNetwork;
+    fn lr(&self) -> f64;
+    fn set_lr(&mut self, lr: f64);
+
+    // Updates the parameters of the model using the gradients computed by
+    // the last backward pass.
+    fn step(&mut self);
+
+    // One training step on a batch: forward pass, loss, backward pass and
+    // parameter update. Returns the loss of the batch before the update.
+    fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        let model = self.model_mut();
+        let Z = model.forward(x);
+        let loss = model.loss.forward(&Z, y);
+        model.backward();
+        self.step();
+        return loss;
+    }
+
+    // Same as update, for call sites that want to make explicit that the
+    // loss is used.
+    fn update_returning_loss(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        return self.update(x, y);
+    }
+
+    // Trains the model for the given number of epochs over the batches of
+    // loader. If a validation loader is given, the validation loss is
+    // recorded after every epoch.
+    //
+    // With grad_accum_steps = k > 1 the optimizer only steps every k batches,
+    // using the gradients of those batches averaged (weighted by batch size),
+    // which emulates a k times larger batch size without the memory cost.
+    // Any leftover batches at the end of an epoch are stepped on together.
+    fn fit(&mut self, loader: &mut DataLoader, mut val_loader: Option<&mut DataLoader>, epochs: usize,
+           grad_accum_steps: usize) -> History {
+        assert!(grad_accum_steps > 0, "grad_accum_steps must be positive");
+        let mut history = History::new();
+        for _ in 0..epochs {
+            let mut total_loss = 0.0;
+            let mut accumulator = GradientAccumulator::new();
+            for (x, y) in loader.batches() {
+                let model = self.model_mut();
+                let Z = model.forward(&x);
+                total_loss += model.loss.forward(&Z, &y) * x.nrows() as f64;
+                model.backward();
+                if grad_accum_steps == 1 {
+                    self.step();
+                    continue;
+                }
+
+                accumulator.add(self.model(), x.nrows());
+                if accumulator.num_batches == grad_accum_steps {
+                    accumulator.apply(self.model_mut());
+                    self.step();
+                }
+            }
+            if accumulator.num_batches > 0 {
+                accumulator.apply(self.model_mut());
+                self.step();
+            }
+            history.loss.push(total_loss / loader.num_samples() as f64);
+            history.lr.push(self.lr());
+
+            if let Some(val_loader) = val_loader.as_deref_mut() {
+                history.val_loss.push(evaluate_loader(self.model_mut(), val_loader));
+            }
+        }
+        return history;
+    }
+
+    // Convenience wrapper around fit for a dataset held in memory: shuffles
+    // the samples (rows) of X and Y every epoch with an RNG seeded by seed,
+    // trains on mini-batches of batch_size (the last one smaller when N is
+    // not a multiple of batch_size) and returns the average training loss of
+    // each epoch.
+    fn fit_arrays(&mut self, X: &DMatrix<f64>, Y: &DMatrix<f64>, batch_size: usize, epochs: usize, seed: u64) -> Vec<f64> {
+        let mut loader = DataLoader::new(X.clone(), Y.clone(), batch_size, true, seed);
+        return self.fit(&mut loader, None, epochs, 1).loss;
+    }
+}
+
+// Running sum of the gradients of several batches, each weighted by its
+// number of samples, used for gradient accumulation in fit. It holds one
+// sum per parameter, in the order of NeuralNetwork::gradients.
+struct GradientAccumulator {
+    grads: Vec<DMatrix<f64>>,
+    num_samples: usize,
+    num_batches: usize
+}
+
+impl GradientAccumulator {
+    fn new() -> Self {
+        GradientAccumulator {
+            grads: Vec::new(),
+            num_samples: 0,
+            num_batches: 0
+        }
+    }
+
+    // Adds the gradients of the last backward pass over a batch of n samples
+    fn add(&mut self, model: &NeuralNetwork, n: usize) {
+        if self.num_batches == 0 {
+            self.grads = model.gradients().iter().map(|g| n as f64 * *g).collect();
+        } else {
+            for (sum, grad) in self.grads.iter_mut().zip(model.gradients()) {
+                *sum += n as f64 * grad;
+            }
+        }
+        self.num_samples += n;
+        self.num_batches += 1;
+    }
+
+    // Replaces the gradients by the accumulated average and resets
+    fn apply(&mut self, model: &mut NeuralNetwork) {
+        let n = self.num_samples as f64;
+        for (grad, sum) in model.gradients_mut().into_iter().zip(self.grads.iter()) {
+            *grad = sum / n;
+        }
+        self.num_samples = 0;
+        self.num_batches = 0;
+    }
+}
+
+// Zero matrices with the shapes of every parameter of a model, in the order
+// of NeuralNetwork::parameters, e.g. for the moment buffers of an optimizer
+pub(crate) fn zeros_like_parameters<T: Real>(model: &NeuralNetwork<T>) -> Vec<DMatrix<T>> {
+    return model.parameters().iter().map(|p| DMatrix::zeros(p.nrows(), p.ncols())).collect();
+}
+
+// Average loss of the model over every sample of a loader
+pub fn evaluate_loader(model: &mut NeuralNetwork, loader: &mut DataLoader) -> f64 {
+    let mut total_loss = 0.0;
+    for (x, y) in loader.batches() {
+        total_loss += model.evaluate(&x, &y) * x.nrows() as f64;
+    }
+    return total_loss / loader.num_samples() as f64;
+}
+
+// Fixtures shared by the tests of the optimizers
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+
+    // 16 points of the line y = slope * x + intercept, with x evenly spaced in [-1, 1)
+    pub fn line(slope: f64, intercept: f64) -> (DMatrix<f64>, DMatrix<f64>) {
+        let x = DMatrix::from_fn(16, 1, |i, _| i as f64 / 8.0 - 1.0);
+        let y = x.map(|v| slope * v + intercept);
+        return (x, y);
+    }
+
+    // A single Linear(1, 1) layer with MSE, which can fit a line exactly
+    pub fn linear_model() -> NeuralNetwork {
+        return NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+    }
+
+    // Asserts that 500 full-batch updates of an optimizer over linear_model
+    // fit the line y = 2x + 0.5
+    pub fn assert_fits_line<O: Optimizer>(optim: &mut O) {
+        let (x, y) = line(2.0, 0.5);
+        for _ in 0..500 {
+            optim.update(&x, &y);
+        }
+        let loss = optim.model_mut().evaluate(&x, &y);
+        assert!(loss < 1e-3, "loss {} after 500 updates", loss);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::sgd::SGD;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_update_returns_loss_before_step() {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -1.0,
+                                                0.5, 2.0,
+                                                -1.5, 0.0]);
+        let y = DMatrix::from_row_slice(3, 1, &[0.5, -1.0, 2.0]);
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0);
+
+        let expected = optim.model.evaluate(&x, &y);
+        assert_abs_diff_eq!(optim.update(&x, &y), expected, epsilon = 1e-12);
+        let expected = optim.model.evaluate(&x, &y);
+        assert_abs_diff_eq!(optim.update_returning_loss(&x, &y), expected, epsilon = 1e-12);
+        assert!(optim.model.evaluate(&x, &y) < expected);
+    }
+
+    #[test]
+    fn test_fit_arrays_with_ragged_batches() {
+        // 10 samples in batches of 4, 4 and 2
+        let X = DMatrix::from_fn(10, 1, |i, _| i as f64 / 5.0 - 1.0);
+        let Y = X.map(|x| 1.0 - 3.0 * x);
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+        let twin = model.clone();
+        let mut optim = SGD::new(model, 0.1, 0.0);
+        let losses = optim.fit_arrays(&X, &Y, 4, 100, 3);
+        assert_eq!(losses.len(), 100);
+        assert!(losses.iter().all(|l| l.is_finite()));
+        assert!(losses[99] < 1e-2 * losses[0]);
+
+        // The same seed reproduces the run
+        let mut rerun = SGD::new(twin, 0.1, 0.0);
+        assert_eq!(rerun.fit_arrays(&X, &Y, 4, 100, 3), losses);
+    }
+
+    #[test]
+    fn test_fit_reduces_loss() {
+        // y = 2x + 1, fit by a single linear layer
+        let X = DMatrix::from_fn(32, 1, |i, _| i as f64 / 16.0 - 1.0);
+        let Y = X.map(|x| 2.0 * x + 1.0);
+        let mut loader = DataLoader::new(X.clone(), Y.clone(), 8, true, 0);
+        let mut val_loader = DataLoader::new(X, Y, 32, false, 0);
+
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0);
+        let history = optim.fit(&mut loader, Some(&mut val_loader), 50, 1);
+
+        assert_eq!(history.loss.len(), 50);
+        assert_eq!(history.val_loss.len(), 50);
+        assert!(history.lr.iter().all(|lr| *lr == 0.1));
+        assert!(history.loss[49] < history.loss[0]);
+        assert!(history.val_loss[49] < 1e-3);
+    }
+
+    #[test]
+    fn test_fit_grad_accumulation_matches_larger_batches() {
+        let X = DMatrix::from_fn(24, 2, |i, j| ((i * 7 + j * 3) % 11) as f64 / 5.0 - 1.0);
+        let Y = DMatrix::from_fn(24, 1, |i, _| X[(i, 0)] - 2.0 * X[(i, 1)] + 0.3);
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut twin = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        twin.layers[0].W = model.layers[0].W.clone();
+        twin.layers[0].b = model.layers[0].b.clone();
+
+        let mut accumulated = SGD::new(model, 0.1, 0.9);
+        let mut large_batch = SGD::new(twin, 0.1, 0.9);
+        let mut small_loader = DataLoader::new(X.clone(), Y.clone(), 4, false, 0);
+        let mut large_loader = DataLoader::new(X, Y, 8, false, 0);
+
+        for _ in 0..5 {
+            let _ = accumulated.fit(&mut small_loader, None, 1, 2);
+            let _ = large_batch.fit(&mut large_loader, None, 1, 1);
+            assert_abs_diff_eq!(accumulated.model.layers[0].W, large_batch.model.layers[0].W, epsilon = 1e-12);
+            assert_abs_diff_eq!(accumulated.model.layers[0].b, large_batch.model.layers[0].b, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_history_to_jsonl() {
+        let X = DMatrix::from_fn(8, 1, |i, _| i as f64 / 8.0);
+        let Y = X.map(|x| 0.5 * x);
+        let mut loader = DataLoader::new(X.clone(), Y.clone(), 4, false, 0);
+        let mut val_loader = DataLoader::new(X, Y, 8, false, 0);
+
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0);
+        let mut history = optim.fit(&mut loader, Some(&mut val_loader), 3, 1);
+        history.metrics.insert("accuracy".to_string(), vec![0.5, 0.75, 1.0]);
+
+        let path = std::env::temp_dir().join(format!("dnn_rs_history_{}.jsonl", std::process::id()));
+        history.to_jsonl(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for (epoch, line) in lines.iter().enumerate() {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(record["epoch"], epoch);
+            assert_abs_diff_eq!(record["loss"].as_f64().unwrap(), history.loss[epoch], epsilon = 1e-12);
+            assert_abs_diff_eq!(record["val_loss"].as_f64().unwrap(), history.val_loss[epoch], epsilon = 1e-12);
+            assert_abs_diff_eq!(record["lr"].as_f64().unwrap(), 0.1, epsilon = 1e-12);
+            assert_abs_diff_eq!(record["metrics"]["accuracy"].as_f64().unwrap(), history.metrics["accuracy"][epoch], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_history_to_jsonl_missing_values_are_null() {
+        let mut history = History::new();
+        history.loss = vec![1.0, f64::NAN];
+        history.lr = vec![0.1, 0.1];
+
+        let path = std::env::temp_dir().join(format!("dnn_rs_history_null_{}.jsonl", std::process::id()));
+        history.to_jsonl(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let records: Vec<serde_json::Value> = contents.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert!(records[0]["val_loss"].is_null());
+        assert!(records[1]["loss"].is_null());
+        assert!(records[1]["metrics"].as_object().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_history_to_jsonl_escapes_metric_names() {
+        let mut history = History::new();
+        history.loss = vec![1.0];
+        history.lr = vec![0.1];
+        let name = "top\u{1}\"k\"\\n\t\u{7f}é";
+        history.metrics.insert(name.to_string(), vec![0.5]);
+
+        let path = std::env::temp_dir().join(format!("dnn_rs_history_escape_{}.jsonl", std::process::id()));
+        history.to_jsonl(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(contents.contains("\\u0001"));
+        let record: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+        assert_eq!(record["metrics"][name], 0.5);
+    }
+}
@@ -0,0 +1,178 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{zeros_like_parameters, Optimizer};
+
+
+/**
+    * RAdam (Rectified Adam) Optimizer
+    *
+    * Adam keeps exponential moving averages of the gradient, m, and of the
+    * squared gradient, v, and scales the step of each parameter by
+    * 1 / sqrt(v_hat). In the first steps v is estimated from very few
+    * gradients, so its variance is large and the adaptive step size is
+    * unreliable, which is why Adam usually needs a learning rate warmup.
+    *
+    * RAdam removes that need by rectifying the variance of the adaptive step.
+    * With ρ_∞ = 2 / (1 - β2) - 1, the length of the approximated simple moving
+    * average at step t is
+    *
+    *   ρ_t = ρ_∞ - 2 * t * β2^t / (1 - β2^t)
+    *
+    * While ρ_t <= 5 the variance is intractable and RAdam takes a plain
+    * momentum step, W -= lr * m_hat. Afterwards it takes the Adam step scaled
+    * by the rectification term
+    *
+    *   r_t = sqrt(((ρ_t - 4) * (ρ_t - 2) * ρ_∞) / ((ρ_∞ - 4) * (ρ_∞ - 2) * ρ_t))
+    *   W -= lr * r_t * m_hat / (sqrt(v_hat) + eps)
+    *
+    * where m_hat = m / (1 - β1^t) and v_hat = v / (1 - β2^t) are the bias
+    * corrected moments.
+    *
+**/
+
+
+// Rectification term r_t of step t, or None while the variance of the
+// adaptive step is intractable (ρ_t <= 5) and the momentum step is used.
+fn rectification(beta2: f64, t: i32) -> Option<f64> {
+    let rho_inf = 2.0 / (1.0 - beta2) - 1.0;
+    let beta2_t = beta2.powi(t);
+    let rho_t = rho_inf - 2.0 * t as f64 * beta2_t / (1.0 - beta2_t);
+    if rho_t <= 5.0 {
+        return None;
+    }
+    let r_t = ((rho_t - 4.0) * (rho_t - 2.0) * rho_inf / ((rho_inf - 4.0) * (rho_inf - 2.0) * rho_t)).sqrt();
+    return Some(r_t);
+}
+
+// Update direction of one parameter from its moments: the rectified adaptive
+// step r_t * m_hat / (sqrt(v_hat) + eps), or the momentum step m_hat while
+// the variance is not yet tractable (r_t = None).
+fn rectified_step(m: &DMatrix<f64>, v: &DMatrix<f64>, bias_correction1: f64, bias_correction2: f64,
+                  r_t: Option<f64>, eps: f64) -> DMatrix<f64> {
+    let m_hat = m / bias_correction1;
+    return match r_t {
+        Some(r_t) => r_t * m_hat.zip_map(v, |m, v| m / ((v / bias_correction2).sqrt() + eps)),
+        None => m_hat
+    };
+}
+
+pub struct RAdam {
+    pub model: NeuralNetwork,
+    pub lr: f64, // Learning Rate
+    pub beta1: f64, // Decay rate of the gradient EMA
+    pub beta2: f64, // Decay rate of the squared gradient EMA
+    pub eps: f64, // Added to the denominator for numerical stability
+    pub m: Vec<DMatrix<f64>>, // Gradient EMA of each parameter (see NeuralNetwork::parameters)
+    pub v: Vec<DMatrix<f64>>, // Squared gradient EMA of each parameter
+    pub t: i32 // Number of steps taken, used for bias correction and rectification
+}
+
+impl RAdam {
+    // Constructor for the RAdam struct. The moment buffers start at zero
+    // with the shapes of each layer's parameters.
+    pub fn new(model: NeuralNetwork, lr: f64, beta1: f64, beta2: f64, eps: f64) -> Self {
+        let m = zeros_like_parameters(&model);
+        RAdam {
+            model: model,
+            lr: lr,
+            beta1: beta1,
+            beta2: beta2,
+            eps: eps,
+            v: m.clone(),
+            m: m,
+            t: 0
+        }
+    }
+
+    // Whether the last step taken used the rectified adaptive update rather
+    // than the momentum fallback.
+    pub fn is_rectified(&self) -> bool {
+        return self.t > 0 && rectification(self.beta2, self.t).is_some();
+    }
+
+    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        return Optimizer::update(self, x, y);
+    }
+}
+
+impl Optimizer for RAdam {
+    fn model(&self) -> &NeuralNetwork {
+        return &self.model;
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return &mut self.model;
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self) {
+        self.t += 1;
+        let bias_correction1 = 1.0 - self.beta1.powi(self.t);
+        let bias_correction2 = 1.0 - self.beta2.powi(self.t);
+        let r_t = rectification(self.beta2, self.t);
+
+        let gradients: Vec<DMatrix<f64>> = self.model.gradients().into_iter().cloned().collect();
+        let requires_grad = self.model.requires_grad();
+        for (k, param) in self.model.parameters_mut().into_iter().enumerate() {
+            // Frozen parameters are skipped, including their moment estimates
+            if !requires_grad[k] {
+                continue;
+            }
+            let g = &gradients[k];
+            self.m[k] = self.beta1 * &self.m[k] + (1.0 - self.beta1) * g;
+            self.v[k] = self.beta2 * &self.v[k] + (1.0 - self.beta2) * g.component_mul(g);
+            let step = rectified_step(&self.m[k], &self.v[k], bias_correction1, bias_correction2, r_t, self.eps);
+            *param -= self.lr * step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::optimizer::fixtures;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_radam_switches_to_adaptive_step_after_warmup() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+        let (lr, beta2) = (0.01, 0.999);
+        let mut optim = RAdam::new(model, lr, 0.9, beta2, 1e-8);
+
+        // With a constant gradient g, m_hat = g and v_hat = g^2, so the momentum
+        // step is lr * g while the rectified step is lr * r_t (independent of g)
+        let g = 3.0;
+        for t in 1..=10 {
+            optim.model.layers[0].dLdW = DMatrix::from_element(1, 1, g);
+            optim.model.layers[0].dLdb = DMatrix::from_element(1, 1, g);
+            let before = optim.model.layers[0].W[(0, 0)];
+            optim.step();
+            let delta = before - optim.model.layers[0].W[(0, 0)];
+
+            // ρ_t first exceeds 5 at t = 6 for β2 = 0.999
+            if t < 6 {
+                assert!(!optim.is_rectified(), "step {}", t);
+                assert_abs_diff_eq!(delta, lr * g, epsilon = 1e-12);
+            } else {
+                assert!(optim.is_rectified(), "step {}", t);
+                let r_t = rectification(beta2, t).unwrap();
+                assert!(r_t > 0.0 && r_t < 1.0);
+                assert_abs_diff_eq!(delta, lr * r_t, epsilon = 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_radam_converges() {
+        fixtures::assert_fits_line(&mut RAdam::new(fixtures::linear_model(), 0.05, 0.9, 0.999, 1e-8));
+    }
+}
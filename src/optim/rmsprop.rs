@@ -0,0 +1,107 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{zeros_like_parameters, Optimizer};
+
+
+/**
+    * RMSProp Optimizer
+    *
+    * RMSProp keeps an exponential moving average of the squared gradient, s,
+    * for every parameter and divides the gradient by its root:
+    *
+    *   s = α * s + (1 - α) * g^2
+    *   W -= lr * g / (sqrt(s) + eps)
+    *
+    * Like Adam's second moment, this gives every parameter a step size of
+    * roughly lr whatever the scale of its gradient, but without momentum and
+    * without bias correction, so the first steps are larger than lr (by
+    * 1 / sqrt(1 - α) for the very first one).
+    *
+**/
+
+
+pub struct RMSProp {
+    pub model: NeuralNetwork,
+    pub lr: f64, // Learning Rate
+    pub alpha: f64, // Decay rate of the squared gradient EMA
+    pub eps: f64, // Added to the denominator for numerical stability
+    pub s: Vec<DMatrix<f64>> // Squared gradient EMA of each parameter (see NeuralNetwork::parameters)
+}
+
+impl RMSProp {
+    // Constructor for the RMSProp struct. The squared gradient averages start
+    // at zero with the shapes of each layer's parameters, as SGD's velocity.
+    pub fn new(model: NeuralNetwork, lr: f64, alpha: f64, eps: f64) -> Self {
+        let s = zeros_like_parameters(&model);
+        RMSProp {
+            model: model,
+            lr: lr,
+            alpha: alpha,
+            eps: eps,
+            s: s
+        }
+    }
+
+    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        return Optimizer::update(self, x, y);
+    }
+}
+
+impl Optimizer for RMSProp {
+    fn model(&self) -> &NeuralNetwork {
+        return &self.model;
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return &mut self.model;
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self) {
+        let gradients: Vec<DMatrix<f64>> = self.model.gradients().into_iter().cloned().collect();
+        let requires_grad = self.model.requires_grad();
+        for (k, param) in self.model.parameters_mut().into_iter().enumerate() {
+            // Frozen parameters are skipped, including their averages
+            if !requires_grad[k] {
+                continue;
+            }
+            let g = &gradients[k];
+            self.s[k] = self.alpha * &self.s[k] + (1.0 - self.alpha) * g.component_mul(g);
+            let step = g.zip_map(&self.s[k], |g, s| g / (s.sqrt() + self.eps));
+            *param -= self.lr * step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::optimizer::fixtures;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_rmsprop_first_step_is_scale_free() {
+        // s = (1 - α) g^2, so the first step is lr / sqrt(1 - α) * sign(g)
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = RMSProp::new(model, 0.01, 0.75, 1e-12);
+        let W = optim.model.layers[0].W.clone();
+        optim.model.layers[0].dLdW = DMatrix::from_row_slice(1, 2, &[250.0, -0.003]);
+        optim.model.layers[0].dLdb = DMatrix::from_element(1, 1, 1.0);
+        optim.step();
+        assert_abs_diff_eq!(&W - &optim.model.layers[0].W, DMatrix::from_row_slice(1, 2, &[0.02, -0.02]), epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_rmsprop_converges() {
+        fixtures::assert_fits_line(&mut RMSProp::new(fixtures::linear_model(), 0.01, 0.9, 1e-8));
+    }
+}
@@ -0,0 +1,152 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::Optimizer;
+
+
+/**
+    * Sharpness-Aware Minimization (SAM)
+    *
+    * SAM looks for parameters whose whole neighborhood has a low loss, i.e.
+    * flat minima, which tend to generalize better than sharp ones. It
+    * minimizes the worst-case loss max_{‖ε‖ <= ρ} L(θ + ε) with two gradient
+    * evaluations per step:
+    *
+    *   1. ascent:  ε = ρ * ∇L(θ) / ‖∇L(θ)‖, the first order worst case
+    *   2. descent: g = ∇L(θ + ε), then the base optimizer steps from θ
+    *               (not from θ + ε) using g
+    *
+    * Sam wraps any other optimizer, which performs the actual update. The
+    * norm ‖∇L(θ)‖ is taken over all parameters of the model together.
+    *
+**/
+
+
+pub struct Sam<O: Optimizer> {
+    pub base: O, // Optimizer applying the update with the perturbed gradient
+    pub rho: f64 // Radius of the neighborhood
+}
+
+impl<O: Optimizer> Sam<O> {
+    pub fn new(base: O, rho: f64) -> Self {
+        assert!(rho >= 0.0, "rho must be non-negative, got {}", rho);
+        Sam {
+            base: base,
+            rho: rho
+        }
+    }
+
+    pub fn model(&self) -> &NeuralNetwork {
+        return self.base.model();
+    }
+
+    // Ascent step: computes the gradient on the batch and moves the
+    // parameters to θ + ε with ε = ρ ∇L / ‖∇L‖. Returns ε (in the layout of
+    // flat_parameters) and the loss at θ.
+    pub fn first_step(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> (Vec<f64>, f64) {
+        let model = self.base.model_mut();
+        let Z = model.forward(x);
+        let loss = model.loss.forward(&Z, y);
+        model.backward();
+
+        let grad = model.flat_gradients();
+        let norm = grad.iter().map(|g| g * g).sum::<f64>().sqrt();
+        let scale = if norm > 0.0 { self.rho / norm } else { 0.0 };
+        let eps: Vec<f64> = grad.iter().map(|g| scale * g).collect();
+
+        let perturbed: Vec<f64> = model.flat_parameters().iter().zip(eps.iter()).map(|(p, e)| p + e).collect();
+        model.set_flat_parameters(&perturbed);
+        return (eps, loss);
+    }
+
+    // Descent step: computes the gradient at the perturbed parameters θ + ε,
+    // moves the parameters back to θ and lets the base optimizer step with
+    // the perturbed gradient.
+    pub fn second_step(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>, eps: &[f64]) {
+        let model = self.base.model_mut();
+        let _ = model.output_grad(x, y);
+        model.backward();
+
+        let original: Vec<f64> = model.flat_parameters().iter().zip(eps.iter()).map(|(p, e)| p - e).collect();
+        model.set_flat_parameters(&original);
+        self.base.step();
+    }
+
+    // One full SAM step on a batch. Returns the loss at the parameters
+    // before the step.
+    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        let (eps, loss) = self.first_step(x, y);
+        self.second_step(x, y, &eps);
+        return loss;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::ReLU;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::sgd::SGD;
+    use approx::assert_abs_diff_eq;
+
+    fn network() -> NeuralNetwork {
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 3)), Box::new(Linear::new(3, 1))],
+                                             vec![Box::new(ReLU::new())],
+                                             Box::new(MSE::new()));
+        let params: Vec<f64> = (0..network.num_parameters()).map(|k| ((k * 5) % 7) as f64 / 7.0 - 0.3).collect();
+        network.set_flat_parameters(&params);
+        return network;
+    }
+
+    fn batch() -> (DMatrix<f64>, DMatrix<f64>) {
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, -2.0,
+                                                0.5, 0.5,
+                                                -1.0, 3.0]);
+        let y = DMatrix::from_row_slice(3, 1, &[1.0, 0.0, 2.0]);
+        return (x, y);
+    }
+
+    #[test]
+    fn test_sam_ascent_step_has_norm_rho() {
+        let (x, y) = batch();
+        let rho = 0.05;
+        let mut sam = Sam::new(SGD::new(network(), 0.1, 0.0), rho);
+        let before = sam.model().flat_parameters();
+        let (eps, _) = sam.first_step(&x, &y);
+        let after = sam.model().flat_parameters();
+
+        let moved: f64 = before.iter().zip(after.iter()).map(|(b, a)| (a - b) * (a - b)).sum::<f64>().sqrt();
+        assert_abs_diff_eq!(moved, rho, epsilon = 1e-12);
+        for k in 0..eps.len() {
+            assert_abs_diff_eq!(after[k] - before[k], eps[k], epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_sam_steps_from_original_weights_with_perturbed_gradient() {
+        let (x, y) = batch();
+        let (lr, rho) = (0.1, 0.05);
+
+        // Gradient at the perturbed point, computed by hand
+        let mut reference = network();
+        let _ = reference.output_grad(&x, &y);
+        reference.backward();
+        let grad = reference.flat_gradients();
+        let norm = grad.iter().map(|g| g * g).sum::<f64>().sqrt();
+        let original = reference.flat_parameters();
+        let perturbed: Vec<f64> = original.iter().zip(grad.iter()).map(|(p, g)| p + rho * g / norm).collect();
+        reference.set_flat_parameters(&perturbed);
+        let _ = reference.output_grad(&x, &y);
+        reference.backward();
+        let perturbed_grad = reference.flat_gradients();
+
+        let mut sam = Sam::new(SGD::new(network(), lr, 0.0), rho);
+        let _ = sam.update(&x, &y);
+        let params = sam.model().flat_parameters();
+        for k in 0..params.len() {
+            assert_abs_diff_eq!(params[k], original[k] - lr * perturbed_grad[k], epsilon = 1e-12);
+        }
+        // The perturbed gradient differs from the plain one, so SAM is not plain SGD
+        assert!(grad.iter().zip(perturbed_grad.iter()).any(|(g, p)| (g - p).abs() > 1e-6));
+    }
+}
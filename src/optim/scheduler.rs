@@ -0,0 +1,188 @@
+use std::f64::consts::PI;
+
+/**
+    * Learning Rate Schedulers
+    *
+    * A fixed learning rate is rarely ideal for the whole training run. Early on
+    * a large step size makes quick progress, but later it causes the loss to
+    * bounce around a minimum instead of settling into it. Schedulers adjust the
+    * learning rate of an optimizer as training progresses.
+    *
+    * Currently, the following schedulers are implemented:
+    * 1. ReduceLROnPlateau - Multiplies the learning rate by `factor` once a
+    *                        monitored metric (e.g. validation loss) has not
+    *                        improved for `patience` consecutive steps.
+    * 2. StepDecay - Multiplies the base learning rate by gamma every
+    *                step_size steps: lr = base * gamma^⌊step / step_size⌋.
+    * 3. ExponentialDecay - Multiplies the base learning rate by gamma every
+    *                       step: lr = base * gamma^step.
+    * 4. CosineAnnealing - Anneals the learning rate from base to 0 along half
+    *                      a cosine over t_max steps:
+    *                      lr = base * (1 + cos(π * step / t_max)) / 2,
+    *                      and stays at 0 afterwards.
+    *
+    * StepDecay, ExponentialDecay and CosineAnnealing only depend on the
+    * number of steps taken so far and implement the Scheduler trait, which
+    * lets an optimizer (see SGD::with_scheduler) set its learning rate before
+    * every step.
+    *
+**/
+
+
+pub trait Scheduler {
+    // Learning rate to use for the given step, counting from 0
+    fn lr(&mut self, step: usize) -> f64;
+}
+
+
+pub struct ReduceLROnPlateau {
+    pub lr: f64, // Current learning rate
+    pub factor: f64, // Multiplicative factor applied on a plateau (0 < factor < 1)
+    pub patience: usize, // Number of steps without improvement before reducing
+    pub min_lr: f64, // Lower bound on the learning rate
+    best: f64, // Best (lowest) metric seen so far
+    num_bad_steps: usize // Steps since the last improvement
+}
+
+impl ReduceLROnPlateau {
+    // Constructor for the ReduceLROnPlateau struct. Starts from the learning
+    // rate lr and monitors a metric where lower is better.
+    pub fn new(lr: f64, factor: f64, patience: usize, min_lr: f64) -> Self {
+        assert!(factor > 0.0 && factor < 1.0, "factor must be in (0, 1), got {}", factor);
+        ReduceLROnPlateau {
+            lr: lr,
+            factor: factor,
+            patience: patience,
+            min_lr: min_lr,
+            best: f64::INFINITY,
+            num_bad_steps: 0
+        }
+    }
+
+    // Records a new value of the monitored metric and returns the learning
+    // rate to use from now on. If the metric has not improved on the best value
+    // for `patience` calls, the learning rate is multiplied by `factor` (but
+    // never below min_lr) and the patience counter starts over.
+    pub fn step(&mut self, metric: f64) -> f64 {
+        if metric < self.best {
+            self.best = metric;
+            self.num_bad_steps = 0;
+        } else {
+            self.num_bad_steps += 1;
+        }
+
+        if self.num_bad_steps >= self.patience {
+            self.lr = (self.lr * self.factor).max(self.min_lr);
+            self.num_bad_steps = 0;
+        }
+        return self.lr;
+    }
+}
+
+pub struct StepDecay {
+    pub base: f64, // Initial learning rate
+    pub gamma: f64, // Multiplicative factor applied every step_size steps
+    pub step_size: usize // Number of steps between two decays
+}
+
+impl StepDecay {
+    pub fn new(base: f64, gamma: f64, step_size: usize) -> Self {
+        assert!(step_size > 0, "step_size must be positive");
+        StepDecay {
+            base: base,
+            gamma: gamma,
+            step_size: step_size
+        }
+    }
+}
+
+impl Scheduler for StepDecay {
+    fn lr(&mut self, step: usize) -> f64 {
+        return self.base * self.gamma.powi((step / self.step_size) as i32);
+    }
+}
+
+pub struct ExponentialDecay {
+    pub base: f64, // Initial learning rate
+    pub gamma: f64 // Multiplicative factor applied every step
+}
+
+impl ExponentialDecay {
+    pub fn new(base: f64, gamma: f64) -> Self {
+        ExponentialDecay {
+            base: base,
+            gamma: gamma
+        }
+    }
+}
+
+impl Scheduler for ExponentialDecay {
+    fn lr(&mut self, step: usize) -> f64 {
+        return self.base * self.gamma.powi(step as i32);
+    }
+}
+
+pub struct CosineAnnealing {
+    pub base: f64, // Initial learning rate
+    pub t_max: usize // Number of steps to anneal over
+}
+
+impl CosineAnnealing {
+    pub fn new(base: f64, t_max: usize) -> Self {
+        assert!(t_max > 0, "t_max must be positive");
+        CosineAnnealing {
+            base: base,
+            t_max: t_max
+        }
+    }
+}
+
+impl Scheduler for CosineAnnealing {
+    fn lr(&mut self, step: usize) -> f64 {
+        let t = step.min(self.t_max) as f64 / self.t_max as f64;
+        return self.base * (1.0 + (PI * t).cos()) / 2.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_reduce_lr_on_plateau() {
+        let mut scheduler = ReduceLROnPlateau::new(0.1, 0.5, 2, 0.02);
+
+        // Improving metric keeps the learning rate
+        assert_abs_diff_eq!(scheduler.step(1.0), 0.1, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.1, epsilon = 1e-12);
+
+        // Plateau: the lr drops only once `patience` steps pass without improvement
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.1, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.9), 0.05, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.05, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.025, epsilon = 1e-12);
+
+        // The lr is clamped at min_lr on further plateaus
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.025, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.02, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.02, epsilon = 1e-12);
+        assert_abs_diff_eq!(scheduler.step(0.8), 0.02, epsilon = 1e-12);
+
+        // A new best resets the patience counter
+        assert_abs_diff_eq!(scheduler.step(0.5), 0.02, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_step_based_schedulers() {
+        let mut exponential = ExponentialDecay::new(0.1, 0.9);
+        assert_abs_diff_eq!(exponential.lr(0), 0.1, epsilon = 1e-12);
+        assert_abs_diff_eq!(exponential.lr(3), 0.1 * 0.729, epsilon = 1e-12);
+
+        let mut cosine = CosineAnnealing::new(0.1, 10);
+        assert_abs_diff_eq!(cosine.lr(0), 0.1, epsilon = 1e-12);
+        assert_abs_diff_eq!(cosine.lr(5), 0.05, epsilon = 1e-12);
+        assert_abs_diff_eq!(cosine.lr(10), 0.0, epsilon = 1e-12);
+        assert_abs_diff_eq!(cosine.lr(20), 0.0, epsilon = 1e-12);
+    }
+}
@@ -1,5 +1,10 @@
 use nalgebra::{DMatrix};
 use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{zeros_like_parameters, Optimizer};
+use crate::optim::scheduler::Scheduler;
+use crate::scalar::{from_f64, Real};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
 
 /**
@@ -13,69 +18,382 @@ use crate::nn::model::NeuralNetwork;
     * the loss with respect to the parameters for each sample in the training
     * data, and then updating the parameters using the average gradient over
     * the entire training data.
+    *
+    * With nesterov set, the momentum update uses the look-ahead gradient of
+    * Nesterov accelerated gradient: v = μ * v + dLdW, W -= lr * (μ * v + dLdW),
+    * instead of W -= lr * v.
+    *
+    * With weight decay λ > 0, λ * W is added to the weight gradient before
+    * the update, which is the gradient of the L2 penalty λ/2 * ||W||^2 and
+    * pulls the weights toward zero. Biases are not decayed.
+    *
+    * With max_grad_norm set, the gradients of all layers are clipped by their
+    * global L2 norm before each step (see NeuralNetwork::clip_grad_norm).
+    *
+    * With a Scheduler set, lr is recomputed from the number of steps taken
+    * so far at the start of every step, i.e. of every update (and every step
+    * of fit). Without one, lr stays constant.
+    *
+    * Learnable parameters of activations (e.g. the β of SwishBeta) are
+    * updated like the layer parameters, with momentum but without weight
+    * decay.
+    *
+    * With the rayon feature, the element-wise updates of large matrices (of
+    * the parameters and the velocities) run in parallel over chunks of the
+    * matrix, with bit-identical results to the sequential path.
+    *
+    * SGD is generic over the scalar type of its model, e.g. SGD<f32> trains
+    * a NeuralNetwork<f32> with update and step. The Optimizer trait, and
+    * with it fit and the learning rate schedulers, is implemented for f64.
 
 **/
 
 
-pub struct SGD {
-    pub model: NeuralNetwork,
-    pub lr: f64, // Learning Rate
-    pub mu: f64, // Momentum
-    pub v_W: Vec<DMatrix<f64>>, // Velocity for weights
-    pub v_b: Vec<DMatrix<f64>> // Velocity for biases
+pub struct SGD<T: Real = f64> {
+    pub model: NeuralNetwork<T>,
+    pub lr: T, // Learning Rate
+    pub mu: T, // Momentum
+    pub nesterov: bool, // Whether momentum uses the Nesterov look-ahead
+    pub weight_decay: T, // L2 regularization strength λ for the weights
+    pub max_grad_norm: Option<T>, // Global gradient norm to clip to, if any
+    pub velocity: Vec<DMatrix<T>>, // Velocity of each parameter (see NeuralNetwork::parameters)
+    pub scheduler: Option<Box<dyn Scheduler>>, // Learning rate schedule, if any
+    num_steps: usize // Number of steps taken so far
 }
 
-impl SGD {
+impl<T: Real> SGD<T> {
     // Constructor for the SGD struct. Creates a new SGD optimizer with
     // the specified learning rate and momentum.
-    pub fn new(model: NeuralNetwork, lr: f64, mu: f64) -> Self {
-        let mut v_W = Vec::new();
-        let mut v_b = Vec::new();
-        for i in 0..model.layers.len() {
-            v_W.push(DMatrix::zeros(model.layers[i].W.nrows(), model.layers[i].W.ncols()));
-            v_b.push(DMatrix::zeros(model.layers[i].b.nrows(), model.layers[i].b.ncols()));
-        }
+    pub fn new(model: NeuralNetwork<T>, lr: T, mu: T) -> Self {
+        let velocity = zeros_like_parameters(&model);
         SGD {
             model: model,
             lr: lr,
             mu: mu,
-            v_W: v_W,
-            v_b: v_b
+            nesterov: false,
+            weight_decay: T::zero(),
+            max_grad_norm: None,
+            velocity: velocity,
+            scheduler: None,
+            num_steps: 0
         }
     }
 
+    // Adds weight_decay * W to the weight gradients of every step
+    pub fn with_weight_decay(mut self, weight_decay: T) -> Self {
+        assert!(weight_decay >= T::zero(), "weight_decay must be non-negative, got {}", weight_decay);
+        self.weight_decay = weight_decay;
+        return self;
+    }
+
+    // Uses Nesterov momentum instead of classical momentum
+    pub fn with_nesterov(mut self, nesterov: bool) -> Self {
+        self.nesterov = nesterov;
+        return self;
+    }
+
+    // Clips the global gradient norm to max_grad_norm before every step
+    pub fn with_max_grad_norm(mut self, max_grad_norm: T) -> Self {
+        assert!(max_grad_norm > T::zero(), "max_grad_norm must be positive, got {}", max_grad_norm);
+        self.max_grad_norm = Some(max_grad_norm);
+        return self;
+    }
+
+    // Sets lr from scheduler before every step
+    pub fn with_scheduler(mut self, scheduler: Box<dyn Scheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        return self;
+    }
+
     // The update method is used to update the parameters of the neural network
     // using the Stochastic Gradient Descent (SGD) algorithm. The update is done
     // by computing the gradient of the loss with respect to the parameters for
     // each sample in the training data, and then updating the parameters using
-    // the average gradient over the entire training data.
-    pub fn update(&mut self, x: &DMatrix<f64>, y: &DMatrix<f64>) {
-
-        // Forward pass (compute loss)
-        let Z = self.model.forward(&x);
-        let _ = self.model.loss.forward(&Z, &y);
-
-        // Backward pass (compute gradients)
-        let dLdA = self.model.backward();
-
-        for i in 0..self.model.layers.len() {
-
-            if self.mu == 0.0 {
-                // Update the weights and biases using the negative gradient
-                // of the loss with respect to the parameters
-                let dLdW = self.model.layers[i].dLdW.clone();
-                let dLdb = self.model.layers[i].dLdb.clone();
-                self.model.layers[i].W -= self.lr * &dLdW;
-                self.model.layers[i].b -= self.lr * &dLdb;
-            } else {
-                // Update the weights and biases using momentum
-                let dLdW = self.model.layers[i].dLdW.clone();
-                let dLdb = self.model.layers[i].dLdb.clone();
-                self.v_W[i] = self.mu * &self.v_W[i] + &dLdW;
-                self.v_b[i] = self.mu * &self.v_b[i] + &dLdb;
-                self.model.layers[i].W -= self.lr * &self.v_W[i];
-                self.model.layers[i].b -= self.lr * &self.v_b[i];
+    // the average gradient over the entire training data. Returns the loss of
+    // the batch before the update.
+    pub fn update(&mut self, x: &DMatrix<T>, y: &DMatrix<T>) -> T {
+        let Z = self.model.forward(x);
+        let loss = self.model.loss.forward(&Z, y);
+        self.model.backward();
+        self.step();
+        return loss;
+    }
+
+    // Applies one SGD step using the gradients stored in the layers by the
+    // last backward pass.
+    pub fn step(&mut self) {
+        if let Some(scheduler) = self.scheduler.as_mut() {
+            self.lr = from_f64(scheduler.lr(self.num_steps));
+        }
+        self.num_steps += 1;
+        if let Some(max_norm) = self.max_grad_norm {
+            let _ = self.model.clip_grad_norm(max_norm);
+        }
+        let gradients: Vec<DMatrix<T>> = self.model.gradients().into_iter().cloned().collect();
+        let requires_grad = self.model.requires_grad();
+        let num_layer_parameters = 2 * self.model.layers.len();
+        for (k, (param, mut grad)) in self.model.parameters_mut().into_iter().zip(gradients).enumerate() {
+            // Frozen parameters are skipped, including their momentum
+            if !requires_grad[k] {
+                continue;
+            }
+            // The L2 penalty only applies to the weights, which alternate
+            // with the biases in the layer parameters
+            let is_weight = k < num_layer_parameters && k % 2 == 0;
+            if is_weight && self.weight_decay != T::zero() {
+                grad += &*param * self.weight_decay;
+            }
+            update_parameter(param, &mut self.velocity[k], &grad, self.lr, self.mu, self.nesterov);
+        }
+    }
+}
+
+// Updates one parameter matrix (and its velocity) with its gradient
+fn update_parameter<T: Real>(param: &mut DMatrix<T>, velocity: &mut DMatrix<T>, grad: &DMatrix<T>, lr: T, mu: T, nesterov: bool) {
+    if mu == T::zero() {
+        // Plain gradient descent: param -= lr * grad
+        elementwise(param, grad, |p, g| *p -= g * lr);
+        return;
+    }
+    // v = μ * v + grad
+    elementwise(velocity, grad, |v, g| *v = *v * mu + g);
+    if nesterov {
+        // param -= lr * (μ * v + grad)
+        let mut look_ahead = grad.clone();
+        elementwise(&mut look_ahead, velocity, |a, v| *a = v * mu + *a);
+        elementwise(param, &look_ahead, |p, a| *p -= a * lr);
+    } else {
+        // param -= lr * v
+        elementwise(param, velocity, |p, v| *p -= v * lr);
+    }
+}
+
+// Applies f(target_k, source_k) to every pair of entries
+#[cfg(not(feature = "rayon"))]
+fn elementwise<T: Real, F: Fn(&mut T, T) + Sync>(target: &mut DMatrix<T>, source: &DMatrix<T>, f: F) {
+    target.zip_apply(source, |t, s| f(t, s));
+}
+
+// Matrices with at least this many entries are updated in parallel
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LEN: usize = 1 << 14;
+// Number of entries handed to a rayon task at a time
+#[cfg(feature = "rayon")]
+const CHUNK_LEN: usize = 1 << 12;
+
+// Applies f(target_k, source_k) to every pair of entries, in parallel chunks
+// for large matrices. Every entry goes through the same operations as in the
+// sequential version, so the results are bit-identical.
+#[cfg(feature = "rayon")]
+fn elementwise<T: Real, F: Fn(&mut T, T) + Sync>(target: &mut DMatrix<T>, source: &DMatrix<T>, f: F) {
+    assert_eq!(target.shape(), source.shape(), "Matrices must have the same shape");
+    if target.len() < PARALLEL_MIN_LEN {
+        target.zip_apply(source, |t, s| f(t, s));
+        return;
+    }
+    target.as_mut_slice().par_chunks_mut(CHUNK_LEN)
+        .zip(source.as_slice().par_chunks(CHUNK_LEN))
+        .for_each(|(t, s)| t.iter_mut().zip(s.iter()).for_each(|(t, s)| f(t, *s)));
+}
+
+impl Optimizer for SGD {
+    fn model(&self) -> &NeuralNetwork {
+        return &self.model;
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return &mut self.model;
+    }
+
+    fn lr(&self) -> f64 {
+        return self.lr;
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn step(&mut self) {
+        SGD::step(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::{ReLU, SwishBeta};
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::scheduler::StepDecay;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_sgd_updates_activation_parameters() {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 2))], vec![Box::new(SwishBeta::new(1.0))], Box::new(MSE::new()));
+        model.layers[0].W = DMatrix::from_row_slice(2, 2, &[1.0, 0.5,
+                                                            -0.5, 1.0]);
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                -1.0, 0.5]);
+        let y = DMatrix::from_row_slice(2, 2, &[3.0, 0.0,
+                                                0.0, 1.0]);
+        let mut optim = SGD::new(model, 0.1, 0.0);
+        let _ = optim.update(&x, &y);
+        let dLdbeta = optim.model.activations[0].gradients()[0][(0, 0)];
+        assert!(dLdbeta != 0.0);
+        assert_abs_diff_eq!(optim.model.activations[0].parameters_mut()[0][(0, 0)], 1.0 - 0.1 * dLdbeta, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_clip_grad_norm_uses_one_global_factor() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 2)), Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0).with_max_grad_norm(1.0);
+        optim.model.layers[0].dLdW = DMatrix::from_row_slice(2, 2, &[30.0, -40.0,
+                                                                     0.0, 10.0]);
+        optim.model.layers[0].dLdb = DMatrix::from_row_slice(2, 1, &[20.0, 0.0]);
+        optim.model.layers[1].dLdW = DMatrix::from_row_slice(1, 2, &[0.5, -0.5]);
+        optim.model.layers[1].dLdb = DMatrix::from_row_slice(1, 1, &[0.0]);
+        let before = optim.model.flat_gradients();
+        let W = optim.model.layers[1].W.clone();
+
+        optim.step();
+        let after = optim.model.flat_gradients();
+        let norm = after.iter().map(|g| g * g).sum::<f64>().sqrt();
+        assert_abs_diff_eq!(norm, 1.0, epsilon = 1e-12);
+        // The small gradients of the second layer shrink by the same factor
+        let scale = after[0] / before[0];
+        for k in 0..after.len() {
+            assert_abs_diff_eq!(after[k], scale * before[k], epsilon = 1e-12);
+        }
+        assert_abs_diff_eq!(optim.model.layers[1].W, W - 0.1 * &optim.model.layers[1].dLdW, epsilon = 1e-12);
+
+        // Gradients below the threshold are left unchanged
+        assert_abs_diff_eq!(optim.model.clip_grad_norm(2.0), 1.0, epsilon = 1e-12);
+        assert_eq!(optim.model.flat_gradients(), after);
+    }
+
+    #[test]
+    fn test_weight_decay_shrinks_weights_without_gradient() {
+        for mu in [0.0, 0.9] {
+            let model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![], Box::new(MSE::new()));
+            let mut optim = SGD::new(model, 0.1, mu).with_weight_decay(0.5);
+            let b = optim.model.layers[0].b.clone();
+            let mut norm = optim.model.layers[0].W.norm();
+            for _ in 0..5 {
+                optim.model.layers[0].dLdW = DMatrix::zeros(2, 3);
+                optim.model.layers[0].dLdb = DMatrix::zeros(2, 1);
+                optim.step();
+                let new_norm = optim.model.layers[0].W.norm();
+                assert!(new_norm < norm, "mu = {}", mu);
+                norm = new_norm;
+            }
+            assert_eq!(optim.model.layers[0].b, b); // Biases are not decayed
+        }
+
+        // Without momentum every step scales W by exactly 1 - lr * λ
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 2))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0).with_weight_decay(0.5);
+        let W = optim.model.layers[0].W.clone();
+        optim.model.layers[0].dLdW = DMatrix::zeros(2, 3);
+        optim.model.layers[0].dLdb = DMatrix::zeros(2, 1);
+        optim.step();
+        assert_abs_diff_eq!(optim.model.layers[0].W, W * 0.95, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_sgd_trains_f32_model() {
+        let model: NeuralNetwork<f32> = NeuralNetwork::new(vec![Box::new(Linear::<f32>::new(2, 4)), Box::new(Linear::<f32>::new(4, 1))],
+                                                           vec![Box::new(ReLU::<f32>::new())],
+                                                           Box::new(MSE::<f32>::new()));
+        let x = DMatrix::from_row_slice(4, 2, &[0.0f32, 0.0,
+                                                0.0, 1.0,
+                                                1.0, 0.0,
+                                                1.0, 1.0]);
+        let y = DMatrix::from_row_slice(4, 1, &[0.0f32, 1.0, 1.0, 2.0]);
+        let mut optim = SGD::new(model, 0.05f32, 0.9f32);
+        let first = optim.update(&x, &y);
+        let mut last = first;
+        for _ in 0..200 {
+            last = optim.update(&x, &y);
+        }
+        assert!(last < first, "loss went from {} to {}", first, last);
+    }
+
+    #[test]
+    fn test_step_decay_schedule() {
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0).with_scheduler(Box::new(StepDecay::new(0.1, 0.5, 3)));
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 2.0,
+                                                -1.0, 0.5]);
+        let y = DMatrix::from_row_slice(2, 1, &[1.0, 0.0]);
+        for n in 0..10 {
+            let _ = optim.update(&x, &y);
+            assert_abs_diff_eq!(optim.lr, 0.1 * 0.5f64.powi(n / 3), epsilon = 1e-15);
+        }
+
+        // Without a scheduler the learning rate is left alone
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.1, 0.0);
+        for _ in 0..5 {
+            let _ = optim.update(&x, &y);
+        }
+        assert_eq!(optim.lr, 0.1);
+    }
+
+    #[test]
+    fn test_nesterov_looks_ahead() {
+        let mut trajectories = Vec::new();
+        for nesterov in [false, true] {
+            let model = NeuralNetwork::new(vec![Box::new(Linear::new(1, 1))], vec![], Box::new(MSE::new()));
+            let mut optim = SGD::new(model, 0.1, 0.5).with_nesterov(nesterov);
+            optim.model.layers[0].W = DMatrix::zeros(1, 1);
+            let mut trajectory = Vec::new();
+            for _ in 0..3 {
+                optim.model.layers[0].dLdW = DMatrix::from_element(1, 1, 1.0);
+                optim.model.layers[0].dLdb = DMatrix::zeros(1, 1);
+                optim.step();
+                trajectory.push(optim.model.layers[0].W[(0, 0)]);
+            }
+            trajectories.push(trajectory);
+        }
+        // Both share the velocities 1, 1.5, 1.75; classical momentum steps by
+        // lr * v, Nesterov by lr * (μ * v + g)
+        let expected = [[-0.1, -0.25, -0.425], [-0.15, -0.325, -0.5125]];
+        for (trajectory, expected) in trajectories.iter().zip(expected.iter()) {
+            for (w, e) in trajectory.iter().zip(expected.iter()) {
+                assert_abs_diff_eq!(*w, *e, epsilon = 1e-12);
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_update_of_large_layer_matches_matrix_expressions() {
+        // Large enough for the parallel path of the rayon feature
+        let (rows, cols) = (300, 200);
+        let gradient = |k: usize| DMatrix::from_fn(rows, cols, |i, j| ((i * 31 + j * 17 + k * 7) % 101) as f64 / 50.0 - 1.0);
+        for (mu, nesterov) in [(0.0, false), (0.9, false), (0.9, true)] {
+            let model = NeuralNetwork::new(vec![Box::new(Linear::new(cols, rows))], vec![], Box::new(MSE::new()));
+            let mut optim = SGD::new(model, 0.01, mu).with_nesterov(nesterov);
+            let mut W = optim.model.layers[0].W.clone();
+            let mut v = DMatrix::zeros(rows, cols);
+            for k in 0..3 {
+                let dLdW = gradient(k);
+                optim.model.layers[0].dLdW = dLdW.clone();
+                optim.model.layers[0].dLdb = DMatrix::zeros(rows, 1);
+                optim.step();
+
+                // Sequential reference
+                if mu == 0.0 {
+                    W -= &dLdW * 0.01;
+                } else {
+                    v = &v * mu + &dLdW;
+                    if nesterov {
+                        W -= (&v * mu + &dLdW) * 0.01;
+                    } else {
+                        W -= &v * 0.01;
+                    }
+                }
+                assert_eq!(optim.model.layers[0].W, W);
+            }
+        }
+    }
+}
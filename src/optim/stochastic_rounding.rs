@@ -0,0 +1,99 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::Optimizer;
+use crate::precision::stochastic_round_to_bf16;
+
+
+/**
+    * Stochastic Rounding
+    *
+    * When parameters are stored in a low precision format such as bfloat16,
+    * an update lr * g smaller than half the spacing of the format around a
+    * weight is lost entirely under round to nearest, so small gradients never
+    * change the weights. Stochastic rounding rounds up or down at random,
+    * with probabilities proportional to the distance to each neighbour, which
+    * makes the stored weight equal to the exact update in expectation. Over
+    * many steps the small updates accumulate as they would in full precision.
+    *
+    * StochasticRounding wraps any optimizer: it lets the base optimizer take
+    * its step in f64 and then rounds the updated parameters to the bfloat16
+    * grid, drawing from a seeded RNG. Frozen parameters are left untouched.
+    *
+**/
+
+
+pub struct StochasticRounding<O: Optimizer> {
+    pub base: O, // Optimizer computing the full precision update
+    rng: StdRng
+}
+
+impl<O: Optimizer> StochasticRounding<O> {
+    pub fn new(base: O, seed: u64) -> Self {
+        StochasticRounding {
+            base: base,
+            rng: StdRng::seed_from_u64(seed)
+        }
+    }
+}
+
+impl<O: Optimizer> Optimizer for StochasticRounding<O> {
+    fn model(&self) -> &NeuralNetwork {
+        return self.base.model();
+    }
+
+    fn model_mut(&mut self) -> &mut NeuralNetwork {
+        return self.base.model_mut();
+    }
+
+    fn lr(&self) -> f64 {
+        return self.base.lr();
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.base.set_lr(lr);
+    }
+
+    fn step(&mut self) {
+        self.base.step();
+        let rng = &mut self.rng;
+        let model = self.base.model_mut();
+        let requires_grad = model.requires_grad();
+        for (param, requires_grad) in model.parameters_mut().into_iter().zip(requires_grad) {
+            if requires_grad {
+                *param = stochastic_round_to_bf16(param, rng);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{DMatrix};
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::sgd::SGD;
+
+    #[test]
+    fn test_stochastic_rounding_keeps_small_updates_on_average() {
+        // Every step subtracts 2^-10, an eighth of the bfloat16 spacing at 1.
+        // Round to nearest would keep the weights at 1 forever.
+        let (steps, delta) = (200, 2f64.powi(-10));
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(50, 20))], vec![], Box::new(MSE::new()));
+        let mut optim = StochasticRounding::new(SGD::new(model, 1.0, 0.0), 7);
+        optim.model_mut().layers[0].W = DMatrix::from_element(20, 50, 1.0);
+        for _ in 0..steps {
+            let layer = &mut optim.model_mut().layers[0];
+            layer.dLdW = DMatrix::from_element(20, 50, delta);
+            layer.dLdb = DMatrix::from_element(20, 1, 0.0);
+            optim.step();
+        }
+
+        let W = &optim.model().layers[0].W;
+        // All weights lie on the bfloat16 grid
+        assert!(W.iter().all(|w| w.to_bits() & ((1u64 << 45) - 1) == 0));
+        let expected = 1.0 - steps as f64 * delta;
+        assert!((W.mean() - expected).abs() < 2e-3, "mean {} vs {}", W.mean(), expected);
+    }
+}
@@ -0,0 +1,181 @@
+use nalgebra::{DMatrix};
+use rand::rngs::StdRng;
+use rand::Rng;
+use crate::nn::activation::ActivationFunction;
+
+/**
+    * Reduced Precision Simulation
+    *
+    * Training in low precision formats saves memory and compute, but every
+    * value is rounded to a coarser grid. These utilities simulate the
+    * rounding while keeping all computations in f64, so its effect on
+    * training can be studied with the rest of the library unchanged.
+    *
+    * bfloat16 keeps the 8-bit exponent of f32 (the same range) but only 7
+    * explicit mantissa bits, i.e. about 2-3 significant decimal digits.
+    *
+    * Currently, the following utilities are implemented:
+    * 1. truncate_to_bf16 - Rounds every entry to the nearest bfloat16 value.
+    * 2. Bf16Cast - A pass-through layer that rounds both the activations it
+    *               forwards and the gradients it backpropagates to bfloat16.
+    * 3. stochastic_round_to_bf16 - Rounds every entry to one of its two
+    *               neighbouring bfloat16 values at random, with probabilities
+    *               that make the result unbiased. Used by the
+    *               StochasticRounding optimizer wrapper.
+    *
+**/
+
+
+// Number of f64 mantissa bits that bfloat16 does not have (52 - 7)
+const DROPPED_BITS: u32 = 45;
+
+// Rounds x to the nearest bfloat16 value, ties to even. Values beyond the
+// bfloat16 range overflow to infinity and values below the smallest normal
+// (2^-126) are rounded to the subnormal grid (multiples of 2^-133).
+fn round_to_bf16(x: f64) -> f64 {
+    if !x.is_finite() {
+        return x;
+    }
+    let smallest_normal = 2f64.powi(-126);
+    if x.abs() < smallest_normal {
+        let spacing = 2f64.powi(-133);
+        return (x / spacing).round_ties_even() * spacing;
+    }
+    let bits = x.to_bits();
+    let lsb = (bits >> DROPPED_BITS) & 1; // Lowest kept mantissa bit, for ties to even
+    let rounded = (bits + (1u64 << (DROPPED_BITS - 1)) - 1 + lsb) & !((1u64 << DROPPED_BITS) - 1);
+    let y = f64::from_bits(rounded);
+    // Largest finite bfloat16 is (2 - 2^-7) * 2^127
+    if y.abs() > (2.0 - 2f64.powi(-7)) * 2f64.powi(127) {
+        return f64::INFINITY.copysign(x);
+    }
+    return y;
+}
+
+// Rounds x down (toward zero) or up (away from zero) to a neighbouring
+// bfloat16 value, going up with probability equal to the distance from the
+// lower neighbour in units of the spacing. The expected result is x itself,
+// so updates far smaller than the spacing still move the value on average,
+// while round to nearest would discard them. u is uniform in [0, 1).
+fn stochastic_round_bf16(x: f64, u: f64) -> f64 {
+    if !x.is_finite() {
+        return x;
+    }
+    let smallest_normal = 2f64.powi(-126);
+    if x.abs() < smallest_normal {
+        let spacing = 2f64.powi(-133);
+        let scaled = x.abs() / spacing;
+        let lower = scaled.floor();
+        let rounded = if u < scaled - lower { lower + 1.0 } else { lower };
+        return (rounded * spacing).copysign(x);
+    }
+    let bits = x.to_bits();
+    let dropped = bits & ((1u64 << DROPPED_BITS) - 1);
+    let lower = bits - dropped;
+    let fraction = dropped as f64 / (1u64 << DROPPED_BITS) as f64;
+    // Adding one unit of the last kept bit carries into the exponent as needed
+    let rounded = if u < fraction { lower + (1u64 << DROPPED_BITS) } else { lower };
+    let y = f64::from_bits(rounded);
+    if y.abs() > (2.0 - 2f64.powi(-7)) * 2f64.powi(127) {
+        return f64::INFINITY.copysign(x);
+    }
+    return y;
+}
+
+// Stochastically rounds every entry of m to bfloat16, drawing from rng
+pub fn stochastic_round_to_bf16(m: &DMatrix<f64>, rng: &mut StdRng) -> DMatrix<f64> {
+    return m.map(|x| stochastic_round_bf16(x, rng.gen::<f64>()));
+}
+
+// Rounds every entry of m to the nearest representable bfloat16 value
+pub fn truncate_to_bf16(m: &DMatrix<f64>) -> DMatrix<f64> {
+    return m.map(round_to_bf16);
+}
+
+// Simulates storing activations (forward) and gradients (backward) in
+// bfloat16 at one point of a network, e.g. placed as the activation after
+// a layer. Apart from the rounding it is the identity.
+#[derive(Clone)]
+pub struct Bf16Cast {}
+
+impl Bf16Cast {
+    pub fn new() -> Self {
+        Bf16Cast {}
+    }
+
+    pub fn forward(&mut self, Z: &DMatrix<f64>) -> DMatrix<f64> {
+        return truncate_to_bf16(Z);
+    }
+
+    pub fn backward(&self, dLdA: &DMatrix<f64>) -> DMatrix<f64> {
+        return truncate_to_bf16(dLdA);
+    }
+}
+
+impl ActivationFunction for Bf16Cast {
+    fn forward(&mut self, Z: &DMatrix<f64>) -> DMatrix<f64> {
+        return Bf16Cast::forward(self, Z);
+    }
+
+    fn backward(&self, dLdA: &DMatrix<f64>) -> DMatrix<f64> {
+        return Bf16Cast::backward(self, dLdA);
+    }
+
+    fn name(&self) -> &str {
+        return "bf16";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_to_bf16_known_values() {
+        let values = [1.0, 1.0 + 2f64.powi(-8), 1.0 + 3.0 * 2f64.powi(-8), 1.0 + 2f64.powi(-8) + 2f64.powi(-20),
+                      std::f64::consts::PI, -0.1, 65536.0];
+        let expected = [1.0, 1.0, 1.0 + 2f64.powi(-6), 1.0 + 2f64.powi(-7),
+                        3.140625, -0.10009765625, 65536.0];
+        let m = DMatrix::from_row_slice(1, values.len(), &values);
+        let truncated = truncate_to_bf16(&m);
+        for (t, e) in truncated.iter().zip(expected.iter()) {
+            assert_eq!(t, e);
+            // All mantissa bits below the 7 kept by bfloat16 are zero
+            assert_eq!(t.to_bits() & ((1u64 << DROPPED_BITS) - 1), 0);
+        }
+    }
+
+    #[test]
+    fn test_truncate_to_bf16_range() {
+        let m = DMatrix::from_row_slice(1, 4, &[1e39, -1e39, 2f64.powi(-134) * 3.0, f64::NEG_INFINITY]);
+        let truncated = truncate_to_bf16(&m);
+        assert_eq!(truncated[0], f64::INFINITY);
+        assert_eq!(truncated[1], f64::NEG_INFINITY);
+        assert_eq!(truncated[2], 2f64.powi(-132)); // 1.5 * 2^-133 rounds to the even multiple 2 * 2^-133
+        assert_eq!(truncated[3], f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_stochastic_round_to_bf16_is_unbiased() {
+        use rand::SeedableRng;
+        let mut rng = StdRng::seed_from_u64(0);
+        let spacing = 2f64.powi(-7); // bfloat16 spacing in [1, 2)
+        let x = 1.0 + 0.3 * spacing;
+        let m = DMatrix::from_element(100, 100, x);
+        let rounded = stochastic_round_to_bf16(&m, &mut rng);
+        // Every entry is one of the two neighbours
+        assert!(rounded.iter().all(|r| *r == 1.0 || *r == 1.0 + spacing));
+        let mean = rounded.mean();
+        // Standard error of the mean is spacing * sqrt(0.3 * 0.7 / 10^4) ≈ 3.6e-5
+        assert!((mean - x).abs() < 2e-4, "mean {} vs {}", mean, x);
+        assert_eq!(stochastic_round_to_bf16(&DMatrix::from_element(1, 1, -3.140625), &mut rng)[0], -3.140625);
+    }
+
+    #[test]
+    fn test_bf16_cast_rounds_both_directions() {
+        let mut cast = Bf16Cast::new();
+        let Z = DMatrix::from_row_slice(1, 2, &[std::f64::consts::PI, 1.0]);
+        assert_eq!(cast.forward(&Z), DMatrix::from_row_slice(1, 2, &[3.140625, 1.0]));
+        assert_eq!(cast.backward(&Z), DMatrix::from_row_slice(1, 2, &[3.140625, 1.0]));
+    }
+}
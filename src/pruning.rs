@@ -0,0 +1,131 @@
+use nalgebra::{DMatrix};
+use crate::data::DataLoader;
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::{History, Optimizer};
+
+/**
+    * Lottery Ticket Pruning
+    *
+    * The lottery ticket hypothesis states that a randomly initialized dense
+    * network contains a sparse subnetwork (a "winning ticket") that, trained
+    * from the same initial weights, reaches the accuracy of the dense one.
+    * It is found by iterative magnitude pruning with rewinding:
+    *
+    *   1. Store the initial weights W_0 and biases b_0
+    *   2. Train the network
+    *   3. Prune a fraction p of the surviving weights of every layer, those
+    *      of smallest magnitude (see Linear::prune)
+    *   4. Rewind: reset the surviving weights to W_0 and the biases to b_0
+    *   5. Repeat from 2
+    *
+    * After k rounds a fraction 1 - (1 - p)^k of the weights of every layer is
+    * pruned. The prune masks keep the pruned weights at zero while training.
+    *
+**/
+
+
+// Initial parameters of a model, for rewinding after each pruning round
+pub struct LotteryTicket {
+    pub fraction: f64, // Fraction of the surviving weights pruned per round
+    initial_W: Vec<DMatrix<f64>>, // Weights of every layer at construction
+    initial_b: Vec<DMatrix<f64>> // Biases of every layer at construction
+}
+
+impl LotteryTicket {
+    // Stores the current (initial) parameters of model
+    pub fn new(model: &NeuralNetwork, fraction: f64) -> Self {
+        assert!((0.0..1.0).contains(&fraction), "The pruning fraction must be in [0, 1), got {}", fraction);
+        LotteryTicket {
+            fraction: fraction,
+            initial_W: model.layers.iter().map(|layer| layer.W.clone()).collect(),
+            initial_b: model.layers.iter().map(|layer| layer.b.clone()).collect()
+        }
+    }
+
+    // Prunes fraction of the surviving weights of every layer by magnitude,
+    // then resets the survivors and the biases to their initial values
+    pub fn prune_and_rewind(&self, model: &mut NeuralNetwork) {
+        assert_eq!(model.layers.len(), self.initial_W.len(), "The model does not match the stored initial weights");
+        for (i, layer) in model.layers.iter_mut().enumerate() {
+            assert_eq!(layer.W.shape(), self.initial_W[i].shape(), "Layer {} does not match the stored initial weights", i);
+            let sparsity = match &layer.prune_mask {
+                Some(mask) => mask.iter().filter(|m| **m == 0.0).count() as f64 / mask.len() as f64,
+                None => 0.0
+            };
+            layer.prune(sparsity + self.fraction * (1.0 - sparsity));
+
+            let mask = layer.prune_mask.as_ref().unwrap();
+            layer.W = self.initial_W[i].component_mul(mask);
+            layer.b = self.initial_b[i].clone();
+        }
+    }
+}
+
+// Runs rounds of the lottery ticket experiment on the model of optimizer:
+// every round trains for epochs over loader, then prunes fraction of the
+// surviving weights and rewinds. Returns the History of every round. The
+// model ends up rewound, i.e. holds the untrained winning ticket, which can
+// be trained with fit as usual. The state of the optimizer (e.g. momentum)
+// is not reset between rounds.
+pub fn lottery_ticket<O: Optimizer>(optimizer: &mut O, loader: &mut DataLoader, epochs: usize, rounds: usize,
+                                    fraction: f64) -> Vec<History> {
+    let ticket = LotteryTicket::new(optimizer.model(), fraction);
+    let mut histories = Vec::new();
+    for _ in 0..rounds {
+        histories.push(optimizer.fit(loader, None, epochs, 1));
+        ticket.prune_and_rewind(optimizer.model_mut());
+    }
+    return histories;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::make_regression;
+    use crate::nn::activation::ReLU;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::sgd::SGD;
+
+    #[test]
+    fn test_rewinding_restores_surviving_weights() {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(4, 5)), Box::new(Linear::new(5, 1))],
+                                           vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        let ticket = LotteryTicket::new(&model, 0.5);
+        let initial: Vec<(DMatrix<f64>, DMatrix<f64>)> = model.layers.iter().map(|l| (l.W.clone(), l.b.clone())).collect();
+
+        for round in 1..=2 {
+            // Stand-in for training
+            for layer in model.layers.iter_mut() {
+                layer.W = layer.W.map(|w| 2.0 * w + 0.1);
+                layer.b = layer.b.map(|b| b - 1.0);
+            }
+            ticket.prune_and_rewind(&mut model);
+
+            for (layer, (W_0, b_0)) in model.layers.iter().zip(initial.iter()) {
+                let mask = layer.prune_mask.as_ref().unwrap();
+                assert_eq!(layer.W, W_0.component_mul(mask));
+                assert_eq!(&layer.b, b_0);
+                // Half of the survivors are pruned every round
+                let pruned = mask.iter().filter(|m| **m == 0.0).count();
+                let expected = (layer.W.len() as f64 * (1.0 - 0.5f64.powi(round))).round() as usize;
+                assert_eq!(pruned, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lottery_ticket_rounds() {
+        let (X, Y) = make_regression(64, 3, 0.1, 0);
+        let mut loader = DataLoader::new(X, Y, 16, true, 0);
+        let model = NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 1))],
+                                       vec![Box::new(ReLU::new())], Box::new(MSE::new()));
+        let mut optim = SGD::new(model, 0.01, 0.0);
+        let histories = lottery_ticket(&mut optim, &mut loader, 2, 3, 0.2);
+        assert_eq!(histories.len(), 3);
+        assert!(histories.iter().all(|h| h.loss.len() == 2));
+        let mask = optim.model.layers[0].prune_mask.as_ref().unwrap();
+        // 1 - 0.8^3 of 12 weights
+        assert_eq!(mask.iter().filter(|m| **m == 0.0).count(), 6);
+    }
+}
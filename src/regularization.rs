@@ -0,0 +1,169 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+use crate::optim::optimizer::Optimizer;
+
+/**
+    * Regularization
+    *
+    * Regularizers add a penalty R(θ) on the parameters θ to the training loss,
+    * L_total = L + R(θ), to steer training towards preferred solutions. Each
+    * regularizer can compute its penalty and add its gradient ∂R/∂θ to the
+    * gradients stored in the layers after the backward pass, before the
+    * optimizer steps.
+    *
+    * Currently, the following regularizers are implemented:
+    * 1. EwcPenalty - Elastic weight consolidation for continual learning,
+    *                 R(θ) = λ/2 * Σ_k F_k * (θ_k - θ_ref_k)^2, where θ_ref are
+    *                 the parameters after training on a previous task and F is
+    *                 their Fisher information diagonal. Parameters that were
+    *                 important for the old task (large F) are kept close to
+    *                 their old values, the others are free to change.
+    *
+**/
+
+
+pub struct EwcPenalty {
+    pub lambda: f64, // Strength of the penalty
+    pub reference: Vec<f64>, // Snapshot of the parameters θ_ref (flat layout)
+    pub fisher: Vec<f64> // Importance F_k of each parameter (flat layout)
+}
+
+impl EwcPenalty {
+    // Snapshots the current parameters of model as the reference, with the
+    // given Fisher diagonal (see NeuralNetwork::fisher_diagonal) as importances.
+    pub fn new(model: &NeuralNetwork, fisher: Vec<f64>, lambda: f64) -> Self {
+        assert_eq!(fisher.len(), model.num_parameters(), "Expected one Fisher entry per parameter");
+        EwcPenalty {
+            lambda: lambda,
+            reference: model.flat_parameters(),
+            fisher: fisher
+        }
+    }
+
+    // R(θ) = λ/2 * Σ_k F_k * (θ_k - θ_ref_k)^2
+    pub fn penalty(&self, model: &NeuralNetwork) -> f64 {
+        let params = model.flat_parameters();
+        let total: f64 = params.iter().zip(self.reference.iter()).zip(self.fisher.iter())
+            .map(|((p, r), f)| f * (p - r) * (p - r))
+            .sum();
+        return 0.5 * self.lambda * total;
+    }
+
+    // Adds ∂R/∂θ_k = λ * F_k * (θ_k - θ_ref_k) to the gradients of the last
+    // backward pass.
+    pub fn add_gradient(&self, model: &mut NeuralNetwork) {
+        let params = model.flat_parameters();
+        // Every gradient is shaped like its parameter, so its column-major
+        // entries line up with flat_parameters
+        let mut k = 0;
+        for grad in model.gradients_mut() {
+            for g in grad.iter_mut() {
+                *g += self.lambda * self.fisher[k] * (params[k] - self.reference[k]);
+                k += 1;
+            }
+        }
+    }
+
+    // One training step on a batch of the new task with the penalty applied:
+    // forward, backward, penalty gradient and optimizer step. Returns the
+    // penalized loss L + R(θ) before the step.
+    pub fn update<O: Optimizer>(&self, optim: &mut O, x: &DMatrix<f64>, y: &DMatrix<f64>) -> f64 {
+        let model = optim.model_mut();
+        let Z = model.forward(x);
+        let loss = model.loss.forward(&Z, y);
+        model.backward();
+        let penalty = self.penalty(model);
+        self.add_gradient(model);
+        optim.step();
+        return loss + penalty;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use crate::optim::sgd::SGD;
+    use approx::assert_abs_diff_eq;
+
+    fn linear_model() -> NeuralNetwork {
+        let mut model = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        model.set_flat_parameters(&[1.0, -1.0, 0.5]);
+        return model;
+    }
+
+    #[test]
+    fn test_ewc_gradient_pulls_towards_reference() {
+        let mut model = linear_model();
+        let ewc = EwcPenalty::new(&model, vec![4.0, 1.0, 0.0], 0.5);
+        model.set_flat_parameters(&[2.0, -3.0, 1.5]);
+        for layer in model.layers.iter_mut() {
+            layer.dLdW = DMatrix::zeros(1, 2);
+            layer.dLdb = DMatrix::zeros(1, 1);
+        }
+
+        // R = 0.25 * (4 * 1^2 + 1 * 2^2 + 0 * 1^2) = 2
+        assert_abs_diff_eq!(ewc.penalty(&model), 2.0, epsilon = 1e-12);
+
+        // ∂R/∂θ = λ F (θ - θ_ref) = [2, -1, 0]: a gradient step moves each
+        // parameter back towards the reference, more so the larger F is, and
+        // parameters with F = 0 are not pulled at all
+        ewc.add_gradient(&mut model);
+        assert_eq!(model.flat_gradients(), vec![2.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_ewc_gradient_matches_finite_differences() {
+        let mut model = linear_model();
+        let ewc = EwcPenalty::new(&model, vec![0.3, 2.0, 1.1], 1.7);
+        let x = DMatrix::from_row_slice(3, 2, &[1.0, 2.0,
+                                                -1.0, 0.5,
+                                                0.0, 1.0]);
+        let y = DMatrix::from_row_slice(3, 1, &[1.0, 0.0, -2.0]);
+        model.set_flat_parameters(&[0.2, 0.7, -0.4]);
+
+        let _ = model.output_grad(&x, &y);
+        model.backward();
+        ewc.add_gradient(&mut model);
+        let analytic = model.flat_gradients();
+
+        let params = model.flat_parameters();
+        let eps = 1e-6;
+        for k in 0..params.len() {
+            let mut total_loss = |delta: f64| {
+                let mut p = params.clone();
+                p[k] += delta;
+                model.set_flat_parameters(&p);
+                model.evaluate(&x, &y) + ewc.penalty(&model)
+            };
+            let numeric = (total_loss(eps) - total_loss(-eps)) / (2.0 * eps);
+            assert_abs_diff_eq!(analytic[k], numeric, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_ewc_update_keeps_important_parameters_close() {
+        // The new task can be solved in many ways, but the first weight is
+        // important for the old task and must stay near its old value 1.0
+        let x = DMatrix::from_row_slice(2, 2, &[1.0, 0.0,
+                                                0.0, 1.0]);
+        let y = DMatrix::zeros(2, 1);
+        let train = |fisher: Vec<f64>| {
+            let model = linear_model();
+            let ewc = EwcPenalty::new(&model, fisher, 1.0);
+            let mut optim = SGD::new(model, 0.005, 0.0);
+            for _ in 0..2000 {
+                let _ = ewc.update(&mut optim, &x, &y);
+            }
+            let loss = optim.model.evaluate(&x, &y);
+            (optim.model.flat_parameters(), loss)
+        };
+
+        let (with_ewc, loss) = train(vec![100.0, 0.0, 0.0]);
+        let (without_ewc, _) = train(vec![0.0, 0.0, 0.0]);
+        assert!(loss < 1e-2);
+        assert!((with_ewc[0] - 1.0).abs() < 0.05, "{:?}", with_ewc);
+        assert!((without_ewc[0] - 1.0).abs() > 0.2, "{:?}", without_ewc);
+    }
+}
@@ -0,0 +1,105 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Reinforcement Learning
+    *
+    * Utilities for value-based and actor-critic reinforcement learning.
+    *
+    * Currently, the following utilities are implemented:
+    * 1. TargetNetwork - A slowly moving copy of a model, used to compute the
+    *                    bootstrapped targets r + γ * Q'(s', a') in DQN and
+    *                    DDPG. Regressing a network onto its own outputs is
+    *                    unstable, so the targets come from the copy θ', which
+    *                    follows the trained network θ either by a periodic
+    *                    hard copy θ' = θ (DQN) or by Polyak averaging
+    *                    θ' = τ * θ + (1 - τ) * θ' after every step (DDPG).
+    *
+**/
+
+
+pub struct TargetNetwork {
+    pub model: NeuralNetwork // The target copy θ'
+}
+
+impl TargetNetwork {
+    // Creates a target network starting as an exact copy of source
+    pub fn new(source: &NeuralNetwork) -> Self {
+        TargetNetwork {
+            model: source.clone()
+        }
+    }
+
+    // Outputs of the target network, e.g. Q'(s', ·) for a batch of states
+    pub fn forward(&mut self, x: &DMatrix<f64>) -> DMatrix<f64> {
+        return self.model.forward(x);
+    }
+
+    // Polyak averaging θ' = τ * θ + (1 - τ) * θ' of every parameter (the
+    // weights and biases, and those of the activations, see
+    // NeuralNetwork::parameters). tau = 1 is a hard update, tau = 0 leaves
+    // the target unchanged.
+    pub fn soft_update(&mut self, source: &NeuralNetwork, tau: f64) {
+        assert!((0.0..=1.0).contains(&tau), "tau must be in [0, 1], got {}", tau);
+        self.check_architecture(source);
+        for (target, param) in self.model.parameters_mut().into_iter().zip(source.parameters()) {
+            *target = tau * param + (1.0 - tau) * &*target;
+        }
+    }
+
+    // Copies every parameter of source into the target, θ' = θ
+    pub fn hard_update(&mut self, source: &NeuralNetwork) {
+        self.check_architecture(source);
+        for (target, param) in self.model.parameters_mut().into_iter().zip(source.parameters()) {
+            target.copy_from(param);
+        }
+    }
+
+    fn check_architecture(&self, source: &NeuralNetwork) {
+        assert_eq!(self.model.layers.len(), source.layers.len(), "Target and source must have the same number of layers");
+        for (i, (target, layer)) in self.model.layers.iter().zip(source.layers.iter()).enumerate() {
+            assert_eq!(target.W.shape(), layer.W.shape(), "Layer {} has a different shape in the target", i);
+        }
+        let shapes = |model: &NeuralNetwork| model.parameters().iter().map(|p| p.shape()).collect::<Vec<_>>();
+        assert_eq!(shapes(&self.model), shapes(source), "Target and source must have the same parameters");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::ReLU;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    fn network() -> NeuralNetwork {
+        return NeuralNetwork::new(vec![Box::new(Linear::new(3, 4)), Box::new(Linear::new(4, 2))],
+                                  vec![Box::new(ReLU::new())],
+                                  Box::new(MSE::new()));
+    }
+
+    #[test]
+    fn test_soft_and_hard_updates() {
+        let mut source = network();
+        let mut target = TargetNetwork::new(&source);
+        assert_eq!(target.model.flat_parameters(), source.flat_parameters());
+
+        let params: Vec<f64> = (0..source.num_parameters()).map(|k| k as f64 / 10.0 - 1.0).collect();
+        source.set_flat_parameters(&params);
+        let before = target.model.flat_parameters();
+        let tau = 0.1;
+        target.soft_update(&source, tau);
+        let after = target.model.flat_parameters();
+        // Every parameter moves the fraction tau of the way toward the source
+        for k in 0..after.len() {
+            assert_abs_diff_eq!(after[k] - before[k], tau * (params[k] - before[k]), epsilon = 1e-12);
+        }
+
+        target.hard_update(&source);
+        assert_eq!(target.model.flat_parameters(), params);
+        let x = DMatrix::from_row_slice(2, 3, &[1.0, -2.0, 0.5,
+                                                0.0, 3.0, -1.0]);
+        assert_eq!(target.forward(&x), source.forward(&x));
+    }
+}
@@ -0,0 +1,32 @@
+use nalgebra::{RealField};
+
+/**
+    * Scalar Types
+    *
+    * The core building blocks (activation functions, Linear layers, the MSE
+    * and cross entropy losses, NeuralNetwork and SGD) are generic over the
+    * scalar type of their matrices, so a model can be trained in f32 for
+    * half the memory, or in f64 for accuracy. Every type parameter defaults
+    * to f64, e.g. NeuralNetwork is NeuralNetwork<f64>, and the rest of the
+    * library (other optimizers, data loading, analysis tools, ...) works on
+    * f64 models.
+    *
+    * Real is the bound used for the scalar type: any nalgebra RealField that
+    * is Copy, i.e. f32 and f64.
+    *
+**/
+
+
+pub trait Real: RealField + Copy {}
+
+impl<T: RealField + Copy> Real for T {}
+
+// Converts an f64 constant to the scalar type
+pub fn from_f64<T: Real>(x: f64) -> T {
+    return nalgebra::convert(x);
+}
+
+// Converts a scalar to f64, which is exact for f32 and f64
+pub fn to_f64<T: Real>(x: T) -> f64 {
+    return nalgebra::try_convert(x).unwrap();
+}
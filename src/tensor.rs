@@ -0,0 +1,128 @@
+use alloc::format;
+use alloc::vec::Vec;
+use nalgebra::{DMatrix};
+use crate::error::DnnError;
+
+/**
+    * Tensors
+    *
+    * Layers exchange batches as N x F matrices, one sample per row. Tensor
+    * keeps such a matrix together with the logical shape of its samples,
+    * e.g. [channels, height, width, batch], so layers that care about the
+    * spatial structure (convolution, pooling, flatten) can recover it, while
+    * every other layer keeps working on the matrix.
+    *
+    * The last axis of the shape is the batch axis and indexes the rows of the
+    * matrix. The other axes are flattened in row-major order into the columns:
+    * for the shape [C, H, W, N], element [c, h, w, n] is entry
+    * (n, (c * H + h) * W + w) of the N x (C * H * W) matrix. Reshaping to
+    * another shape with the same batch size and number of features only
+    * changes the logical shape, never the data, so e.g. flattening is free.
+    *
+**/
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor {
+    data: DMatrix<f64>, // Samples as rows (N x F)
+    shape: Vec<usize> // Logical shape, with the batch axis last
+}
+
+// Counts the features per sample of a shape, checking that it has a batch axis
+fn num_features(shape: &[usize]) -> Result<usize, DnnError> {
+    if shape.is_empty() {
+        return Err(DnnError::InvalidShape("a tensor needs at least a batch axis".into()));
+    }
+    return Ok(shape[..shape.len() - 1].iter().product());
+}
+
+impl Tensor {
+    // Views matrix (N x F) as a tensor of the given shape, whose last axis
+    // must be N and whose other axes must multiply to F
+    pub fn from_matrix(matrix: DMatrix<f64>, shape: &[usize]) -> Result<Self, DnnError> {
+        let features = num_features(shape)?;
+        if shape[shape.len() - 1] != matrix.nrows() || features != matrix.ncols() {
+            return Err(DnnError::InvalidShape(format!("shape {:?} does not fit a {}x{} matrix", shape, matrix.nrows(), matrix.ncols())));
+        }
+        return Ok(Tensor {
+            data: matrix,
+            shape: shape.to_vec()
+        });
+    }
+
+    pub fn zeros(shape: &[usize]) -> Result<Self, DnnError> {
+        let features = num_features(shape)?;
+        return Tensor::from_matrix(DMatrix::zeros(shape[shape.len() - 1], features), shape);
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        return &self.shape;
+    }
+
+    pub fn batch_size(&self) -> usize {
+        return self.data.nrows();
+    }
+
+    // The same data under another shape, which must keep the batch size and
+    // the number of features per sample
+    pub fn reshape(&self, shape: &[usize]) -> Result<Tensor, DnnError> {
+        return Tensor::from_matrix(self.data.clone(), shape).map_err(|_| {
+            DnnError::InvalidShape(format!("cannot reshape {:?} to {:?}", self.shape, shape))
+        });
+    }
+
+    // The underlying N x F matrix, as used by the layers
+    pub fn as_matrix(&self) -> &DMatrix<f64> {
+        return &self.data;
+    }
+
+    pub fn into_matrix(self) -> DMatrix<f64> {
+        return self.data;
+    }
+
+    // Element at a multi-dimensional index, one entry per axis
+    pub fn get(&self, index: &[usize]) -> f64 {
+        assert_eq!(index.len(), self.shape.len(), "Index {:?} does not match shape {:?}", index, self.shape);
+        let batch_axis = self.shape.len() - 1;
+        let mut column = 0;
+        for axis in 0..batch_axis {
+            assert!(index[axis] < self.shape[axis], "Index {:?} out of range for shape {:?}", index, self.shape);
+            column = column * self.shape[axis] + index[axis];
+        }
+        return self.data[(index[batch_axis], column)];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tensor_reshape_round_trip() {
+        // 2 channels of 3 x 4 images, batch of 5
+        let matrix = DMatrix::from_fn(5, 24, |n, f| (n * 100 + f) as f64);
+        let tensor = Tensor::from_matrix(matrix.clone(), &[2, 3, 4, 5]).unwrap();
+        assert_eq!(tensor.batch_size(), 5);
+        // Sample 4, feature (1 * 3 + 2) * 4 + 3 = 23
+        assert_eq!(tensor.get(&[1, 2, 3, 4]), 423.0);
+
+        let flat = tensor.reshape(&[24, 5]).unwrap();
+        assert_eq!(flat.shape(), &[24, 5]);
+        assert_eq!(flat.get(&[23, 4]), tensor.get(&[1, 2, 3, 4]));
+        let restored = flat.reshape(&[2, 3, 4, 5]).unwrap();
+        assert_eq!(restored, tensor);
+        assert_eq!(restored.into_matrix(), matrix);
+    }
+
+    #[test]
+    fn test_tensor_shape_validation() {
+        let matrix = DMatrix::zeros(5, 24);
+        assert!(Tensor::from_matrix(matrix.clone(), &[2, 3, 5, 5]).is_err());
+        assert!(Tensor::from_matrix(matrix.clone(), &[]).is_err());
+        let tensor = Tensor::from_matrix(matrix, &[6, 4, 5]).unwrap();
+        // The batch axis stays the same
+        assert!(tensor.reshape(&[12, 10]).is_err());
+        assert!(tensor.reshape(&[25, 5]).is_err());
+        assert_eq!(Tensor::zeros(&[3, 2]).unwrap().as_matrix().shape(), (2, 3));
+    }
+}
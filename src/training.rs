@@ -0,0 +1,80 @@
+use crate::data::DataLoader;
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Training Diagnostics
+    *
+    * Quantities estimated from gradients during training that can drive
+    * decisions about the training run itself.
+    *
+    * Currently, the following estimates are implemented:
+    * 1. grad_variance - Trace of the covariance of the minibatch gradient,
+    *                    tr(Cov(g_B)) = Σ_j var(g_B,j), estimated from
+    *                    n_batches minibatches. It falls as 1 / B with the
+    *                    batch size B, so a batch size can be grown until the
+    *                    gradient noise is small enough relative to ‖E g_B‖^2.
+    *
+**/
+
+
+// Estimates the trace of the covariance of the gradient of the loss over the
+// minibatches of loader, from the gradients of n_batches full batches at the
+// current parameters, using the unbiased sample variance of every parameter.
+// A final partial batch is skipped, as its smaller size makes its gradient
+// noisier, and further epochs are drawn when one epoch has too few batches.
+// Overwrites the gradients stored in the layers.
+pub fn grad_variance(net: &mut NeuralNetwork, loader: &mut DataLoader, n_batches: usize) -> f64 {
+    assert!(n_batches > 1, "grad_variance needs at least two batches, got {}", n_batches);
+    assert!(loader.num_samples() >= loader.batch_size, "The loader has no full batch");
+    let mut grads: Vec<Vec<f64>> = Vec::with_capacity(n_batches);
+    while grads.len() < n_batches {
+        for (x, y) in loader.batches() {
+            if grads.len() == n_batches {
+                break;
+            }
+            if x.nrows() == loader.batch_size {
+                grads.push(net.loss_gradient(&x, &y));
+            }
+        }
+    }
+
+    let n = n_batches as f64;
+    let mut mean = vec![0.0; net.num_parameters()];
+    for grad in grads.iter() {
+        for (m, g) in mean.iter_mut().zip(grad.iter()) {
+            *m += g / n;
+        }
+    }
+    let mut trace = 0.0;
+    for grad in grads.iter() {
+        for (m, g) in mean.iter().zip(grad.iter()) {
+            trace += (g - m) * (g - m) / (n - 1.0);
+        }
+    }
+    return trace;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::make_regression;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+
+    #[test]
+    fn test_grad_variance_decreases_with_batch_size() {
+        let (x, y) = make_regression(1024, 2, 1.0, 0);
+        let mut network = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        network.set_flat_parameters(&[1.0, -1.0, 2.0]);
+
+        let mut variances = Vec::new();
+        for batch_size in [8, 32, 128] {
+            let mut loader = DataLoader::new(x.clone(), y.clone(), batch_size, true, 0);
+            variances.push(grad_variance(&mut network, &mut loader, 32));
+        }
+        assert!(variances.iter().all(|v| *v > 0.0));
+        // Roughly 1 / B: each 4x larger batch should at least halve the variance
+        assert!(variances[1] < 0.5 * variances[0], "{:?}", variances);
+        assert!(variances[2] < 0.5 * variances[1], "{:?}", variances);
+    }
+}
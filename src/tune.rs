@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use crate::data::DataLoader;
+use crate::optim::optimizer::{Optimizer, evaluate_loader};
+
+/**
+    * Hyperparameter Tuning
+    *
+    * Choosing the learning rate, momentum, layer widths, etc. is usually done
+    * empirically: train a fresh model for each candidate setting and keep the
+    * one with the lowest validation loss.
+    *
+    * A setting is described by Params, a map from hyperparameter name to value.
+    * The caller supplies a build function turning Params into a ready-to-train
+    * optimizer (which owns a freshly initialized model), so any hyperparameter
+    * the caller can express as an f64 can be searched over.
+    *
+    * Currently, the following search strategies are implemented:
+    * 1. grid_search - Evaluates every combination of the given values.
+    *
+**/
+
+
+pub type Params = BTreeMap<String, f64>;
+
+// Trains a fresh model for every combination of values in param_grid and
+// returns each combination with its validation loss after training, in grid
+// order (the last hyperparameter varies fastest). Every combination trains on
+// its own copy of loader, so all of them see the same sequence of batches and
+// differ only in their hyperparameters.
+pub fn grid_search<O, F>(build_fn: F, param_grid: &[(&str, Vec<f64>)], loader: &DataLoader,
+                         val_loader: &mut DataLoader, epochs: usize) -> Vec<(Params, f64)>
+where
+    O: Optimizer,
+    F: Fn(&Params) -> O
+{
+    // Expand the grid into the full list of combinations
+    let mut combinations = vec![Params::new()];
+    for (name, values) in param_grid {
+        let mut expanded = Vec::with_capacity(combinations.len() * values.len());
+        for params in &combinations {
+            for value in values {
+                let mut params = params.clone();
+                params.insert(name.to_string(), *value);
+                expanded.push(params);
+            }
+        }
+        combinations = expanded;
+    }
+
+    let mut results = Vec::with_capacity(combinations.len());
+    for params in combinations {
+        let mut optim = build_fn(&params);
+        let mut loader = loader.clone();
+        let _ = optim.fit(&mut loader, None, epochs, 1);
+        let val_loss = evaluate_loader(optim.model_mut(), val_loader);
+        results.push((params, val_loss));
+    }
+    return results;
+}
+
+// Returns the entry of a search result with the lowest validation loss
+pub fn best(results: &[(Params, f64)]) -> Option<&(Params, f64)> {
+    return results.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::optimizer::fixtures;
+    use crate::optim::sgd::SGD;
+    use crate::nn::layers::Linear;
+    use crate::nn::model::NeuralNetwork;
+    use crate::nn::loss::MSE;
+    use nalgebra::DMatrix;
+
+    #[test]
+    fn test_grid_search_evaluates_all_combinations() {
+        let (X, Y) = fixtures::line(3.0, -0.5);
+        let loader = DataLoader::new(X.clone(), Y.clone(), 4, true, 0);
+        let mut val_loader = DataLoader::new(X, Y, 16, false, 0);
+
+        let build_fn = |params: &Params| SGD::new(fixtures::linear_model(), params["lr"], params["mu"]);
+        let grid = [("lr", vec![0.0, 0.1]), ("mu", vec![0.0, 0.5])];
+        let results = grid_search(build_fn, &grid, &loader, &mut val_loader, 30);
+
+        assert_eq!(results.len(), 4);
+        let seen: Vec<(f64, f64)> = results.iter().map(|(p, _)| (p["lr"], p["mu"])).collect();
+        assert_eq!(seen, vec![(0.0, 0.0), (0.0, 0.5), (0.1, 0.0), (0.1, 0.5)]);
+
+        // A zero learning rate never trains, so the best setting must use lr = 0.1
+        let (best_params, best_loss) = best(&results).unwrap();
+        assert_eq!(best_params["lr"], 0.1);
+        assert!(*best_loss < results[0].1);
+        assert!(*best_loss < results[1].1);
+    }
+
+    #[test]
+    fn test_grid_search_repeats_a_setting_exactly() {
+        let (X, Y) = fixtures::line(3.0, -0.5);
+        let loader = DataLoader::new(X.clone(), Y.clone(), 4, true, 0);
+        let mut val_loader = DataLoader::new(X, Y, 16, false, 0);
+
+        // The same setting listed twice, on identically initialized models,
+        // must score the same: with a shuffling loader this only holds if
+        // both runs see the same batch order
+        let build_fn = |params: &Params| {
+            let mut layer = Linear::new(1, 1);
+            layer.W = DMatrix::from_element(1, 1, 0.5);
+            layer.b = DMatrix::zeros(1, 1);
+            let model = NeuralNetwork::new(vec![Box::new(layer)], vec![], Box::new(MSE::new()));
+            return SGD::new(model, params["lr"], 0.0);
+        };
+        let grid = [("lr", vec![0.05, 0.05])];
+        let results = grid_search(build_fn, &grid, &loader, &mut val_loader, 3);
+        assert_eq!(results[0].1, results[1].1);
+    }
+}
@@ -0,0 +1,76 @@
+use nalgebra::{DMatrix};
+use crate::nn::model::NeuralNetwork;
+
+/**
+    * Visualization Helpers
+    *
+    * Functions that compute data worth plotting to understand training. They
+    * return plain numbers so any plotting tool can be used to draw them.
+    *
+    * Currently, the following helpers are implemented:
+    * 1. loss_1d - A 1D slice of the loss landscape: the loss at θ + α * d for
+    *              a direction d in parameter space and a range of α.
+    *
+**/
+
+
+// Evaluates the loss on (x, y) at the parameters θ + α * direction for each
+// α in alphas, where θ are the current (flattened) parameters of net. The
+// direction uses the layout of NeuralNetwork::flat_parameters. The original
+// parameters are restored before returning.
+pub fn loss_1d(net: &mut NeuralNetwork, x: &DMatrix<f64>, y: &DMatrix<f64>, direction: &[f64], alphas: &[f64]) -> Vec<f64> {
+    let theta = net.flat_parameters();
+    assert_eq!(direction.len(), theta.len(),
+               "Direction has {} entries but the network has {} parameters", direction.len(), theta.len());
+
+    let mut losses = Vec::with_capacity(alphas.len());
+    for alpha in alphas {
+        let perturbed: Vec<f64> = theta.iter().zip(direction).map(|(t, d)| t + alpha * d).collect();
+        net.set_flat_parameters(&perturbed);
+        losses.push(net.evaluate(x, y));
+    }
+    net.set_flat_parameters(&theta);
+    return losses;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::layers::Linear;
+    use crate::nn::loss::MSE;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn test_loss_1d_is_parabolic_around_minimum() {
+        // A linear model on noiseless linear data is a quadratic problem, and
+        // the true weights are its minimum
+        let x = DMatrix::from_row_slice(4, 2, &[1.0, 0.0,
+                                                0.0, 1.0,
+                                                1.0, 1.0,
+                                                2.0, -1.0]);
+        let y = DMatrix::from_fn(4, 1, |i, _| 2.0 * x[(i, 0)] - 3.0 * x[(i, 1)] + 0.5);
+        let mut net = NeuralNetwork::new(vec![Box::new(Linear::new(2, 1))], vec![], Box::new(MSE::new()));
+        net.layers[0].W = DMatrix::from_row_slice(1, 2, &[2.0, -3.0]);
+        net.layers[0].b = DMatrix::from_element(1, 1, 0.5);
+        let theta = net.flat_parameters();
+
+        let direction = [1.0, -0.5, 0.25];
+        let alphas: Vec<f64> = (-4..=4).map(|i| i as f64 * 0.5).collect();
+        let losses = loss_1d(&mut net, &x, &y, &direction, &alphas);
+
+        // Minimum (zero loss) at α = 0, symmetric around it
+        let min_index = (0..losses.len()).min_by(|a, b| losses[*a].total_cmp(&losses[*b])).unwrap();
+        assert_eq!(alphas[min_index], 0.0);
+        assert_abs_diff_eq!(losses[4], 0.0, epsilon = 1e-12);
+        for k in 1..=4 {
+            assert_abs_diff_eq!(losses[4 - k], losses[4 + k], epsilon = 1e-10);
+        }
+        // L(α) = c * α^2: constant second differences
+        let second_diff = losses[0] - 2.0 * losses[1] + losses[2];
+        for k in 1..losses.len() - 1 {
+            assert_abs_diff_eq!(losses[k - 1] - 2.0 * losses[k] + losses[k + 1], second_diff, epsilon = 1e-9);
+        }
+        // Parameters are restored
+        assert_eq!(net.flat_parameters(), theta);
+    }
+}